@@ -1,17 +1,30 @@
 //! Admin authentication endpoints
 
 use axum::{
-    extract::State,
-    http::StatusCode,
-    Json,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
-use crate::services::AppState;
+use std::net::SocketAddr;
+use super::keys::EncryptedResponse;
+use super::middleware::{bind_session, peer_ip};
+use super::negotiate::{Format, Negotiated};
+use crate::audit::{audit_event, AuditKind, Outcome};
+use crate::services::{
+    admin_payload_key, parse_public_key, AppState, BackupError, BackupFile, EncryptedMessage, Session, SessionStats,
+};
+
+/// Header carrying the passphrase for encrypted backups
+pub const BACKUP_PASSPHRASE_HEADER: &str = "x-backup-passphrase";
 
 /// Server info response (public, no auth required)
 #[derive(Serialize)]
 pub struct ServerInfoResponse {
+    pub server_id: String,
     pub server_public_key: String,
+    /// Short form of the public key for comparing by eye
+    pub server_fingerprint: String,
     pub server_name: String,
     pub version: String,
 }
@@ -20,6 +33,9 @@ pub struct ServerInfoResponse {
 #[derive(Deserialize)]
 pub struct AdminLoginRequest {
     pub admin_key: String,
+    /// Admin's X25519 public key (hex); enables encrypted admin responses
+    #[serde(default)]
+    pub admin_public_key: Option<String>,
 }
 
 /// Admin login response
@@ -27,6 +43,128 @@ pub struct AdminLoginRequest {
 pub struct AdminLoginResponse {
     pub authenticated: bool,
     pub message: String,
+    /// Set when `admin_public_key` was given; mixed into the payload key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Server key the payload key was agreed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_public_key: Option<String>,
+}
+
+/// Response carrying a freshly rotated admin key
+#[derive(Serialize)]
+pub struct RotateKeyResponse {
+    pub admin_key: String,
+    pub message: String,
+}
+
+/// The default server key after rotation
+#[derive(Serialize)]
+pub struct ServerKeyRotateResponse {
+    pub server_public_key: String,
+    pub server_fingerprint: String,
+    /// How long the previous key is still accepted
+    pub grace_secs: u64,
+}
+
+/// Request to prune idle clients
+#[derive(Deserialize)]
+pub struct PruneIdleRequest {
+    /// Remove clients not seen for this many seconds
+    pub max_idle_secs: u64,
+}
+
+/// Result of pruning idle clients
+#[derive(Serialize)]
+pub struct PruneIdleResponse {
+    pub removed: usize,
+}
+
+/// A `/register/init` that was never completed
+#[derive(Serialize)]
+pub struct PendingRegistration {
+    pub client_id: String,
+    pub server_public_key: String,
+    pub created_at: String,
+}
+
+/// Registrations waiting for `/register/complete`
+#[derive(Serialize)]
+pub struct PendingRegistrationsResponse {
+    pub pending: Vec<PendingRegistration>,
+}
+
+/// Request to purge stale pending registrations
+#[derive(Deserialize)]
+pub struct PrunePendingRequest {
+    /// Remove pending registrations started more than this many seconds ago
+    pub older_than_secs: u64,
+}
+
+/// Result of restoring a backup
+#[derive(Serialize)]
+pub struct RestoreResponse {
+    pub clients: usize,
+    pub server_keys: usize,
+    /// Client ids in the backup that failed validation and were not restored
+    pub rejected: Vec<String>,
+}
+
+/// Filter for listing sessions
+#[derive(Deserialize)]
+pub struct SessionsQuery {
+    pub client_id: Option<String>,
+}
+
+/// An active session, without its API key
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub client_id: Option<String>,
+    pub is_admin: bool,
+    pub created_at: String,
+    pub last_seen: String,
+    pub expires_at: String,
+}
+
+/// Result of purging expired sessions
+#[derive(Serialize)]
+pub struct SessionCleanupResponse {
+    pub removed: usize,
+}
+
+/// Result of logging out a client
+#[derive(Serialize)]
+pub struct ClientLogoutResponse {
+    pub client_id: String,
+    pub revoked: usize,
+}
+
+/// Consistency of one stored server keypair
+#[derive(Serialize)]
+pub struct KeyCheck {
+    pub client_id: String,
+    pub valid: bool,
+}
+
+/// Result of re-checking every server keypair
+#[derive(Serialize)]
+pub struct KeyHealthResponse {
+    pub invalid: usize,
+    pub keys: Vec<KeyCheck>,
+}
+
+/// An entry skipped while loading the key store
+#[derive(Serialize)]
+pub struct StorageIssue {
+    pub path: String,
+    pub error: String,
+}
+
+/// Entries dropped from the key store files at startup
+#[derive(Serialize)]
+pub struct StorageIssuesResponse {
+    pub issues: Vec<StorageIssue>,
 }
 
 /// Admin dashboard data (requires auth)
@@ -37,31 +175,63 @@ pub struct AdminDashboardResponse {
     pub server_public_key: String,
 }
 
-/// Get server public info (for QR code display)
+/// Get server public info (for QR code display), as JSON or YAML
 pub async fn get_server_info(
     State(state): State<AppState>,
-) -> Json<ServerInfoResponse> {
-    Json(ServerInfoResponse {
-        server_public_key: state.admin.get_server_public_key(),
+    format: Format,
+) -> Negotiated<ServerInfoResponse> {
+    Negotiated(format, server_info(&state))
+}
+
+pub(super) fn server_info(state: &AppState) -> ServerInfoResponse {
+    // Everything comes from the one key, so the fields can't disagree mid-rotation
+    let current = state.server_key.current();
+    ServerInfoResponse {
+        server_id: current.server_id(),
+        server_public_key: current.public_key_hex(),
+        server_fingerprint: current.fingerprint(),
         server_name: "Omni Core Server".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
-    })
+    }
 }
 
 /// Admin login
 pub async fn admin_login(
     State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<AdminLoginRequest>,
 ) -> Result<Json<AdminLoginResponse>, (StatusCode, String)> {
     if state.admin.verify(&req.admin_key) {
+        // Agree a shared secret before creating the session, so a bad key
+        // doesn't leave an unused session behind
+        let server_keypair = state.server_key.current();
+        let shared_secret = req.admin_public_key
+            .as_deref()
+            .map(|key| {
+                let admin_public = parse_public_key(key)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+                server_keypair.derive_shared_secret(&admin_public)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+            })
+            .transpose()?;
+
         // Create admin session
-        let session = state.sessions.create(state.config.session_ttl_secs * 24); // 24x longer for admin
-        
+        let session = state.sessions.create_admin(state.config.admin_session_ttl_secs);
+        bind_session(&state, &session, peer_ip(connect.as_ref()));
+        if let Some(shared_secret) = &shared_secret {
+            let key = admin_payload_key(shared_secret, &session.id.to_string());
+            state.sessions.set_payload_key(&session.api_key, key);
+        }
+        audit_event(AuditKind::AdminLogin, "admin", Outcome::Success);
+
         Ok(Json(AdminLoginResponse {
             authenticated: true,
             message: format!("Admin session created. API key: {}", session.api_key),
+            session_id: shared_secret.map(|_| session.id.to_string()),
+            server_public_key: shared_secret.map(|_| server_keypair.public_key_hex()),
         }))
     } else {
+        audit_event(AuditKind::AdminLogin, "admin", Outcome::Failure);
         Err((
             StatusCode::UNAUTHORIZED,
             "Invalid admin key".to_string(),
@@ -69,16 +239,281 @@ pub async fn admin_login(
     }
 }
 
-/// Get admin dashboard (requires valid admin session)
+/// Get admin dashboard (requires admin session)
 pub async fn admin_dashboard(
     State(state): State<AppState>,
 ) -> Json<AdminDashboardResponse> {
+    Json(dashboard(&state))
+}
+
+/// Admin dashboard encrypted under the session's payload key (requires an
+/// admin session opened with `admin_public_key`)
+pub async fn admin_dashboard_encrypted(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<EncryptedResponse>, (StatusCode, String)> {
+    let key = session.payload_key.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Admin session has no payload key; log in with admin_public_key".to_string(),
+        )
+    })?;
+    let body = serde_json::to_vec(&dashboard(&state))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let payload = EncryptedMessage::encrypt(&body, &key)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(EncryptedResponse { payload }))
+}
+
+fn dashboard(state: &AppState) -> AdminDashboardResponse {
     let clients = state.keystore.list_clients();
     let keys = state.keystore.list_server_keys();
-    
-    Json(AdminDashboardResponse {
+
+    AdminDashboardResponse {
         total_clients: clients.len(),
         total_server_keys: keys.len(),
         server_public_key: state.admin.get_server_public_key(),
+    }
+}
+
+/// Rotate the admin key (requires admin session)
+pub async fn rotate_admin_key(
+    State(state): State<AppState>,
+) -> Result<Json<RotateKeyResponse>, (StatusCode, String)> {
+    let admin_key = state.admin.rotate_key()
+        .map_err(|e| {
+            audit_event(AuditKind::AdminKeyRotated, "admin", Outcome::Failure);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+
+    audit_event(AuditKind::AdminKeyRotated, "admin", Outcome::Success);
+
+    Ok(Json(RotateKeyResponse {
+        admin_key,
+        message: "Admin key rotated. Store it now; it will not be shown again.".to_string(),
+    }))
+}
+
+/// Replace the default server keypair (requires admin session).
+///
+/// The previous key keeps decrypting `/keys/send` traffic for the configured
+/// grace period so in-flight clients can re-fetch `/keys/public`.
+pub async fn rotate_server_key(
+    State(state): State<AppState>,
+) -> Result<Json<ServerKeyRotateResponse>, (StatusCode, String)> {
+    let keypair = state.server_key.rotate()
+        .map_err(|e| {
+            audit_event(AuditKind::ServerKeyRotated, "server", Outcome::Failure);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+    audit_event(AuditKind::ServerKeyRotated, "server", Outcome::Success);
+
+    let server_public_key = keypair.public_key_hex();
+    if let Err(e) = state.admin.set_server_public_key(&server_public_key) {
+        tracing::warn!("Failed to record new server key in admin config: {}", e);
+    }
+
+    Ok(Json(ServerKeyRotateResponse {
+        server_public_key,
+        server_fingerprint: keypair.fingerprint(),
+        grace_secs: state.config.server_key_grace_secs,
+    }))
+}
+
+/// Remove clients that have been idle too long (requires admin session)
+pub async fn prune_idle_clients(
+    State(state): State<AppState>,
+    Json(req): Json<PruneIdleRequest>,
+) -> Result<Json<PruneIdleResponse>, (StatusCode, String)> {
+    let max_idle = prune_window(req.max_idle_secs)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "max_idle_secs is too large".to_string()))?;
+
+    let removed = state.keystore.prune_idle(max_idle)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !removed.is_empty() {
+        release_replay_marks(&state);
+    }
+    // A pruned client's sessions would otherwise outlive its registration
+    for client_id in &removed {
+        state.sessions.revoke_all_for_client(client_id);
+    }
+
+    tracing::info!("Pruned {} idle clients", removed.len());
+    Ok(Json(PruneIdleResponse { removed: removed.len() }))
+}
+
+/// An age limit in seconds as a duration, if now minus it is still a valid time
+fn prune_window(secs: u64) -> Option<chrono::Duration> {
+    let window = chrono::Duration::try_seconds(i64::try_from(secs).ok()?)?;
+    chrono::Utc::now().checked_sub_signed(window)?;
+    Some(window)
+}
+
+/// Let the replay marks of clients that are no longer registered age out
+fn release_replay_marks(state: &AppState) {
+    state.replay_guard.retain_registered(|key| state.keystore.find_client_by_public_key(key).is_some());
+}
+
+/// Registrations started with `/register/init` but never completed (requires admin session)
+pub async fn pending_registrations(State(state): State<AppState>) -> Json<PendingRegistrationsResponse> {
+    let pending = state.keystore.list_pending()
+        .into_iter()
+        .map(|key| PendingRegistration {
+            client_id: key.client_id,
+            server_public_key: key.public_key,
+            created_at: key.created_at,
+        })
+        .collect();
+    Json(PendingRegistrationsResponse { pending })
+}
+
+/// Drop pending registrations and their server keys once they are too old (requires admin session)
+pub async fn prune_pending_registrations(
+    State(state): State<AppState>,
+    Json(req): Json<PrunePendingRequest>,
+) -> Result<Json<PruneIdleResponse>, (StatusCode, String)> {
+    let older_than = prune_window(req.older_than_secs)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "older_than_secs is too large".to_string()))?;
+
+    let removed = state.keystore.prune_pending(older_than)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("Pruned {} pending registrations", removed);
+    Ok(Json(PruneIdleResponse { removed }))
+}
+
+fn backup_passphrase(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(BACKUP_PASSPHRASE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+}
+
+/// Download all keys as a backup, sealed if a passphrase header is sent (requires admin session)
+pub async fn backup(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<BackupFile>, (StatusCode, String)> {
+    let passphrase = backup_passphrase(&headers);
+    // Sealed on disk means a plaintext copy would undo the master key
+    if passphrase.is_none() && state.keystore.has_master_key() {
+        audit_event(AuditKind::BackupExported, "keystore", Outcome::Failure);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("A {} header is required while KEYSTORE_MASTER_KEY is set", BACKUP_PASSPHRASE_HEADER),
+        ));
+    }
+
+    let bundle = state.keystore.export_bundle();
+    let file = BackupFile::new(bundle, passphrase)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit_event(AuditKind::BackupExported, "keystore", Outcome::Success);
+    Ok(Json(file))
+}
+
+/// Replace all keys with the contents of a backup (requires admin session)
+pub async fn restore(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(file): Json<BackupFile>,
+) -> Result<Json<RestoreResponse>, (StatusCode, String)> {
+    let bundle = file.open(backup_passphrase(&headers), state.config.max_backup_len)
+        .inspect_err(|_| audit_event(AuditKind::BackupRestored, "keystore", Outcome::Failure))
+        .map_err(|e| match e {
+            BackupError::PassphraseRequired
+            | BackupError::InvalidPassphrase
+            | BackupError::UnsupportedVersion(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+            BackupError::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    let report = state.keystore.import_bundle(bundle)
+        .inspect_err(|_| audit_event(AuditKind::BackupRestored, "keystore", Outcome::Failure))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    release_replay_marks(&state);
+
+    audit_event(AuditKind::BackupRestored, "keystore", Outcome::Success);
+    Ok(Json(RestoreResponse {
+        clients: report.clients,
+        server_keys: report.server_keys,
+        rejected: report.rejected,
+    }))
+}
+
+/// List active sessions, optionally for one client (requires admin session)
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    Query(query): Query<SessionsQuery>,
+) -> Json<Vec<SessionInfo>> {
+    let mut sessions = match query.client_id.as_deref() {
+        Some(client_id) => state.sessions.list_for_client(client_id),
+        None => state.sessions.list_all_active(),
+    };
+    sessions.sort_by_key(|s| s.created_at);
+
+    Json(sessions.into_iter().map(|s| SessionInfo {
+        session_id: s.id.to_string(),
+        client_id: s.client_id,
+        is_admin: s.is_admin,
+        created_at: s.created_at.to_rfc3339(),
+        last_seen: s.last_seen.to_rfc3339(),
+        expires_at: s.expires_at.to_rfc3339(),
+    }).collect())
+}
+
+/// Check that each server public key matches its secret (requires admin session)
+pub async fn key_health(
+    State(state): State<AppState>,
+) -> Json<KeyHealthResponse> {
+    let keys: Vec<KeyCheck> = state.keystore.validate_all()
+        .into_iter()
+        .map(|(client_id, valid)| KeyCheck { client_id, valid })
+        .collect();
+
+    Json(KeyHealthResponse {
+        invalid: keys.iter().filter(|k| !k.valid).count(),
+        keys,
+    })
+}
+
+/// List key store entries that were skipped at load (requires admin session)
+pub async fn storage_issues(
+    State(state): State<AppState>,
+) -> Json<StorageIssuesResponse> {
+    let issues = state.keystore.load_issues()
+        .into_iter()
+        .map(|(path, error)| StorageIssue { path: path.display().to_string(), error })
+        .collect();
+
+    Json(StorageIssuesResponse { issues })
+}
+
+/// Count active and expired sessions still in memory (requires admin session)
+pub async fn session_stats(
+    State(state): State<AppState>,
+) -> Json<SessionStats> {
+    Json(state.sessions.stats())
+}
+
+/// Drop expired sessions now (requires admin session)
+pub async fn cleanup_sessions(
+    State(state): State<AppState>,
+) -> Json<SessionCleanupResponse> {
+    Json(SessionCleanupResponse {
+        removed: state.sessions.cleanup_expired(),
     })
 }
+
+/// Revoke every session of a client (requires admin session)
+pub async fn logout_client(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+) -> Json<ClientLogoutResponse> {
+    let revoked = state.sessions.revoke_all_for_client(&client_id);
+
+    tracing::info!("Revoked {} sessions for client '{}'", revoked, client_id);
+    audit_event(AuditKind::ClientLogout, &client_id, Outcome::Success);
+    Json(ClientLogoutResponse { client_id, revoked })
+}
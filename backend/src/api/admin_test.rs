@@ -0,0 +1,646 @@
+//! Tests for admin endpoints
+
+#[cfg(test)]
+mod tests {
+    use crate::api::routes;
+    use crate::services::{admin_payload_key, parse_public_key, AppState, EncryptedMessage, KeyStoreManager, ReplayGuard, ServerKeyPair};
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request, StatusCode},
+    };
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    fn rotate_request(api_key: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/admin/rotate-key")
+            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_endpoint_requires_admin() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(rotate_request(&session.api_key)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_endpoint_returns_working_key() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(rotate_request(&session.api_key)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let new_key = body["admin_key"].as_str().unwrap();
+        assert!(state.admin.verify(new_key));
+
+        // The admin session used for rotation stays valid
+        assert!(state.sessions.validate(&session.api_key).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_server_info_follows_key_ring() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        // Rotated behind the admin config's back
+        let current = state.server_key.rotate().unwrap();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(Request::builder().uri("/server/info").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["server_public_key"], current.public_key_hex());
+        assert_eq!(body["server_fingerprint"], current.fingerprint());
+        assert_eq!(body["server_id"], current.server_id());
+    }
+
+    #[tokio::test]
+    async fn test_backup_then_restore_endpoints() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.clone().oneshot(
+            Request::builder()
+                .uri("/admin/backup")
+                .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                .header("x-backup-passphrase", "hunter2")
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let backup = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+
+        // Restore into a fresh server
+        let other_dir = tempdir().unwrap();
+        let other = AppState::for_tests(other_dir.path());
+        let other_session = other.sessions.create_admin(3600);
+        let app = routes(other.clone()).with_state(other.clone());
+        let restore = |passphrase: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/admin/restore")
+                .header(header::AUTHORIZATION, format!("Bearer {}", other_session.api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .header("x-backup-passphrase", passphrase)
+                .body(Body::from(backup.clone()))
+                .unwrap()
+        };
+
+        let res = app.clone().oneshot(restore("wrong")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = app.oneshot(restore("hunter2")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            other.keystore.get_server_key("device-1").unwrap().public_key,
+            state.keystore.get_server_key("device-1").unwrap().public_key
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pruned_clients_lose_pinned_replay_marks() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.replay_guard = ReplayGuard::new(1);
+        let session = state.sessions.create_admin(3600);
+        let client_key = ServerKeyPair::generate().public_key_hex();
+        state.keystore.bootstrap_client("device-1", &client_key).unwrap();
+        assert!(state.replay_guard.check_registered(&client_key, 5));
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/admin/clients/prune")
+                .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"max_idle_secs":0}"#))
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(state.keystore.get_client("device-1").is_none());
+
+        // The mark is now in the bounded map and another key can evict it
+        assert!(!state.replay_guard.check(&client_key, 5));
+        assert!(state.replay_guard.check("other", 1));
+        assert_eq!(state.replay_guard.last_sequence(&client_key), None);
+    }
+
+    #[tokio::test]
+    async fn test_pruned_client_sessions_are_revoked() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        state.keystore.bootstrap_client("device-1", &ServerKeyPair::generate().public_key_hex()).unwrap();
+        let client = state.sessions.create_for_client("device-1", 3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/admin/clients/prune")
+                .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"max_idle_secs":0}"#))
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app.oneshot(
+            Request::builder()
+                .uri("/register/me")
+                .header(header::AUTHORIZATION, format!("Bearer {}", client.api_key))
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(state.sessions.validate(&admin.api_key).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_prune_idle_rejects_out_of_range_windows() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+        state.keystore.bootstrap_client("device-1", &ServerKeyPair::generate().public_key_hex()).unwrap();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        // Wraps negative as i64, too long for a duration, too far before now
+        for secs in [u64::MAX, i64::MAX as u64, (i64::MAX / 1000) as u64] {
+            let res = app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/admin/clients/prune")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::json!({ "max_idle_secs": secs }).to_string()))
+                    .unwrap(),
+            ).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "max_idle_secs {}", secs);
+        }
+        assert!(state.keystore.get_client("device-1").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_restore_accepts_backups_over_default_body_limit() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.clone().oneshot(
+            Request::builder()
+                .uri("/admin/backup")
+                .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                .header("x-backup-passphrase", "hunter2")
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        let mut backup = to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec();
+        // Stand in for a large key store: past axum's 2 MB default
+        backup.resize(3 * 1024 * 1024, b' ');
+
+        let restore = |state: &AppState| {
+            let app = routes(state.clone()).with_state(state.clone());
+            let session = state.sessions.create_admin(3600);
+            app.oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/admin/restore")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header("x-backup-passphrase", "hunter2")
+                    .body(Body::from(backup.clone()))
+                    .unwrap(),
+            )
+        };
+
+        let other_dir = tempdir().unwrap();
+        let other = AppState::for_tests(other_dir.path());
+        let res = restore(&other).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(other.keystore.get_server_key("device-1").is_some());
+
+        // MAX_BACKUP_LEN still bounds the body
+        let small_dir = tempdir().unwrap();
+        let mut small = AppState::for_tests(small_dir.path());
+        small.config = std::sync::Arc::new(crate::config::Config {
+            max_backup_len: 1024 * 1024,
+            ..(*small.config).clone()
+        });
+        let res = restore(&small).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_backup_needs_passphrase_with_master_key() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.keystore = KeyStoreManager::with_master_key(&state.config.paths, [3u8; 32]).unwrap();
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+        let session = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let backup = |passphrase: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/admin/backup")
+                .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key));
+            if let Some(passphrase) = passphrase {
+                request = request.header("x-backup-passphrase", passphrase);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let res = app.clone().oneshot(backup(None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = app.oneshot(backup(Some("hunter2"))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["format"], "sealed");
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions_hides_api_keys() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        let device = state.sessions.create_for_client("device-1", 3600);
+        state.sessions.create_for_client("device-2", 3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .uri("/admin/sessions?client_id=device-1")
+                .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains(&device.api_key));
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let sessions = body.as_array().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["session_id"], device.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_logout_client_endpoint() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        let target = state.sessions.create_for_client("device-1", 3600);
+        let other = state.sessions.create_for_client("device-2", 3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/admin/clients/device-1/logout")
+                .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["revoked"], 1);
+        assert!(state.sessions.validate(&target.api_key).is_none());
+        assert!(state.sessions.validate(&other.api_key).is_some());
+        assert!(state.sessions.validate(&admin.api_key).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_register_batch_endpoint() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        let batch = serde_json::json!({
+            "clients": [
+                { "client_id": "device-1", "client_public_key": "B".repeat(64) },
+                { "client_id": "device-2", "client_public_key": "short" },
+            ]
+        });
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/register/batch")
+                .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(batch.to_string()))
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["registered"], 1);
+        assert_eq!(body["results"][0]["registered"], true);
+        assert_eq!(body["results"][0]["server_public_key"].as_str().unwrap().len(), 64);
+        assert_eq!(body["results"][1]["registered"], false);
+        assert!(body["results"][1]["error"].is_string());
+        assert_eq!(state.keystore.get_client("device-1").unwrap().client_public_key, "b".repeat(64));
+    }
+
+    #[tokio::test]
+    async fn test_register_batch_keeps_base64_key_case() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        let generated = hex::decode(ServerKeyPair::generate().public_key_hex()).unwrap();
+        let mut encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, generated);
+        // Force mixed case so lowercasing would change the key
+        encoded.replace_range(0..2, "Aa");
+        let key = hex::encode(parse_public_key(&encoded).unwrap());
+        let batch = serde_json::json!({
+            "clients": [{ "client_id": "device-1", "client_public_key": encoded }]
+        });
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/register/batch")
+                .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(batch.to_string()))
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["registered"], 1);
+        assert_eq!(state.keystore.get_client("device-1").unwrap().client_public_key, key);
+    }
+
+    #[tokio::test]
+    async fn test_session_stats_and_cleanup_endpoints() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let admin = state.sessions.create_admin(3600);
+        state.sessions.create(0);
+        state.sessions.create(0);
+        state.sessions.create(3600);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let send = |method: &str, uri: &str| Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+            .body(Body::empty())
+            .unwrap();
+
+        let res = app.clone().oneshot(send("GET", "/admin/sessions/stats")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({ "active": 2, "expired": 2, "total": 4 }));
+
+        let res = app.oneshot(send("POST", "/admin/sessions/cleanup")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["removed"], 2);
+        assert_eq!(state.sessions.stats().total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_admin_login_uses_admin_session_ttl() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        let mut config = (*state.config).clone();
+        config.admin_session_ttl_secs = 120;
+        state.config = std::sync::Arc::new(config);
+        let admin_key = state.admin.rotate_key().unwrap();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let req = Request::builder()
+            .method("POST")
+            .uri("/admin/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::json!({ "admin_key": admin_key }).to_string()))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let sessions = state.sessions.list_all_active();
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions[0].is_admin);
+        assert_eq!(sessions[0].ttl_secs, 120);
+        assert_eq!(sessions[0].expires_at - sessions[0].created_at, chrono::Duration::seconds(120));
+    }
+
+    #[tokio::test]
+    async fn test_export_public_keys_endpoint() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.register_clients_batch(vec![("device-1".to_string(), "a".repeat(64))]);
+        let admin = state.sessions.create_admin(3600);
+        let client = state.sessions.create(3600);
+
+        let request = |api_key: &str| {
+            Request::builder()
+                .uri("/register/keys/export")
+                .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let app = routes(state.clone()).with_state(state.clone());
+
+        let res = app.clone().oneshot(request(&client.api_key)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = app.oneshot(request(&admin.api_key)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, format!("device-1 {}\n", "a".repeat(64)));
+    }
+
+    #[tokio::test]
+    async fn test_storage_issues_endpoint() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("good").unwrap();
+        let clients = state.config.paths.client_config();
+        std::fs::write(&clients, "clients:\n  broken: 42\n").unwrap();
+        state.keystore = crate::services::KeyStoreManager::new(&state.config.paths).unwrap();
+        let admin = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let req = Request::builder()
+            .uri("/admin/storage/issues")
+            .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let issues = body["issues"].as_array().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0]["path"], clients);
+        assert!(state.keystore.get_server_key("good").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pending_registration_endpoints() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("pending").unwrap();
+        state.keystore.generate_server_key_for_client("done").unwrap();
+        state.keystore.register_client("done", &"a".repeat(64)).unwrap();
+        let admin = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let send = |method: &str, uri: &str, body: Body| Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+
+        let res = app.clone().oneshot(send("GET", "/admin/registrations/pending", Body::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let pending = body["pending"].as_array().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0]["client_id"], "pending");
+        assert!(pending[0].get("secret_key").is_none());
+
+        // Nothing is old enough yet
+        let prune = |secs: u64| Body::from(serde_json::json!({ "older_than_secs": secs }).to_string());
+        let res = app.clone().oneshot(send("POST", "/admin/registrations/pending/prune", prune(3600))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["removed"], 0);
+
+        // Zero means anything started before now
+        let res = app.oneshot(send("POST", "/admin/registrations/pending/prune", prune(0))).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["removed"], 1);
+        assert!(state.keystore.get_server_key("pending").is_none());
+        assert!(state.keystore.get_server_key("done").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_prune_pending_rejects_out_of_range_windows() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("pending").unwrap();
+        let admin = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        // Wraps negative as i64, too long for a duration, too far before now
+        for secs in [u64::MAX, i64::MAX as u64, (i64::MAX / 1000) as u64] {
+            let res = app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/admin/registrations/pending/prune")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", admin.api_key))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::json!({ "older_than_secs": secs }).to_string()))
+                    .unwrap(),
+            ).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "older_than_secs {}", secs);
+        }
+        assert!(state.keystore.get_server_key("pending").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_dashboard_matches_plaintext() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+        let admin_key = state.admin.rotate_key().unwrap();
+        let admin_keypair = ServerKeyPair::generate();
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let login = serde_json::json!({
+            "admin_key": admin_key,
+            "admin_public_key": admin_keypair.public_key_hex(),
+        });
+        let res = app.clone().oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/admin/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(login.to_string()))
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let login: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let api_key = login["message"].as_str().unwrap().rsplit(' ').next().unwrap().to_string();
+        let session_id = login["session_id"].as_str().unwrap();
+
+        let get = |uri: &str| Request::builder()
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(get("/admin/dashboard")).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let plaintext: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let res = app.oneshot(get("/admin/dashboard/encrypted")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let payload: EncryptedMessage = serde_json::from_value(body["payload"].clone()).unwrap();
+
+        // The admin derives the same key locally from the login response
+        let server_public = parse_public_key(login["server_public_key"].as_str().unwrap()).unwrap();
+        let shared = admin_keypair.derive_shared_secret(&server_public).unwrap();
+        let decrypted = payload.decrypt(&admin_payload_key(&shared, session_id)).unwrap();
+        let decrypted: serde_json::Value = serde_json::from_slice(&decrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert_eq!(decrypted["total_server_keys"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_dashboard_needs_payload_key() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+
+        let app = routes(state.clone()).with_state(state.clone());
+        let res = app.oneshot(
+            Request::builder()
+                .uri("/admin/dashboard/encrypted")
+                .header(header::AUTHORIZATION, format!("Bearer {}", session.api_key))
+                .body(Body::empty())
+                .unwrap(),
+        ).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
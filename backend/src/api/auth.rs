@@ -1,11 +1,13 @@
 //! Authentication endpoints
 
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
     http::StatusCode,
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use super::middleware::{bind_session, peer_ip};
 use crate::services::AppState;
 
 #[derive(Serialize)]
@@ -27,6 +29,13 @@ pub struct AuthRequest {
     pub api_key: String,
 }
 
+/// A session re-issued as a self-contained JWT
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub expires_at: String,
+}
+
 #[derive(Serialize)]
 pub struct LogoutResponse {
     pub success: bool,
@@ -35,8 +44,10 @@ pub struct LogoutResponse {
 /// Create a new session and return API key
 pub async fn join(
     State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
 ) -> Json<JoinResponse> {
     let session = state.sessions.create(state.config.session_ttl_secs);
+    bind_session(&state, &session, peer_ip(connect.as_ref()));
     
     Json(JoinResponse {
         session_id: session.id.to_string(),
@@ -48,9 +59,10 @@ pub async fn join(
 /// Verify an API key is valid
 pub async fn verify(
     State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<AuthRequest>,
 ) -> Result<Json<VerifyResponse>, StatusCode> {
-    match state.sessions.validate(&req.api_key) {
+    match state.sessions.validate_bound(&req.api_key, peer_ip(connect.as_ref())) {
         Some(session) => Ok(Json(VerifyResponse {
             valid: true,
             session_id: Some(session.id.to_string()),
@@ -64,6 +76,25 @@ pub async fn verify(
     }
 }
 
+/// Exchange a valid API key for a signed JWT of the same session.
+///
+/// The JWT works as a bearer token here until the session ends; revoking the
+/// session revokes it too. Services that only check the signature locally
+/// cannot see revocation and trust it until `exp`.
+pub async fn token(
+    State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
+    Json(req): Json<AuthRequest>,
+) -> Result<Json<TokenResponse>, (StatusCode, String)> {
+    let session = state.sessions.validate_bound(&req.api_key, peer_ip(connect.as_ref()))
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid or expired API key".to_string()))?;
+
+    Ok(Json(TokenResponse {
+        token: state.sessions.issue_jwt(&session),
+        expires_at: session.expires_at.to_rfc3339(),
+    }))
+}
+
 /// Logout and invalidate session
 pub async fn logout(
     State(state): State<AppState>,
@@ -1,20 +1,49 @@
-//! Health check endpoint
+//! Liveness and readiness endpoints
 
-use axum::Json;
+use axum::{extract::State, http::StatusCode, Json};
 use chrono::Utc;
 use serde::Serialize;
+use std::collections::HashMap;
+use crate::services::AppState;
 
 #[derive(Serialize)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
     pub timestamp: String,
+    pub registered_clients: usize,
+    pub active_sessions: usize,
 }
 
-pub async fn health_check() -> Json<HealthResponse> {
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    pub checks: HashMap<String, bool>,
+}
+
+/// Liveness: always 200 while the process can answer
+pub async fn health_check(
+    State(state): State<AppState>,
+) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "healthy".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         timestamp: Utc::now().to_rfc3339(),
+        registered_clients: state.keystore.list_clients().len(),
+        active_sessions: state.sessions.active_count(),
     })
 }
+
+/// Readiness: 503 until startup has finished and while the data directory is not writable
+pub async fn readiness_check(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<ReadyResponse>) {
+    let checks = HashMap::from([
+        ("startup_complete".to_string(), state.is_ready()),
+        ("data_dir_writable".to_string(), state.keystore.is_writable()),
+    ]);
+    let ready = checks.values().all(|ok| *ok);
+    let code = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (code, Json(ReadyResponse { ready, checks }))
+}
@@ -0,0 +1,71 @@
+//! Tests for health module
+
+#[cfg(test)]
+mod tests {
+    use crate::api::routes;
+    use crate::services::AppState;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+    };
+    use std::fs;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    async fn get(state: &AppState, uri: &str) -> (StatusCode, serde_json::Value) {
+        let app = routes(state.clone()).with_state(state.clone());
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_counts() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.sessions.create(3600);
+
+        let (status, body) = get(&state, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["active_sessions"], 1);
+        assert_eq!(body["registered_clients"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_ready_only_after_startup() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+
+        let (status, body) = get(&state, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"]["startup_complete"], false);
+
+        state.mark_ready();
+        let (status, body) = get(&state, "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["checks"]["data_dir_writable"], true);
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_when_data_dir_unwritable() {
+        let dir = tempdir().unwrap();
+        // A file where the data directory should be cannot be written into
+        let blocker = dir.path().join("data");
+        fs::write(&blocker, "not a directory").unwrap();
+        let state = AppState::for_tests(&blocker);
+        state.mark_ready();
+
+        let (status, body) = get(&state, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["data_dir_writable"], false);
+
+        // Liveness is unaffected
+        let (status, _) = get(&state, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -1,12 +1,19 @@
 //! Key exchange endpoints
 
 use axum::{
-    extract::State,
-    http::StatusCode,
+    body::Bytes,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
-use crate::services::{parse_public_key, AppState, EncryptedMessage};
+use std::net::SocketAddr;
+use super::middleware::{bind_session, peer_ip};
+use crate::services::{
+    constant_time_eq, key_confirmation_tag, open_raw, parse_public_key, ratchet_shared_secret, seal_raw, AppState, CryptoError,
+    EncryptedMessage, PublicKeyBytes, RatchetError, ServerKeyPair, SharedSecret,
+};
 
 /// Response with server's public key
 #[derive(Serialize)]
@@ -35,8 +42,27 @@ pub struct KeyExchangeResponse {
 pub struct EncryptedRequest {
     /// Client's public key for this message
     pub client_public_key: String,
+    /// Per-client counter that must strictly increase; authenticated as AAD.
+    /// With `ratchet_key`, the message's index in the ratchet chain instead.
+    pub sequence: u64,
     /// Encrypted payload
     pub payload: EncryptedMessage,
+    /// Chain from `/keys/ratchet` to encrypt under, rather than the shared secret
+    #[serde(default)]
+    pub ratchet_key: Option<String>,
+}
+
+/// Request to start a ratchet chain
+#[derive(Deserialize)]
+pub struct RatchetRequest {
+    pub client_public_key: String,
+}
+
+/// Server ephemeral key a new ratchet chain is rooted in
+#[derive(Serialize)]
+pub struct RatchetResponse {
+    /// Hex X25519 public key; also names the chain on `/keys/send`
+    pub ratchet_key: String,
 }
 
 /// Response with encrypted data
@@ -45,58 +71,225 @@ pub struct EncryptedResponse {
     pub payload: EncryptedMessage,
 }
 
+/// Request to confirm both sides derived the same shared secret
+#[derive(Deserialize)]
+pub struct ConfirmRequest {
+    pub client_public_key: String,
+    /// Hex HMAC-SHA256 tag from `key_confirmation_tag` under the shared secret
+    pub proof: String,
+}
+
+/// Result of a key confirmation
+#[derive(Serialize)]
+pub struct ConfirmResponse {
+    pub confirmed: bool,
+}
+
 /// Get server's public key for key exchange
 pub async fn get_public_key(
     State(state): State<AppState>,
 ) -> Json<PublicKeyResponse> {
     Json(PublicKeyResponse {
-        public_key: state.server_keypair.public_key_hex(),
+        public_key: state.server_key.current().public_key_hex(),
     })
 }
 
 /// Perform key exchange and create encrypted session
 pub async fn key_exchange(
     State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<KeyExchangeRequest>,
 ) -> Result<Json<KeyExchangeResponse>, (StatusCode, String)> {
     // Parse client's public key
     let client_public = parse_public_key(&req.client_public_key)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-    // Derive shared secret (not returned, used for encryption)
-    let _shared_secret = state.server_keypair.derive_shared_secret(&client_public);
+    // Derive shared secret (not returned, used for encryption); this also
+    // rejects low-order keys before a session is handed out
+    let server_keypair = state.server_key.current();
+    let _shared_secret = server_keypair.derive_shared_secret(&client_public)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
     // Create session
     let session = state.sessions.create(state.config.session_ttl_secs);
+    bind_session(&state, &session, peer_ip(connect.as_ref()));
 
     Ok(Json(KeyExchangeResponse {
         session_id: session.id.to_string(),
         api_key: session.api_key,
         expires_at: session.expires_at.to_rfc3339(),
-        server_public_key: state.server_keypair.public_key_hex(),
+        server_public_key: server_keypair.public_key_hex(),
     }))
 }
 
-/// Send encrypted message to server
-pub async fn send_encrypted(
+/// Check a client's proof that it derived the same shared secret, without
+/// either side revealing it
+pub async fn confirm_key(
     State(state): State<AppState>,
-    Json(req): Json<EncryptedRequest>,
-) -> Result<Json<EncryptedResponse>, (StatusCode, String)> {
-    // Parse client's public key
+    Json(req): Json<ConfirmRequest>,
+) -> Result<Json<ConfirmResponse>, (StatusCode, String)> {
+    let proof = hex::decode(req.proof.trim())
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Proof must be hex-encoded".to_string()))?;
+
+    let (client_public, secrets) = shared_secrets_for(&state, &req.client_public_key)?;
+    let confirmed = secrets.iter().any(|secret| {
+        let expected = key_confirmation_tag(secret, &client_public);
+        proof.len() == expected.len() && constant_time_eq(&proof, &expected)
+    });
+    if !confirmed {
+        return Err((StatusCode::BAD_REQUEST, "Key confirmation failed".to_string()));
+    }
+
+    Ok(Json(ConfirmResponse { confirmed: true }))
+}
+
+/// Start a ratchet chain rooted in the static shared secret and a fresh
+/// server ephemeral key, whose secret half is dropped once the root is derived
+pub async fn start_ratchet(
+    State(state): State<AppState>,
+    Json(req): Json<RatchetRequest>,
+) -> Result<Json<RatchetResponse>, (StatusCode, String)> {
     let client_public = parse_public_key(&req.client_public_key)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let static_secret = state.server_key.current().derive_shared_secret(&client_public)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let ephemeral = ServerKeyPair::generate();
+    let ephemeral_secret = ephemeral.derive_shared_secret(&client_public)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let root = ratchet_shared_secret(&static_secret, &ephemeral_secret);
+
+    let ratchet_key = ephemeral.public_key_hex();
+    if !state.ratchets.start(&hex::encode(client_public), &ratchet_key, &root) {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Ratchet key collision".to_string()));
+    }
+    Ok(Json(RatchetResponse { ratchet_key }))
+}
+
+/// Header carrying the client's public key on `/keys/send-binary`
+pub const CLIENT_PUBLIC_KEY_HEADER: &str = "x-client-public-key";
+/// Header carrying the message sequence on `/keys/send-binary`
+pub const SEQUENCE_HEADER: &str = "x-sequence";
 
-    // Derive shared secret
-    let shared_secret = state.server_keypair.derive_shared_secret(&client_public);
+/// Handler error, as returned by every endpoint here
+type ApiError = (StatusCode, String);
 
-    // Decrypt the incoming message
-    let plaintext = req.payload.decrypt(&shared_secret)
+/// Shared secrets with every accepted server key, current key first
+fn shared_secrets_for(state: &AppState, client_public_key: &str) -> Result<(PublicKeyBytes, Vec<SharedSecret>), ApiError> {
+    let client_public = parse_public_key(client_public_key)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let secrets = state.server_key.accepted()
+        .iter()
+        .map(|keypair| keypair.derive_shared_secret(&client_public))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok((client_public, secrets))
+}
+
+/// An incoming message opened by [`decrypt_for`]
+struct Decrypted {
+    client_public: PublicKeyBytes,
+    /// Secret the message was encrypted under; replies use the same one
+    shared_secret: SharedSecret,
+    plaintext: Vec<u8>,
+}
+
+/// Decrypt with whichever accepted server key the client used, so messages under
+/// a just-rotated key still work during the grace period
+fn decrypt_for(
+    state: &AppState,
+    client_public_key: &str,
+    decrypt: impl Fn(&SharedSecret) -> Result<Vec<u8>, CryptoError>,
+) -> Result<Decrypted, ApiError> {
+    let (client_public, secrets) = shared_secrets_for(state, client_public_key)?;
+    let mut first_error = None;
+    for secret in secrets {
+        match decrypt(&secret) {
+            Ok(plaintext) => return Ok(Decrypted { client_public, shared_secret: secret, plaintext }),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(decrypt_error(first_error.unwrap_or(CryptoError::DecryptionFailed)))
+}
+
+fn decrypt_error(e: CryptoError) -> ApiError {
+    match e {
+        CryptoError::CiphertextTooLarge | CryptoError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()),
+        _ => (StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+/// Only accept sequences newer than the last one seen from this key
+fn check_replay(state: &AppState, client_public: &PublicKeyBytes, sequence: u64) -> Result<(), ApiError> {
+    let key = hex::encode(client_public);
+    let fresh = if state.keystore.find_client_by_public_key(&key).is_some() {
+        state.replay_guard.check_registered(&key, sequence)
+    } else {
+        state.replay_guard.check(&key, sequence)
+    };
+    if !fresh {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Sequence {} has already been used", sequence),
+        ));
+    }
+    Ok(())
+}
+
+/// Open a ratcheted message. Each chain index opens once, so the ratchet
+/// takes over from the replay guard and tolerates reordering in its window.
+fn decrypt_ratcheted(state: &AppState, req: &EncryptedRequest, ratchet_key: &str) -> Result<Decrypted, ApiError> {
+    let client_public = parse_public_key(&req.client_public_key)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let (plaintext, message_key) = state.ratchets
+        .open(&hex::encode(client_public), ratchet_key, req.sequence, |key| {
+            req.payload.decrypt_with_limit(key, &req.sequence.to_be_bytes(), state.config.max_ciphertext_len)
+        })
+        .map_err(|e| match e {
+            RatchetError::Crypto(e) => decrypt_error(e),
+            _ => (StatusCode::CONFLICT, e.to_string()),
+        })?;
+    Ok(Decrypted { client_public, shared_secret: message_key, plaintext })
+}
+
+/// Activity tracking and processing shared by both send endpoints
+fn process_message(
+    state: &AppState,
+    client_public: &PublicKeyBytes,
+    plaintext: &[u8],
+) -> Result<String, (StatusCode, String)> {
+    // Record activity for the registered client using this key, if any
+    if let Some(client) = state.keystore.find_client_by_public_key(&hex::encode(client_public)) {
+        if let Err(e) = state.keystore.touch_client(&client.client_id) {
+            tracing::warn!("Failed to update last_seen for '{}': {}", client.client_id, e);
+        }
+    }
 
     // Process the message (echo back for now)
-    let response_text = format!("Received: {}", String::from_utf8_lossy(&plaintext));
+    Ok(format!("Received: {}", String::from_utf8_lossy(plaintext)))
+}
 
-    // Encrypt the response
+/// Send encrypted message to server
+pub async fn send_encrypted(
+    State(state): State<AppState>,
+    Json(req): Json<EncryptedRequest>,
+) -> Result<Json<EncryptedResponse>, (StatusCode, String)> {
+    // Decrypt the incoming message, which also authenticates the sequence
+    let Decrypted { client_public, shared_secret, plaintext } = if let Some(ratchet_key) = &req.ratchet_key {
+        decrypt_ratcheted(&state, &req, ratchet_key)?
+    } else {
+        let decrypted = decrypt_for(&state, &req.client_public_key, |secret| {
+            req.payload.decrypt_with_limit(secret, &req.sequence.to_be_bytes(), state.config.max_ciphertext_len)
+        })?;
+        check_replay(&state, &decrypted.client_public, req.sequence)?;
+        decrypted
+    };
+
+    let response_text = process_message(&state, &client_public, &plaintext)?;
+
+    // Encrypt the response; ratcheted replies reuse the message key
     let encrypted_response = EncryptedMessage::encrypt(response_text.as_bytes(), &shared_secret)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -104,3 +297,35 @@ pub async fn send_encrypted(
         payload: encrypted_response,
     }))
 }
+
+/// Binary variant of [`send_encrypted`]: the body is raw `nonce || ciphertext`
+/// and the response is the same, without base64 or JSON framing
+pub async fn send_binary(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let header_str = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Missing {} header", name)))
+    };
+    let sequence: u64 = header_str(SEQUENCE_HEADER)?
+        .trim()
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid {} header", SEQUENCE_HEADER)))?;
+
+    // Decrypt the incoming message, which also authenticates the sequence
+    let Decrypted { client_public, shared_secret, plaintext } = decrypt_for(&state, header_str(CLIENT_PUBLIC_KEY_HEADER)?, |secret| {
+        open_raw(&body, secret, &sequence.to_be_bytes(), state.config.max_ciphertext_len)
+    })?;
+
+    check_replay(&state, &client_public, sequence)?;
+    let response_text = process_message(&state, &client_public, &plaintext)?;
+
+    let sealed = seal_raw(response_text.as_bytes(), &shared_secret, &[])
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(([(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"))], sealed))
+}
@@ -0,0 +1,107 @@
+//! Authentication middleware for protected routes
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use crate::services::{AppState, Session};
+
+/// Extract the token from an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Caller address; without connection info (e.g. in-process tests) every caller is 0.0.0.0
+pub fn peer_ip(connect: Option<&ConnectInfo<SocketAddr>>) -> IpAddr {
+    connect
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Tie a new session to the caller's IP when `SESSION_BIND_IP` is on
+pub fn bind_session(state: &AppState, session: &Session, ip: IpAddr) {
+    if state.config.bind_sessions_to_ip {
+        state.sessions.bind_to_ip(&session.api_key, ip);
+    }
+}
+
+/// Validate the bearer token in `req`, an API key or a JWT from `/auth/token`,
+/// against the session store
+fn authenticate(state: &AppState, req: &Request) -> Result<Session, (StatusCode, String)> {
+    let api_key = bearer_token(req.headers())
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Missing bearer token".to_string()))?;
+
+    let ip = peer_ip(req.extensions().get());
+    // API keys are unpadded URL-safe base64, so only JWTs contain dots
+    let session = if api_key.contains('.') {
+        state.sessions.validate_jwt_bound(api_key, ip)
+    } else {
+        state.sessions.validate_bound(api_key, ip)
+    };
+    session.ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid or expired API key".to_string()))
+}
+
+/// Require a valid session API key as a bearer token.
+///
+/// The validated `Session` is stored in the request extensions for handlers,
+/// and a client's session counts as activity for its `last_seen`.
+pub async fn require_session(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let session = authenticate(&state, &req)?;
+    if let Some(client_id) = &session.client_id {
+        if let Err(e) = state.keystore.touch_client(client_id) {
+            tracing::warn!("Failed to update last_seen for '{}': {}", client_id, e);
+        }
+    }
+    req.extensions_mut().insert(session);
+    Ok(next.run(req).await)
+}
+
+/// Require a valid session that was created through admin login
+pub async fn require_admin(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let session = authenticate(&state, &req)?;
+    if !session.is_admin {
+        return Err((StatusCode::FORBIDDEN, "Admin session required".to_string()));
+    }
+
+    req.extensions_mut().insert(session);
+    Ok(next.run(req).await)
+}
+
+/// Throttle registration requests per client IP.
+///
+/// Responds with `429 Too Many Requests` and `Retry-After` once the
+/// configured per-minute budget is spent.
+pub async fn rate_limit_register(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Without connection info (e.g. in-process tests) all callers share one bucket
+    let ip = peer_ip(req.extensions().get());
+
+    match state.register_limiter.check(ip) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, (retry_after.as_secs_f64().ceil() as u64).max(1).to_string())],
+            "Too many requests".to_string(),
+        ).into_response(),
+    }
+}
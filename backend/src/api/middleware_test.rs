@@ -0,0 +1,231 @@
+//! Tests for middleware module
+
+#[cfg(test)]
+mod tests {
+    use crate::api::routes;
+    use crate::services::{AppState, RateLimiter, RatchetStore, ServerKeyPair};
+    use axum::{
+        body::Body,
+        extract::ConnectInfo,
+        http::{header, Request, StatusCode},
+        Router,
+    };
+    use std::net::SocketAddr;
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    fn app(state: &AppState) -> Router {
+        routes(state.clone()).with_state(state.clone())
+    }
+
+    fn get(uri: &str, api_key: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(key) = api_key {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_protected_route_requires_bearer_token() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+
+        let res = app(&state).oneshot(get("/register/me", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_protected_route_rejects_unknown_key() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+
+        let res = app(&state).oneshot(get("/register/me", Some("omni_bogus"))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_protected_route_accepts_valid_key() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+        let registered = state.keystore.register_client("device-1", &"a".repeat(64)).unwrap();
+        let session = state.sessions.create_for_client("device-1", 3600);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let res = app(&state).oneshot(get("/register/me", Some(&session.api_key))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // Using a client's session counts as activity
+        assert!(state.keystore.get_client("device-1").unwrap().last_seen > registered.last_seen);
+    }
+
+    #[tokio::test]
+    async fn test_protected_route_accepts_jwt_until_revoked() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+        state.keystore.register_client("device-1", &"a".repeat(64)).unwrap();
+        let session = state.sessions.create_for_client("device-1", 3600);
+        let token = state.sessions.issue_jwt(&session);
+
+        let res = app(&state).oneshot(get("/register/me", Some(&token))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        state.sessions.revoke(&session.api_key);
+        let res = app(&state).oneshot(get("/register/me", Some(&token))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_client_listings_refuse_joined_session() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+
+        let join = Request::builder().method("POST").uri("/auth/join").body(Body::empty()).unwrap();
+        let res = app(&state).oneshot(join).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let api_key = body["api_key"].as_str().unwrap();
+
+        for uri in ["/register/clients", "/register/keys"] {
+            let res = app(&state).oneshot(get(uri, Some(api_key))).await.unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+
+        let admin = state.sessions.create_admin(3600);
+        for uri in ["/register/clients", "/register/keys"] {
+            let res = app(&state).oneshot(get(uri, Some(&admin.api_key))).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_public_routes_stay_open() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+
+        let res = app(&state).oneshot(get("/health", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app(&state).oneshot(get("/server/info", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_admin_route_rejects_regular_session() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create(3600);
+
+        let res = app(&state).oneshot(get("/admin/dashboard", Some(&session.api_key))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_admin_route_accepts_admin_session() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let session = state.sessions.create_admin(3600);
+
+        let res = app(&state).oneshot(get("/admin/dashboard", Some(&session.api_key))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    fn register_init_from(addr: &str) -> Request<Body> {
+        let mut req = Request::builder()
+            .method("POST")
+            .uri("/register/init")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"client_id":"rate-limited"}"#))
+            .unwrap();
+        req.extensions_mut().insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_registration_is_rate_limited_per_ip() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.register_limiter = RateLimiter::per_minute(2);
+
+        for _ in 0..2 {
+            let res = app(&state).oneshot(register_init_from("10.0.0.1:5000")).await.unwrap();
+            assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let res = app(&state).oneshot(register_init_from("10.0.0.1:5001")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "30");
+
+        // Another address still has its own budget
+        let res = app(&state).oneshot(register_init_from("10.0.0.2:5000")).await.unwrap();
+        assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    fn start_ratchet_from(addr: &str) -> Request<Body> {
+        let body = serde_json::json!({ "client_public_key": ServerKeyPair::generate().public_key_hex() });
+        let mut req = Request::builder()
+            .method("POST")
+            .uri("/keys/ratchet")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        req.extensions_mut().insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_ratchet_flood_cannot_evict_other_chains() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.register_limiter = RateLimiter::per_minute(5);
+        state.ratchets = RatchetStore::new(8, 32);
+
+        let res = app(&state).oneshot(start_ratchet_from("10.0.0.2:5000")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let ratchet_key = body["ratchet_key"].as_str().unwrap().to_string();
+
+        // Far more starts than the store holds, all from one address
+        let mut throttled = 0;
+        for _ in 0..50 {
+            let res = app(&state).oneshot(start_ratchet_from("10.0.0.1:5000")).await.unwrap();
+            if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                throttled += 1;
+            }
+        }
+        assert_eq!(throttled, 45);
+        assert_eq!(state.ratchets.next_index(&ratchet_key), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_bound_session_rejected_from_other_ip() {
+        let dir = tempdir().unwrap();
+        let mut state = AppState::for_tests(dir.path());
+        state.config = std::sync::Arc::new(crate::config::Config {
+            bind_sessions_to_ip: true,
+            ..(*state.config).clone()
+        });
+        let from = |addr: &str, mut req: Request<Body>| {
+            req.extensions_mut().insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+            req
+        };
+
+        let join = Request::builder().method("POST").uri("/auth/join").body(Body::empty()).unwrap();
+        let res = app(&state).oneshot(from("10.0.0.1:5000", join)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let api_key = body["api_key"].as_str().unwrap();
+
+        // A new source port on the same host is fine; the session authenticates
+        // but, not being a client's, has no registration to return
+        let res = app(&state).oneshot(from("10.0.0.1:6000", get("/register/me", Some(api_key)))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = app(&state).oneshot(from("10.0.0.2:5000", get("/register/me", Some(api_key)))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
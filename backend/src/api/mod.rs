@@ -4,31 +4,93 @@ mod admin;
 mod auth;
 mod health;
 mod keys;
+mod middleware;
+mod negotiate;
 mod register;
 
-use axum::{routing::{get, post}, Router};
-use crate::services::AppState;
+#[cfg(test)]
+mod admin_test;
+#[cfg(test)]
+mod health_test;
+#[cfg(test)]
+mod middleware_test;
+#[cfg(test)]
+mod negotiate_test;
+
+use axum::{extract::DefaultBodyLimit, routing::{get, post, put}, Router};
+use crate::services::{base64_len, AppState};
+
+/// Room for the JSON envelope around an encrypted payload
+const ENVELOPE_OVERHEAD: usize = 16 * 1024;
+
+pub fn routes(state: AppState) -> Router<AppState> {
+    // Routes that require a valid session bearer token
+    let protected = Router::new()
+        .route("/register/me", get(register::get_self))
+        .route("/register/metadata", put(register::update_metadata))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::require_session));
+
+    // Registration and ratchet setup, throttled per client IP; each new
+    // ratchet can push another client's chain out of the store
+    let registration = Router::new()
+        .route("/register/init", post(register::register_init))
+        .route("/register/complete", post(register::register_complete))
+        .route("/register/bootstrap", post(register::register_bootstrap))
+        .route("/keys/ratchet", post(keys::start_ratchet))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::rate_limit_register));
+
+    // Routes that require an admin session
+    let admin_only = Router::new()
+        .route("/admin/dashboard", get(admin::admin_dashboard))
+        .route("/admin/dashboard/encrypted", get(admin::admin_dashboard_encrypted))
+        .route("/admin/rotate-key", post(admin::rotate_admin_key))
+        .route("/admin/server-key/rotate", post(admin::rotate_server_key))
+        .route("/admin/clients/prune", post(admin::prune_idle_clients))
+        .route("/admin/registrations/pending", get(admin::pending_registrations))
+        .route("/admin/registrations/pending/prune", post(admin::prune_pending_registrations))
+        .route("/admin/backup", get(admin::backup))
+        // A restore carries the whole key store, well past axum's default limit
+        .route("/admin/restore", post(admin::restore).layer(DefaultBodyLimit::max(state.config.max_backup_len)))
+        .route("/admin/sessions", get(admin::list_sessions))
+        .route("/admin/sessions/stats", get(admin::session_stats))
+        .route("/admin/sessions/cleanup", post(admin::cleanup_sessions))
+        .route("/admin/keys/health", get(admin::key_health))
+        .route("/admin/storage/issues", get(admin::storage_issues))
+        .route("/admin/clients/:client_id/logout", post(admin::logout_client))
+        // Every client and key; `/auth/join` sessions are open to anyone
+        .route("/register/clients", get(register::list_clients))
+        .route("/register/keys", get(register::list_server_keys))
+        .route("/register/batch", post(register::register_batch))
+        .route("/register/keys/export", get(register::export_public_keys))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::require_admin));
+
+    // Encrypted payloads: cap bodies just above the largest accepted ciphertext
+    let body_limit = base64_len(state.config.max_ciphertext_len) + ENVELOPE_OVERHEAD;
+    let encrypted = Router::new()
+        .route("/keys/exchange", post(keys::key_exchange))
+        .route("/keys/send", post(keys::send_encrypted))
+        .route("/keys/send-binary", post(keys::send_binary))
+        .layer(DefaultBodyLimit::max(body_limit));
 
-pub fn routes() -> Router<AppState> {
     Router::new()
         // Health
         .route("/health", get(health::health_check))
+        .route("/ready", get(health::readiness_check))
         // Server info (public)
         .route("/server/info", get(admin::get_server_info))
         // Admin
         .route("/admin/login", post(admin::admin_login))
-        .route("/admin/dashboard", get(admin::admin_dashboard))
         // Auth
         .route("/auth/join", post(auth::join))
         .route("/auth/verify", post(auth::verify))
+        .route("/auth/token", post(auth::token))
         .route("/auth/logout", post(auth::logout))
         // Key exchange (legacy)
         .route("/keys/public", get(keys::get_public_key))
-        .route("/keys/exchange", post(keys::key_exchange))
-        .route("/keys/send", post(keys::send_encrypted))
+        .route("/keys/confirm", post(keys::confirm_key))
+        .merge(encrypted)
         // Registration (per-client keypairs)
-        .route("/register/init", post(register::register_init))
-        .route("/register/complete", post(register::register_complete))
-        .route("/register/clients", get(register::list_clients))
-        .route("/register/keys", get(register::list_server_keys))
+        .merge(registration)
+        .merge(protected)
+        .merge(admin_only)
 }
@@ -0,0 +1,64 @@
+//! Response content-type negotiation (JSON or YAML)
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::convert::Infallible;
+
+const APPLICATION_YAML: &str = "application/yaml";
+
+/// Serialization format picked from the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// YAML only if a YAML type is listed before `application/json`; otherwise JSON
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return Self::Json;
+        };
+        let wants_yaml = accept
+            .split(',')
+            .map(|part| part.split(';').next().unwrap_or("").trim())
+            .take_while(|media| *media != "application/json")
+            .any(|media| matches!(media, APPLICATION_YAML | "application/x-yaml" | "text/yaml"));
+        if wants_yaml { Self::Yaml } else { Self::Json }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts.headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+        Ok(Self::from_accept(accept))
+    }
+}
+
+/// Handler output serialized in the negotiated format
+pub struct Negotiated<T>(pub Format, pub T);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        match self.0 {
+            Format::Json => Json(self.1).into_response(),
+            Format::Yaml => match serde_yaml::to_string(&self.1) {
+                Ok(body) => (
+                    [(header::CONTENT_TYPE, HeaderValue::from_static(APPLICATION_YAML))],
+                    body,
+                ).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            },
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Tests for negotiate module
+
+#[cfg(test)]
+mod tests {
+    use crate::api::negotiate::*;
+    use crate::api::routes;
+    use crate::services::AppState;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request, StatusCode},
+    };
+    use tempfile::tempdir;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_format_from_accept() {
+        assert_eq!(Format::from_accept(None), Format::Json);
+        assert_eq!(Format::from_accept(Some("*/*")), Format::Json);
+        assert_eq!(Format::from_accept(Some("application/json")), Format::Json);
+        assert_eq!(Format::from_accept(Some("application/yaml")), Format::Yaml);
+        assert_eq!(Format::from_accept(Some("text/html, application/x-yaml;q=0.9")), Format::Yaml);
+        // Earlier entries win
+        assert_eq!(Format::from_accept(Some("application/json, application/yaml")), Format::Json);
+    }
+
+    async fn server_info(accept: Option<&str>) -> (Option<String>, Vec<u8>) {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let mut req = Request::builder().uri("/server/info");
+        if let Some(accept) = accept {
+            req = req.header(header::ACCEPT, accept);
+        }
+
+        let app = routes(state.clone()).with_state(state);
+        let res = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let content_type = res.headers().get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        (content_type, to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_server_info_defaults_to_json() {
+        for accept in [None, Some("*/*"), Some("application/json")] {
+            let (content_type, body) = server_info(accept).await;
+            assert_eq!(content_type.as_deref(), Some("application/json"));
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["server_name"], "Omni Core Server");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_info_as_yaml() {
+        let (content_type, body) = server_info(Some("application/yaml")).await;
+
+        assert_eq!(content_type.as_deref(), Some("application/yaml"));
+        let body: serde_yaml::Value = serde_yaml::from_slice(&body).unwrap();
+        assert_eq!(body["server_name"].as_str(), Some("Omni Core Server"));
+        assert_eq!(body["server_public_key"].as_str().unwrap().len(), 64);
+    }
+}
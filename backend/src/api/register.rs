@@ -1,12 +1,17 @@
 //! Client registration endpoints with per-client keypairs
 
 use axum::{
-    extract::State,
-    http::StatusCode,
-    Json,
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
-use crate::services::{AppState, EncryptedMessage};
+use std::net::SocketAddr;
+use super::admin::{server_info, ServerInfoResponse};
+use super::middleware::{bind_session, peer_ip};
+use crate::audit::{audit_event, AuditKind, Outcome};
+use crate::services::{parse_public_key, AppState, ClientEntry, ClientMetadata, EncryptedMessage, KeyStoreError, Session};
 
 /// Request to initiate registration
 #[derive(Deserialize)]
@@ -20,6 +25,8 @@ pub struct RegisterInitRequest {
 pub struct RegisterInitResponse {
     pub client_id: String,
     pub server_public_key: String,
+    /// Random hex challenge, bound to the encrypted key as associated data
+    pub challenge: String,
     pub message: String,
 }
 
@@ -27,7 +34,10 @@ pub struct RegisterInitResponse {
 #[derive(Deserialize)]
 pub struct RegisterCompleteRequest {
     pub client_id: String,
-    /// Client's public key, encrypted with server's public key
+    /// One-time X25519 public key used only to encrypt this request
+    pub ephemeral_public_key: String,
+    /// Client's raw 32-byte public key, encrypted under the secret shared by the
+    /// ephemeral key and the per-client server key, with the challenge as AAD
     pub encrypted_client_public_key: EncryptedMessage,
 }
 
@@ -40,10 +50,80 @@ pub struct RegisterCompleteResponse {
     pub message: String,
 }
 
-/// List of registered clients (for admin/debug)
+/// Request to register in one step by sending the public key directly
+#[derive(Deserialize)]
+pub struct BootstrapRequest {
+    pub client_id: String,
+    /// Client's X25519 public key (hex-encoded)
+    pub client_public_key: String,
+}
+
+/// Everything a new client needs: its server key, a session and server details
+#[derive(Serialize)]
+pub struct ClientBootstrap {
+    pub client_id: String,
+    /// Server's per-client public key
+    pub server_public_key: String,
+    pub api_key: String,
+    pub expires_at: String,
+    pub server: ServerInfoResponse,
+}
+
+/// Most clients accepted by one batch registration
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// A client to provision in a batch
+#[derive(Deserialize)]
+pub struct BatchClient {
+    pub client_id: String,
+    /// Client's X25519 public key (hex-encoded)
+    pub client_public_key: String,
+}
+
+/// Request to register many clients at once
+#[derive(Deserialize)]
+pub struct BatchRegisterRequest {
+    pub clients: Vec<BatchClient>,
+}
+
+/// Outcome for one client in a batch
 #[derive(Serialize)]
-pub struct ClientListResponse {
-    pub clients: Vec<ClientInfo>,
+pub struct BatchResult {
+    pub client_id: String,
+    pub registered: bool,
+    /// Server's per-client public key, when registered
+    pub server_public_key: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Per-client outcomes, in request order
+#[derive(Serialize)]
+pub struct BatchRegisterResponse {
+    pub registered: usize,
+    pub results: Vec<BatchResult>,
+}
+
+/// Default page size for list endpoints
+const DEFAULT_PAGE_LIMIT: usize = 100;
+/// Largest page size a caller may request
+const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Pagination and filtering query parameters
+#[derive(Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Case-insensitive substring to match against the client_id
+    pub search: Option<String>,
+}
+
+/// One page of results
+#[derive(Serialize)]
+pub struct PaginatedResponse<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
 }
 
 #[derive(Serialize)]
@@ -53,6 +133,18 @@ pub struct ClientInfo {
     pub last_seen: Option<String>,
 }
 
+/// The calling client's own registration, without server secrets
+#[derive(Serialize)]
+pub struct ClientSelfResponse {
+    pub client_id: String,
+    pub client_public_key: String,
+    /// Server's per-client public key
+    pub server_public_key: Option<String>,
+    pub registered_at: String,
+    pub last_seen: Option<String>,
+    pub metadata: ClientMetadata,
+}
+
 /// List of server keys (public keys only)
 #[derive(Serialize)]
 pub struct ServerKeyListResponse {
@@ -80,19 +172,24 @@ pub async fn register_init(
     }
 
     // Generate new server keypair for this client
-    let server_key = state.keystore.generate_server_key_for_client(&req.client_id);
+    let server_key = state.keystore.generate_server_key_for_client(&req.client_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let challenge = state.challenges.issue(&req.client_id);
 
     Ok(Json(RegisterInitResponse {
         client_id: req.client_id,
         server_public_key: server_key.public_key,
-        message: "Encrypt your public key with this server key and send to /register/complete".to_string(),
+        challenge,
+        message: "Encrypt your public key with an ephemeral key and this server key, then send to /register/complete".to_string(),
     }))
 }
 
-/// Step 2: Client sends their public key encrypted with server's public key
-/// Server decrypts and stores the client's public key
+/// Step 2: Client sends its public key encrypted under an ephemeral ECDH secret.
+/// Server derives the same secret, decrypts and stores the client's public key
 pub async fn register_complete(
     State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<RegisterCompleteRequest>,
 ) -> Result<Json<RegisterCompleteResponse>, (StatusCode, String)> {
     // Get server key for this client
@@ -102,46 +199,75 @@ pub async fn register_complete(
             format!("No pending registration for client '{}'", req.client_id),
         ))?;
 
-    // For initial registration, we need to decrypt using a temporary shared secret
-    // The client encrypted with server's public key, so we use server's secret
-    // But we don't have client's public key yet... 
-    // 
-    // Alternative approach: Client sends public key in plaintext for initial exchange
-    // Then all subsequent communication is encrypted
-    // This is the standard ECDH approach - public keys are meant to be public
-    
-    // Actually, let's use a simpler approach for registration:
-    // The encrypted_client_public_key is encrypted using a key derived from
-    // a well-known value (like the client_id) as a simple proof of intent
-    
-    // For now, let's accept the public key directly (it's meant to be public anyway)
-    // The encryption layer is for the actual data exchange after registration
-    
-    // Decode the "encrypted" public key (in this case, just base64 for transport)
-    let client_public_key = String::from_utf8(
-        base64::Engine::decode(
-            &base64::engine::general_purpose::STANDARD,
-            &req.encrypted_client_public_key.ciphertext
-        ).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
-    ).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-
-    // Validate it's a valid hex public key (64 hex chars = 32 bytes)
-    if client_public_key.len() != 64 || hex::decode(&client_public_key).is_err() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Invalid public key format (expected 64 hex characters)".to_string(),
-        ));
-    }
+    // The challenge is single-use, so a failed attempt needs a new /register/init.
+    // A client that is already registered may be retrying after a lost response,
+    // so its request is checked against the challenge the first attempt used.
+    let challenge = if state.keystore.get_client(&req.client_id).is_some() {
+        state.challenges.last_taken(&req.client_id)
+            .ok_or_else(|| (
+                StatusCode::CONFLICT,
+                format!("Client '{}' already registered", req.client_id),
+            ))?
+    } else {
+        state.challenges.take(&req.client_id)
+            .ok_or_else(|| (
+                StatusCode::BAD_REQUEST,
+                "No valid challenge for this client; call /register/init again".to_string(),
+            ))?
+    };
+    let challenge = hex::decode(challenge)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Register the client
-    let _client = state.keystore.register_client(&req.client_id, &client_public_key)
-        .ok_or_else(|| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to register client".to_string(),
-        ))?;
+    let ephemeral = parse_public_key(&req.ephemeral_public_key)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let shared_secret = server_key.derive_shared_secret(&hex::encode(ephemeral))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid ephemeral public key".to_string()))?;
 
-    // Create a session for the client
-    let session = state.sessions.create(state.config.session_ttl_secs);
+    let plaintext = req.encrypted_client_public_key
+        .decrypt_with_aad(&shared_secret, &challenge)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let client_public: [u8; 32] = plaintext.try_into()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Client public key must be 32 bytes".to_string()))?;
+    let client_public_key = hex::encode(client_public);
+
+    // Register the client; a retry with the same key keeps the stored entry
+    let (_, created) = state.keystore.register_client_once(&req.client_id, &client_public_key)
+        .inspect_err(|_| audit_event(AuditKind::ClientRegistered, &req.client_id, Outcome::Failure))
+        .map_err(|e| match e {
+            KeyStoreError::MissingServerKey(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            KeyStoreError::AlreadyRegistered(_) => (StatusCode::CONFLICT, e.to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    // Create a session for the client. A retry gets back the session its own
+    // completion created, and only at the address that completion came from,
+    // so a replayed request body doesn't hand out a live API key.
+    let ip = peer_ip(connect.as_ref());
+    let existing = if created {
+        audit_event(AuditKind::ClientRegistered, &req.client_id, Outcome::Success);
+        None
+    } else {
+        let api_key = state.challenges.completion_for(&req.client_id, ip)
+            .ok_or_else(|| (
+                StatusCode::CONFLICT,
+                format!("Client '{}' already registered", req.client_id),
+            ))?;
+        let session = state.sessions.validate_bound(&api_key, ip);
+        if session.is_none() {
+            // Expired or logged out since; replace it rather than revive it
+            state.sessions.revoke(&api_key);
+        }
+        session
+    };
+    let session = match existing {
+        Some(session) => session,
+        None => {
+            let session = state.sessions.create_for_client(&req.client_id, state.config.session_ttl_secs);
+            bind_session(&state, &session, ip);
+            state.challenges.record_completion(&req.client_id, &session.api_key, ip);
+            session
+        }
+    };
 
     Ok(Json(RegisterCompleteResponse {
         client_id: req.client_id,
@@ -151,11 +277,101 @@ pub async fn register_complete(
     }))
 }
 
-/// List all registered clients
+/// Init, complete and session creation in one request, for clients that can
+/// send their public key immediately
+pub async fn register_bootstrap(
+    State(state): State<AppState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
+    Json(req): Json<BootstrapRequest>,
+) -> Result<Json<ClientBootstrap>, (StatusCode, String)> {
+    let client_public = parse_public_key(&req.client_public_key)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let (client, server_key) = state.keystore.bootstrap_client(&req.client_id, &hex::encode(client_public))
+        .inspect_err(|_| audit_event(AuditKind::ClientRegistered, &req.client_id, Outcome::Failure))
+        .map_err(|e| match e {
+            KeyStoreError::AlreadyRegistered(_) | KeyStoreError::RegistrationPending(_) => (StatusCode::CONFLICT, e.to_string()),
+            KeyStoreError::InvalidPublicKey(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    audit_event(AuditKind::ClientRegistered, &client.client_id, Outcome::Success);
+
+    let session = state.sessions.create_for_client(&client.client_id, state.config.session_ttl_secs);
+    bind_session(&state, &session, peer_ip(connect.as_ref()));
+
+    Ok(Json(ClientBootstrap {
+        client_id: client.client_id,
+        server_public_key: server_key.public_key,
+        api_key: session.api_key,
+        expires_at: session.expires_at.to_rfc3339(),
+        server: server_info(&state),
+    }))
+}
+
+/// All client public keys as `client_id <hex_public_key>` lines (requires admin session)
+pub async fn export_public_keys(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"))],
+        state.keystore.export_public_keys(),
+    )
+}
+
+/// Provision many clients in one step (requires admin session)
+pub async fn register_batch(
+    State(state): State<AppState>,
+    Json(req): Json<BatchRegisterRequest>,
+) -> Result<Json<BatchRegisterResponse>, (StatusCode, String)> {
+    if req.clients.len() > MAX_BATCH_SIZE {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} clients per batch", MAX_BATCH_SIZE),
+        ));
+    }
+
+    let ids: Vec<String> = req.clients.iter().map(|c| c.client_id.clone()).collect();
+    let entries = req.clients
+        .into_iter()
+        .map(|c| (c.client_id, c.client_public_key))
+        .collect();
+
+    let results: Vec<BatchResult> = state.keystore.register_clients_batch(entries)
+        .into_iter()
+        .zip(ids)
+        .map(|(result, client_id)| match result {
+            Ok(entry) => {
+                audit_event(AuditKind::ClientRegistered, &client_id, Outcome::Success);
+                BatchResult {
+                    server_public_key: state.keystore.get_server_key(&entry.client_id).map(|k| k.public_key),
+                    client_id,
+                    registered: true,
+                    error: None,
+                }
+            }
+            Err(e) => BatchResult {
+                client_id,
+                registered: false,
+                server_public_key: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+
+    Ok(Json(BatchRegisterResponse {
+        registered: results.iter().filter(|r| r.registered).count(),
+        results,
+    }))
+}
+
+/// List registered clients, one page at a time
 pub async fn list_clients(
     State(state): State<AppState>,
-) -> Json<ClientListResponse> {
-    let clients = state.keystore.list_clients()
+    Query(query): Query<ListQuery>,
+) -> Json<PaginatedResponse<ClientInfo>> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+
+    let (clients, total) = state.keystore.list_clients_paginated(offset, limit, query.search.as_deref());
+    let items = clients
         .into_iter()
         .map(|c| ClientInfo {
             client_id: c.client_id,
@@ -164,7 +380,48 @@ pub async fn list_clients(
         })
         .collect();
 
-    Json(ClientListResponse { clients })
+    Json(PaginatedResponse { items, total, limit, offset })
+}
+
+fn not_a_client() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Session is not tied to a registered client".to_string())
+}
+
+fn self_response(state: &AppState, client: ClientEntry) -> ClientSelfResponse {
+    ClientSelfResponse {
+        server_public_key: state.keystore.get_server_key(&client.client_id).map(|k| k.public_key),
+        client_id: client.client_id,
+        client_public_key: client.client_public_key,
+        registered_at: client.registered_at,
+        last_seen: client.last_seen,
+        metadata: client.metadata,
+    }
+}
+
+/// Return the registration belonging to the caller's session
+pub async fn get_self(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+) -> Result<Json<ClientSelfResponse>, (StatusCode, String)> {
+    let client = session.client_id.as_deref()
+        .and_then(|client_id| state.keystore.get_client(client_id))
+        .ok_or_else(not_a_client)?;
+
+    Ok(Json(self_response(&state, client)))
+}
+
+/// Update the caller's metadata; fields left out are kept
+pub async fn update_metadata(
+    State(state): State<AppState>,
+    Extension(session): Extension<Session>,
+    Json(metadata): Json<ClientMetadata>,
+) -> Result<Json<ClientSelfResponse>, (StatusCode, String)> {
+    let client_id = session.client_id.ok_or_else(not_a_client)?;
+    let client = state.keystore.update_metadata(&client_id, metadata)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(not_a_client)?;
+
+    Ok(Json(self_response(&state, client)))
 }
 
 /// List all server keys (public keys only)
@@ -0,0 +1,89 @@
+//! Audit trail for security-relevant events
+//!
+//! Every event goes through [`audit_event`], which logs at the fixed
+//! [`AUDIT_TARGET`] with the same `kind`, `subject` and `outcome` fields.
+//! [`file_layer`] additionally copies those events to an append-only file.
+
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Tracing target carrying audit events
+pub const AUDIT_TARGET: &str = "omni::audit";
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    AdminLogin,
+    AdminKeyRotated,
+    ServerKeyRotated,
+    ClientRegistered,
+    ClientLogout,
+    BackupExported,
+    BackupRestored,
+}
+
+impl AuditKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AdminLogin => "admin_login",
+            Self::AdminKeyRotated => "admin_key_rotated",
+            Self::ServerKeyRotated => "server_key_rotated",
+            Self::ClientRegistered => "client_registered",
+            Self::ClientLogout => "client_logout",
+            Self::BackupExported => "backup_exported",
+            Self::BackupRestored => "backup_restored",
+        }
+    }
+}
+
+/// Whether the attempt succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Record a security-relevant event; failures are logged at warn level
+pub fn audit_event(kind: AuditKind, subject: &str, outcome: Outcome) {
+    match outcome {
+        Outcome::Success => tracing::info!(
+            target: AUDIT_TARGET,
+            kind = kind.as_str(),
+            subject,
+            outcome = outcome.as_str(),
+            "audit"
+        ),
+        Outcome::Failure => tracing::warn!(
+            target: AUDIT_TARGET,
+            kind = kind.as_str(),
+            subject,
+            outcome = outcome.as_str(),
+            "audit"
+        ),
+    }
+}
+
+/// Layer appending audit events (and nothing else) to `path`
+pub fn file_layer<S>(path: &str) -> std::io::Result<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(tracing_subscriber::fmt::layer()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_filter(Targets::new().with_target(AUDIT_TARGET, Level::INFO)))
+}
@@ -0,0 +1,79 @@
+//! Tests for audit module
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+    use tracing_subscriber::fmt::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Collects formatted log output in memory
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_audit_event_fields() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(captured.clone())
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            audit_event(AuditKind::ClientRegistered, "device-1", Outcome::Success);
+            audit_event(AuditKind::AdminLogin, "admin", Outcome::Failure);
+        });
+
+        let lines: Vec<String> = captured.text().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(" INFO omni::audit"));
+        assert!(lines[0].contains(r#"kind="client_registered" subject="device-1" outcome="success""#));
+        assert!(lines[1].contains(" WARN omni::audit"));
+        assert!(lines[1].contains(r#"kind="admin_login" subject="admin" outcome="failure""#));
+    }
+
+    #[test]
+    fn test_file_layer_keeps_only_audit_events() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let subscriber = tracing_subscriber::registry()
+            .with(file_layer(path.to_str().unwrap()).unwrap());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("ordinary request log");
+            audit_event(AuditKind::AdminKeyRotated, "admin", Outcome::Success);
+        });
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.contains(r#"kind="admin_key_rotated""#));
+        assert!(!log.contains("ordinary request log"));
+    }
+}
@@ -0,0 +1,21 @@
+//! HTTP response compression
+
+use tower_http::compression::{
+    predicate::{DefaultPredicate, Predicate, SizeAbove},
+    CompressionLayer,
+};
+
+/// Responses smaller than this many bytes are sent as-is by default
+pub const DEFAULT_MIN_SIZE: u16 = 1024;
+
+/// Gzip/deflate layer honoring `Accept-Encoding`.
+///
+/// Only bodies of at least `min_size` bytes are compressed; the usual
+/// exclusions (images, gRPC, event streams) still apply. Bodies are
+/// compressed as they stream, never buffered whole.
+pub fn layer(min_size: u16) -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new()
+        .no_br()
+        .no_zstd()
+        .compress_when(DefaultPredicate::new().and(SizeAbove::new(min_size)))
+}
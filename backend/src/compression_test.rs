@@ -0,0 +1,52 @@
+//! Tests for compression module
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        routing::get,
+        Router,
+    };
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route("/large", get(|| async { axum::Json(vec!["omni-core"; 500]) }))
+            .route("/small", get(|| async { "ok" }))
+            .layer(layer(DEFAULT_MIN_SIZE))
+    }
+
+    fn request(uri: &str, encoding: Option<&str>) -> Request<Body> {
+        let mut req = Request::builder().uri(uri);
+        if let Some(encoding) = encoding {
+            req = req.header(header::ACCEPT_ENCODING, encoding);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_large_response_gzipped_when_accepted() {
+        let res = app().oneshot(request("/large", Some("gzip"))).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+        let values: Vec<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(values.len(), 500);
+        assert!(body.len() < json.len());
+    }
+
+    #[tokio::test]
+    async fn test_uncompressed_without_accept_encoding_or_below_threshold() {
+        let res = app().oneshot(request("/large", None)).await.unwrap();
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+
+        let res = app().oneshot(request("/small", Some("gzip"))).await.unwrap();
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap(), "ok");
+    }
+}
@@ -1,6 +1,69 @@
 //! Server configuration
 
 use serde::Deserialize;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::tls::TlsPaths;
+
+/// Default directory for persisted state
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+/// Placeholder secret used when `SECRET_KEY` is unset
+pub const DEFAULT_SECRET_KEY: &str = "change-me-in-production";
+
+/// Longest session lifetime accepted, ten years
+pub const MAX_SESSION_TTL_SECS: u64 = 10 * 365 * 24 * 3600;
+
+/// Locations of every file the server persists, all under one data root
+#[derive(Debug, Clone, Deserialize)]
+pub struct Paths {
+    pub data_dir: PathBuf,
+}
+
+impl Paths {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Self { data_dir: data_dir.into() }
+    }
+
+    /// Build from the `OMNI_DATA_DIR` value, falling back to [`DEFAULT_DATA_DIR`]
+    pub fn from_var(data_dir: Option<String>) -> Self {
+        Self::new(data_dir.filter(|d| !d.is_empty()).unwrap_or_else(|| DEFAULT_DATA_DIR.to_string()))
+    }
+
+    pub fn server_keys(&self) -> String {
+        self.file("server_keys.yaml")
+    }
+
+    pub fn client_config(&self) -> String {
+        self.file("client_config.yaml")
+    }
+
+    pub fn admin_config(&self) -> String {
+        self.file("admin_config.yaml")
+    }
+
+    /// Default keypair used by the `/keys/*` endpoints
+    pub fn server_key(&self) -> String {
+        self.file("server_key.yaml")
+    }
+
+    /// Append-only session lifecycle log, when `SESSION_EVENT_LOG` is on
+    pub fn session_events(&self) -> String {
+        self.file("session_events.log")
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.data_dir.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self::new(DEFAULT_DATA_DIR)
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -10,8 +73,77 @@ pub struct Config {
     #[serde(default = "default_secret_key")]
     pub secret_key: String,
 
+    /// Lifetime of client sessions
     #[serde(default = "default_session_ttl")]
     pub session_ttl_secs: u64,
+
+    /// Lifetime of admin sessions
+    #[serde(default = "default_admin_session_ttl")]
+    pub admin_session_ttl_secs: u64,
+
+    /// Renew sessions on activity, but never beyond this long after creation
+    #[serde(default)]
+    pub session_max_lifetime_secs: Option<u64>,
+
+    /// Requests per minute per IP allowed on registration endpoints
+    #[serde(default = "default_register_rate")]
+    pub register_rate_per_min: u32,
+
+    /// Serve HTTPS when set
+    #[serde(default)]
+    pub tls: Option<TlsPaths>,
+
+    /// Where keys and registrations are stored
+    #[serde(default)]
+    pub paths: Paths,
+
+    /// Also append audit events to this file
+    #[serde(default)]
+    pub audit_log: Option<String>,
+
+    /// Largest encrypted payload accepted, in decoded bytes
+    #[serde(default = "default_max_ciphertext_len")]
+    pub max_ciphertext_len: usize,
+
+    /// Largest `/admin/restore` body accepted, in bytes
+    #[serde(default = "default_max_backup_len")]
+    pub max_backup_len: usize,
+
+    /// Encrypt server secret keys at rest with a key derived from this
+    #[serde(default)]
+    pub master_key: Option<String>,
+
+    /// Browser origins allowed by CORS; empty allows any
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+
+    /// Only accept a session from the IP that created it
+    #[serde(default)]
+    pub bind_sessions_to_ip: bool,
+
+    /// How long the previous default server key keeps working after rotation
+    #[serde(default = "default_server_key_grace")]
+    pub server_key_grace_secs: u64,
+
+    /// Gzip/deflate responses for clients that accept it
+    #[serde(default = "default_true")]
+    pub compress_responses: bool,
+
+    /// Smallest response body, in bytes, worth compressing
+    #[serde(default = "default_compression_min_size")]
+    pub compression_min_size: u16,
+
+    /// Addresses to listen on; empty means `0.0.0.0:<port>`
+    #[serde(default)]
+    pub bind_addrs: Vec<SocketAddr>,
+
+    /// Append session create/revoke/expire events to `session_events.log`
+    #[serde(default)]
+    pub session_event_log: bool,
+
+    /// Size at which the session event log is rotated
+    #[serde(default = "default_session_event_log_max_bytes")]
+    pub session_event_log_max_bytes: u64,
 }
 
 fn default_port() -> u16 {
@@ -19,26 +151,156 @@ fn default_port() -> u16 {
 }
 
 fn default_secret_key() -> String {
-    "change-me-in-production".to_string()
+    DEFAULT_SECRET_KEY.to_string()
 }
 
 fn default_session_ttl() -> u64 {
     3600 // 1 hour
 }
 
+fn default_admin_session_ttl() -> u64 {
+    24 * 3600 // 24 hours
+}
+
+fn default_server_key_grace() -> u64 {
+    300 // 5 minutes
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_compression_min_size() -> u16 {
+    crate::compression::DEFAULT_MIN_SIZE
+}
+
+fn default_session_event_log_max_bytes() -> u64 {
+    crate::services::DEFAULT_EVENT_LOG_MAX_BYTES
+}
+
+fn default_register_rate() -> u32 {
+    10
+}
+
+fn default_max_ciphertext_len() -> usize {
+    1024 * 1024 // 1 MiB
+}
+
+fn default_max_backup_len() -> usize {
+    64 * 1024 * 1024 // 64 MiB
+}
+
+/// Parse a setting: unset or empty uses `default`, anything unparseable is an error
+pub fn parse_var<T>(name: &str, value: Option<String>, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(default),
+        Some(raw) => raw.parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {} '{}': {}", name, raw, e)),
+    }
+}
+
 impl Config {
     pub fn from_env() -> anyhow::Result<Self> {
-        Ok(Self {
-            port: std::env::var("PORT")
-                .ok()
-                .and_then(|p| p.parse().ok())
-                .unwrap_or_else(default_port),
+        let config = Self {
+            port: parse_var("PORT", std::env::var("PORT").ok(), default_port())?,
             secret_key: std::env::var("SECRET_KEY")
                 .unwrap_or_else(|_| default_secret_key()),
-            session_ttl_secs: std::env::var("SESSION_TTL")
-                .ok()
-                .and_then(|t| t.parse().ok())
-                .unwrap_or_else(default_session_ttl),
-        })
+            session_ttl_secs: parse_var("SESSION_TTL", std::env::var("SESSION_TTL").ok(), default_session_ttl())?,
+            admin_session_ttl_secs: parse_var(
+                "ADMIN_SESSION_TTL",
+                std::env::var("ADMIN_SESSION_TTL").ok(),
+                default_admin_session_ttl(),
+            )?,
+            session_max_lifetime_secs: parse_var(
+                "SESSION_MAX_LIFETIME",
+                std::env::var("SESSION_MAX_LIFETIME").ok(),
+                0,
+            ).map(|secs| (secs > 0).then_some(secs))?,
+            register_rate_per_min: parse_var(
+                "REGISTER_RATE_PER_MIN",
+                std::env::var("REGISTER_RATE_PER_MIN").ok(),
+                default_register_rate(),
+            )?,
+            tls: TlsPaths::from_vars(
+                std::env::var("TLS_CERT_PATH").ok(),
+                std::env::var("TLS_KEY_PATH").ok(),
+            )?,
+            paths: Paths::from_var(std::env::var("OMNI_DATA_DIR").ok()),
+            audit_log: std::env::var("AUDIT_LOG_PATH").ok().filter(|p| !p.is_empty()),
+            max_ciphertext_len: parse_var(
+                "MAX_CIPHERTEXT_LEN",
+                std::env::var("MAX_CIPHERTEXT_LEN").ok(),
+                default_max_ciphertext_len(),
+            )?,
+            max_backup_len: parse_var(
+                "MAX_BACKUP_LEN",
+                std::env::var("MAX_BACKUP_LEN").ok(),
+                default_max_backup_len(),
+            )?,
+            master_key: std::env::var("KEYSTORE_MASTER_KEY").ok().filter(|k| !k.is_empty()),
+            cors_allowed_origins: crate::cors::origins_from_var(std::env::var("CORS_ALLOWED_ORIGINS").ok()),
+            bind_sessions_to_ip: parse_var("SESSION_BIND_IP", std::env::var("SESSION_BIND_IP").ok(), false)?,
+            server_key_grace_secs: parse_var(
+                "SERVER_KEY_GRACE",
+                std::env::var("SERVER_KEY_GRACE").ok(),
+                default_server_key_grace(),
+            )?,
+            compress_responses: parse_var("RESPONSE_COMPRESSION", std::env::var("RESPONSE_COMPRESSION").ok(), true)?,
+            compression_min_size: parse_var(
+                "COMPRESSION_MIN_SIZE",
+                std::env::var("COMPRESSION_MIN_SIZE").ok(),
+                default_compression_min_size(),
+            )?,
+            bind_addrs: crate::listen::parse_bind_addrs(std::env::var("BIND_ADDRS").ok())?,
+            session_event_log: parse_var("SESSION_EVENT_LOG", std::env::var("SESSION_EVENT_LOG").ok(), false)?,
+            session_event_log_max_bytes: parse_var(
+                "SESSION_EVENT_LOG_MAX_BYTES",
+                std::env::var("SESSION_EVENT_LOG_MAX_BYTES").ok(),
+                default_session_event_log_max_bytes(),
+            )?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject values that parse but cannot work
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.port == 0 {
+            anyhow::bail!("PORT must not be 0");
+        }
+        if self.session_ttl_secs == 0 {
+            anyhow::bail!("SESSION_TTL must be greater than 0");
+        }
+        if self.admin_session_ttl_secs == 0 {
+            anyhow::bail!("ADMIN_SESSION_TTL must be greater than 0");
+        }
+        for (name, secs) in [
+            ("SESSION_TTL", Some(self.session_ttl_secs)),
+            ("ADMIN_SESSION_TTL", Some(self.admin_session_ttl_secs)),
+            ("SESSION_MAX_LIFETIME", self.session_max_lifetime_secs),
+        ] {
+            if secs.is_some_and(|secs| secs > MAX_SESSION_TTL_SECS) {
+                anyhow::bail!("{} must be at most {} seconds", name, MAX_SESSION_TTL_SECS);
+            }
+        }
+        Ok(())
+    }
+
+    /// Where to listen: `bind_addrs`, or every IPv4 interface on `port`
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        if self.bind_addrs.is_empty() {
+            vec![SocketAddr::from(([0, 0, 0, 0], self.port))]
+        } else {
+            self.bind_addrs.clone()
+        }
+    }
+
+    /// Whether `SECRET_KEY` was left at its placeholder
+    pub fn uses_default_secret(&self) -> bool {
+        self.secret_key == DEFAULT_SECRET_KEY
     }
 }
@@ -0,0 +1,110 @@
+//! Tests for config module
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{parse_var, Config, Paths, DEFAULT_DATA_DIR, MAX_SESSION_TTL_SECS};
+    use crate::services::{AppState, SessionStore};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_paths_default_to_data_dir() {
+        let paths = Paths::from_var(None);
+
+        assert_eq!(paths.data_dir, Path::new(DEFAULT_DATA_DIR));
+        assert_eq!(Paths::from_var(Some(String::new())).data_dir, paths.data_dir);
+        assert_eq!(
+            Path::new(&paths.server_keys()),
+            Path::new(DEFAULT_DATA_DIR).join("server_keys.yaml")
+        );
+    }
+
+    #[test]
+    fn test_omni_data_dir_relocates_state() {
+        let dir = tempdir().unwrap();
+
+        // The only test that reads OMNI_DATA_DIR, so setting it here is safe
+        std::env::set_var("OMNI_DATA_DIR", dir.path());
+        let config = Config::from_env().unwrap();
+        std::env::remove_var("OMNI_DATA_DIR");
+        assert_eq!(config.paths.data_dir, dir.path());
+
+        let state = AppState::new(config).unwrap();
+        state.keystore.generate_server_key_for_client("device-1").unwrap();
+
+        assert!(dir.path().join("admin_config.yaml").exists());
+        assert!(dir.path().join("server_keys.yaml").exists());
+    }
+
+    #[test]
+    fn test_parse_var_defaults_when_unset() {
+        assert_eq!(parse_var::<u16>("PORT", None, 8080).unwrap(), 8080);
+        assert_eq!(parse_var::<u16>("PORT", Some(" ".to_string()), 8080).unwrap(), 8080);
+        assert_eq!(parse_var::<u16>("PORT", Some("9000".to_string()), 8080).unwrap(), 9000);
+    }
+
+    #[test]
+    fn test_parse_var_rejects_malformed_values() {
+        let err = parse_var::<u16>("PORT", Some("eighty".to_string()), 8080).unwrap_err();
+        assert!(err.to_string().contains("PORT"));
+        assert!(parse_var::<u16>("PORT", Some("70000".to_string()), 8080).is_err());
+        assert!(parse_var::<u64>("SESSION_TTL", Some("-1".to_string()), 3600).is_err());
+    }
+
+    #[test]
+    fn test_validate_ranges() {
+        let dir = tempdir().unwrap();
+        let mut config = (*AppState::for_tests(dir.path()).config).clone();
+        config.port = 8080;
+        assert!(config.validate().is_ok());
+
+        config.port = 0;
+        assert!(config.validate().is_err());
+
+        config.port = 8080;
+        config.session_ttl_secs = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_oversized_ttls() {
+        let dir = tempdir().unwrap();
+        let mut config = (*AppState::for_tests(dir.path()).config).clone();
+        config.port = 8080;
+        config.session_ttl_secs = MAX_SESSION_TTL_SECS;
+        config.admin_session_ttl_secs = MAX_SESSION_TTL_SECS;
+        config.session_max_lifetime_secs = Some(MAX_SESSION_TTL_SECS);
+        assert!(config.validate().is_ok());
+
+        // The largest accepted TTL still yields working sessions
+        let sessions = SessionStore::new().with_sliding_renewal(MAX_SESSION_TTL_SECS);
+        let session = sessions.create(MAX_SESSION_TTL_SECS);
+        sessions.create_admin(MAX_SESSION_TTL_SECS);
+        assert!(sessions.validate(&session.api_key).is_some());
+
+        config.admin_session_ttl_secs = 3600;
+        config.session_max_lifetime_secs = None;
+
+        config.session_ttl_secs = MAX_SESSION_TTL_SECS + 1;
+        assert!(config.validate().is_err());
+
+        config.session_ttl_secs = 3600;
+        config.admin_session_ttl_secs = u64::MAX;
+        assert!(config.validate().is_err());
+
+        config.admin_session_ttl_secs = 3600;
+        config.session_max_lifetime_secs = Some(u64::MAX);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_listen_addrs_default_to_port() {
+        let dir = tempdir().unwrap();
+        let mut config = (*AppState::for_tests(dir.path()).config).clone();
+        config.port = 8080;
+        assert_eq!(config.listen_addrs(), vec!["0.0.0.0:8080".parse().unwrap()]);
+
+        config.bind_addrs = vec!["0.0.0.0:9000".parse().unwrap(), "[::]:9000".parse().unwrap()];
+        assert_eq!(config.listen_addrs(), config.bind_addrs);
+    }
+}
@@ -0,0 +1,41 @@
+//! Cross-origin policy for browser clients
+
+use anyhow::Context;
+use axum::http::HeaderValue;
+use tower_http::cors::{AllowHeaders, AllowMethods, Any, CorsLayer};
+
+/// Split a comma-separated `CORS_ALLOWED_ORIGINS` value; unset or blank means none
+pub fn origins_from_var(origins: Option<String>) -> Vec<String> {
+    origins
+        .unwrap_or_default()
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect()
+}
+
+/// CORS layer allowing exactly `origins`, with credentials.
+///
+/// An empty list allows any origin without credentials, as before origins
+/// were configurable.
+pub fn layer(origins: &[String]) -> anyhow::Result<CorsLayer> {
+    if origins.is_empty() {
+        return Ok(CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any));
+    }
+
+    let origins = origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin)
+            .with_context(|| format!("Invalid origin '{}' in CORS_ALLOWED_ORIGINS", origin)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Wildcards are not allowed alongside credentials, so echo the preflight back
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(true))
+}
@@ -0,0 +1,69 @@
+//! Tests for cors module
+
+#[cfg(test)]
+mod tests {
+    use crate::cors::*;
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn app(origins: &[String]) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(layer(origins).unwrap())
+    }
+
+    async fn allowed_origin(app: Router, origin: &str) -> (StatusCode, Option<String>, bool) {
+        let req = Request::builder()
+            .uri("/")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let headers = res.headers();
+        let allow_origin = headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap().to_string());
+        let credentials = headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS);
+        (res.status(), allow_origin, credentials)
+    }
+
+    #[test]
+    fn test_origins_from_var() {
+        assert!(origins_from_var(None).is_empty());
+        assert!(origins_from_var(Some(" , ".to_string())).is_empty());
+        assert_eq!(
+            origins_from_var(Some("https://a.example, https://b.example/".to_string())),
+            vec!["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unset_allows_any_origin() {
+        let (status, allow_origin, credentials) = allowed_origin(app(&[]), "https://any.example").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(allow_origin.as_deref(), Some("*"));
+        assert!(!credentials);
+    }
+
+    #[tokio::test]
+    async fn test_configured_origins_only() {
+        let origins = origins_from_var(Some("https://app.example".to_string()));
+
+        let (_, allow_origin, credentials) = allowed_origin(app(&origins), "https://app.example").await;
+        assert_eq!(allow_origin.as_deref(), Some("https://app.example"));
+        assert!(credentials);
+
+        let (_, allow_origin, _) = allowed_origin(app(&origins), "https://evil.example").await;
+        assert!(allow_origin.is_none());
+    }
+
+    #[test]
+    fn test_invalid_origin_rejected() {
+        assert!(layer(&["bad\norigin".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! Omni Core backend library
+//!
+//! The `omni-server` binary wires these modules together; integration tests
+//! under `tests/` build the same router in-process.
+
+pub mod api;
+pub mod audit;
+pub mod compression;
+pub mod config;
+pub mod cors;
+pub mod listen;
+pub mod services;
+pub mod shutdown;
+pub mod tls;
+
+#[cfg(test)]
+mod audit_test;
+#[cfg(test)]
+mod compression_test;
+#[cfg(test)]
+mod config_test;
+#[cfg(test)]
+mod cors_test;
+#[cfg(test)]
+mod listen_test;
+#[cfg(test)]
+mod shutdown_test;
+#[cfg(test)]
+mod tls_test;
@@ -0,0 +1,36 @@
+//! Listening sockets for every configured bind address
+
+use anyhow::Context;
+use std::net::{SocketAddr, TcpListener};
+
+/// Parse `BIND_ADDRS`: comma-separated socket addresses such as
+/// `0.0.0.0:8080,[::]:8080`. Unset or blank yields an empty list.
+pub fn parse_bind_addrs(value: Option<String>) -> anyhow::Result<Vec<SocketAddr>> {
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| {
+            addr.parse().map_err(|_| {
+                anyhow::anyhow!("BIND_ADDRS entry '{}' is not a socket address like 0.0.0.0:8080 or [::]:8080", addr)
+            })
+        })
+        .collect()
+}
+
+/// Bind every address before serving any, so one bad or busy address stops
+/// startup instead of leaving the server half up
+pub fn bind_all(addrs: &[SocketAddr]) -> anyhow::Result<Vec<TcpListener>> {
+    addrs
+        .iter()
+        .map(|addr| {
+            let listener = TcpListener::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+            // Tokio and axum-server both adopt std listeners in non-blocking mode
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .collect()
+}
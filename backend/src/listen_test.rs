@@ -0,0 +1,49 @@
+//! Tests for listen module
+
+#[cfg(test)]
+mod tests {
+    use crate::listen::*;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_parse_bind_addrs_dual_stack() {
+        let addrs = parse_bind_addrs(Some("127.0.0.1:8080, [::1]:8080".to_string())).unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs[0].is_ipv4());
+        assert!(addrs[1].is_ipv6());
+        assert_eq!(addrs[1].port(), 8080);
+    }
+
+    #[test]
+    fn test_parse_bind_addrs_unset_or_blank() {
+        assert!(parse_bind_addrs(None).unwrap().is_empty());
+        assert!(parse_bind_addrs(Some(" , ".to_string())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_bind_addrs_rejects_bad_entry() {
+        let err = parse_bind_addrs(Some("0.0.0.0:8080,localhost".to_string())).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("BIND_ADDRS"));
+        assert!(message.contains("localhost"));
+    }
+
+    #[test]
+    fn test_bind_all_builds_one_listener_per_address() {
+        let addrs: Vec<SocketAddr> = vec!["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()];
+        let listeners = bind_all(&addrs).unwrap();
+
+        assert_eq!(listeners.len(), 2);
+        assert!(listeners[0].local_addr().unwrap().is_ipv4());
+        assert!(listeners[1].local_addr().unwrap().is_ipv6());
+    }
+
+    #[test]
+    fn test_bind_all_fails_on_busy_address() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addrs = vec![taken.local_addr().unwrap()];
+
+        let err = bind_all(&addrs).unwrap_err();
+        assert!(err.to_string().contains("Failed to bind"));
+    }
+}
@@ -1,50 +1,105 @@
 //! Omni Core Backend Server
 
 use axum::Router;
+use omni_backend::{api, audit, compression, config, cors, listen, services, shutdown};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
-use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod api;
-mod config;
-mod services;
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load .env if present
     let _ = dotenvy::dotenv();
 
+    // Load config
+    let config = config::Config::from_env()?;
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::try_from_default_env()
             .unwrap_or_else(|_| "info,tower_http=debug".into()))
         .with(tracing_subscriber::fmt::layer())
+        .with(config.audit_log.as_deref().map(audit::file_layer).transpose()?)
         .init();
+    if config.uses_default_secret() {
+        tracing::warn!("SECRET_KEY is the default '{}'; session JWTs use a per-process key until a real secret is set", config::DEFAULT_SECRET_KEY);
+    }
 
-    // Load config
-    let config = config::Config::from_env()?;
-    let port = config.port;
+    let addrs = config.listen_addrs();
+    if config.cors_allowed_origins.is_empty() {
+        tracing::warn!("CORS_ALLOWED_ORIGINS is not set; allowing requests from any origin");
+    }
+    let cors = cors::layer(&config.cors_allowed_origins)?;
+    let compression = config.compress_responses.then(|| compression::layer(config.compression_min_size));
+    let tls = config.tls.clone();
 
     // Create app state
-    let state = services::AppState::new(config);
+    let state = services::AppState::new(config)?;
 
     // Build router
-    let app = Router::new()
-        .nest("/api/v1", api::routes())
-        .layer(CorsLayer::new()
-            .allow_origin(Any)
-            .allow_methods(Any)
-            .allow_headers(Any))
+    let mut app = Router::new()
+        .nest("/api/v1", api::routes(state.clone()))
+        .layer(cors);
+    if let Some(compression) = compression {
+        app = app.layer(compression);
+    }
+    let app = app
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .with_state(state.clone());
+
+    // Bind everything before reporting ready, so a bad address fails fast
+    let listeners = listen::bind_all(&addrs)?;
+
+    // Stores are loaded; let readiness probes through
+    state.mark_ready();
+
+    // Start one server per address; they share the router and stop together
+    let mut servers = tokio::task::JoinSet::new();
+    if let Some(tls) = tls {
+        let rustls_config = tls.load().await?;
+
+        let handle = axum_server::Handle::new();
+        let shutdown_handle = handle.clone();
+        tokio::spawn(async move {
+            shutdown::drain_on(shutdown::shutdown_signal()).await;
+            shutdown_handle.graceful_shutdown(None);
+        });
+
+        for (listener, addr) in listeners.into_iter().zip(&addrs) {
+            tracing::info!("🚀 Omni Core server listening on https://{}", addr);
+            let server = axum_server::from_tcp_rustls(listener, rustls_config.clone())
+                .handle(handle.clone())
+                .serve(app.clone().into_make_service_with_connect_info::<SocketAddr>());
+            servers.spawn(server);
+        }
+    } else {
+        let (stop, stopped) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            shutdown::drain_on(shutdown::shutdown_signal()).await;
+            let _ = stop.send(true);
+        });
+
+        for (listener, addr) in listeners.into_iter().zip(&addrs) {
+            tracing::info!("🚀 Omni Core server listening on {}", addr);
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let mut stopped = stopped.clone();
+            let server = axum::serve(listener, app.clone().into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(async move {
+                    let _ = stopped.wait_for(|stop| *stop).await;
+                });
+            servers.spawn(server.into_future());
+        }
+    }
+
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
 
-    // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    tracing::info!("🚀 Omni Core server listening on {}", addr);
+    // In-flight requests are done; persist what they changed
+    shutdown::flush_state(&state);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    tracing::info!("Server stopped");
 
     Ok(())
 }
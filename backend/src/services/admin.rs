@@ -1,17 +1,23 @@
 //! Admin authentication service
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 
-const ADMIN_CONFIG_FILE: &str = "data/admin_config.yaml";
+use super::crypto::constant_time_eq;
+use super::storage::atomic_write;
 
 /// Admin configuration with generated key
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdminConfig {
-    /// Admin API key (generated on first run)
-    pub admin_key: String,
+    /// Salted hash of the admin key, `sha256$<salt hex>$<digest hex>`
+    #[serde(default)]
+    pub admin_key_hash: String,
+    /// Plaintext key from older versions; hashed and dropped on load
+    #[serde(default, skip_serializing)]
+    admin_key: Option<String>,
     /// When the key was generated
     pub created_at: String,
     /// Server's default public key for display
@@ -19,79 +25,159 @@ pub struct AdminConfig {
 }
 
 impl AdminConfig {
-    /// Generate new admin config with random key
-    pub fn generate(server_public_key: &str) -> Self {
-        use base64::Engine;
-        use rand::RngCore;
-
-        let mut bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut bytes);
-        let admin_key = format!(
-            "admin_{}",
-            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
-        );
-
-        Self {
-            admin_key,
+    /// Generate new admin config with random key.
+    ///
+    /// Returns the config and the plaintext key, which is not kept anywhere.
+    pub fn generate(server_public_key: &str) -> (Self, String) {
+        let admin_key = generate_admin_key();
+        let config = Self {
+            admin_key_hash: hash_admin_key(&admin_key),
+            admin_key: None,
             created_at: chrono::Utc::now().to_rfc3339(),
             server_public_key: server_public_key.to_string(),
-        }
+        };
+        (config, admin_key)
     }
 
     /// Load from file or generate new
-    pub fn load_or_generate(server_public_key: &str) -> Self {
-        if Path::new(ADMIN_CONFIG_FILE).exists() {
-            let content = fs::read_to_string(ADMIN_CONFIG_FILE).unwrap_or_default();
+    pub fn load_or_generate(path: &str, server_public_key: &str) -> Self {
+        if Path::new(path).exists() {
+            let content = fs::read_to_string(path).unwrap_or_default();
             if let Ok(mut config) = serde_yaml::from_str::<AdminConfig>(&content) {
                 // Update server public key if changed
                 config.server_public_key = server_public_key.to_string();
-                return config;
+                if let Some(legacy) = config.admin_key.take() {
+                    config.admin_key_hash = hash_admin_key(&legacy);
+                    match config.save_to(path) {
+                        Ok(()) => tracing::info!("Replaced plaintext admin key with its hash"),
+                        Err(e) => tracing::warn!("Failed to rewrite admin config: {}", e),
+                    }
+                }
+                if !config.admin_key_hash.is_empty() {
+                    return config;
+                }
             }
         }
 
         // Generate new config
-        let config = Self::generate(server_public_key);
-        let _ = config.save();
+        let (config, admin_key) = Self::generate(server_public_key);
+        let _ = config.save_to(path);
         
-        // Log the admin key on first generation
+        // Log the admin key on first generation; only its hash is stored
         tracing::warn!("==============================================");
         tracing::warn!("ADMIN KEY GENERATED (save this securely!):");
-        tracing::warn!("{}", config.admin_key);
+        tracing::warn!("{}", admin_key);
         tracing::warn!("==============================================");
         
         config
     }
 
-    /// Save to file
-    pub fn save(&self) -> std::io::Result<()> {
-        if let Some(parent) = Path::new(ADMIN_CONFIG_FILE).parent() {
-            fs::create_dir_all(parent)?;
-        }
-        let yaml = serde_yaml::to_string(self).map_err(|e| {
-            std::io::Error::new(std::io::ErrorKind::Other, e)
-        })?;
-        fs::write(ADMIN_CONFIG_FILE, yaml)
+    /// Check a candidate key against the stored hash
+    pub fn verify(&self, key: &str) -> bool {
+        let mut parts = self.admin_key_hash.splitn(3, '$');
+        let (Some("sha256"), Some(salt), Some(digest)) = (parts.next(), parts.next(), parts.next()) else {
+            return false;
+        };
+        let (Ok(salt), Ok(digest)) = (hex::decode(salt), hex::decode(digest)) else {
+            return false;
+        };
+        constant_time_eq(&salted_digest(&salt, key), &digest)
+    }
+
+    /// Save to a specific file
+    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
+        let yaml = serde_yaml::to_string(self).map_err(std::io::Error::other)?;
+        atomic_write(path, yaml)
     }
 }
 
+/// Hash a key with a fresh random salt
+pub(crate) fn hash_admin_key(key: &str) -> String {
+    use rand::RngCore;
+
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    format!("sha256${}${}", hex::encode(salt), hex::encode(salted_digest(&salt, key)))
+}
+
+fn salted_digest(salt: &[u8], key: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(key.as_bytes());
+    hasher.finalize().into()
+}
+
+fn generate_admin_key() -> String {
+    use base64::Engine;
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!(
+        "admin_{}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    )
+}
+
 /// Admin authentication manager
 #[derive(Clone)]
 pub struct AdminAuth {
     config: Arc<RwLock<AdminConfig>>,
+    /// Where the config is persisted; `None` keeps it in memory
+    config_path: Option<String>,
 }
 
 impl AdminAuth {
-    pub fn new(server_public_key: &str) -> Self {
-        let config = AdminConfig::load_or_generate(server_public_key);
+    /// Load or create the admin config at `path`
+    pub fn new(path: &str, server_public_key: &str) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(AdminConfig::load_or_generate(path, server_public_key))),
+            config_path: Some(path.to_string()),
+        }
+    }
+
+    /// Use an already loaded config without touching disk
+    pub fn from_config(config: AdminConfig) -> Self {
         Self {
             config: Arc::new(RwLock::new(config)),
+            config_path: None,
         }
     }
 
+    /// Replace the admin key with a fresh one and persist it.
+    ///
+    /// Returns the new key; existing admin sessions are not affected.
+    pub fn rotate_key(&self) -> std::io::Result<String> {
+        let mut config = self.config.write().unwrap();
+        let admin_key = generate_admin_key();
+        let mut rotated = config.clone();
+        rotated.admin_key_hash = hash_admin_key(&admin_key);
+        rotated.created_at = chrono::Utc::now().to_rfc3339();
+
+        if let Some(path) = &self.config_path {
+            rotated.save_to(path)?;
+        }
+        *config = rotated;
+        Ok(admin_key)
+    }
+
     /// Verify admin key
     pub fn verify(&self, key: &str) -> bool {
         let config = self.config.read().unwrap();
-        config.admin_key == key
+        config.verify(key)
+    }
+
+    /// Record a new server public key and persist it
+    pub fn set_server_public_key(&self, server_public_key: &str) -> std::io::Result<()> {
+        let mut config = self.config.write().unwrap();
+        let mut updated = config.clone();
+        updated.server_public_key = server_public_key.to_string();
+
+        if let Some(path) = &self.config_path {
+            updated.save_to(path)?;
+        }
+        *config = updated;
+        Ok(())
     }
 
     /// Get server public key for display
@@ -103,6 +189,6 @@ impl AdminAuth {
     /// Check if admin key exists (for UI display logic)
     pub fn has_admin_key(&self) -> bool {
         let config = self.config.read().unwrap();
-        !config.admin_key.is_empty()
+        !config.admin_key_hash.is_empty()
     }
 }
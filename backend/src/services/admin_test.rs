@@ -0,0 +1,102 @@
+//! Tests for admin module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::admin::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generated_config_verifies_its_key() {
+        let (config, key) = AdminConfig::generate("abc123");
+        let auth = AdminAuth::from_config(config);
+        assert!(auth.verify(&key));
+        assert!(!auth.verify("admin_wrong"));
+        assert!(auth.has_admin_key());
+    }
+
+    #[test]
+    fn test_config_with_cached_server_id_still_loads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("admin_config.yaml");
+        let (config, key) = AdminConfig::generate("abc123");
+        config.save_to(path.to_str().unwrap()).unwrap();
+        // Older versions also stored the server id, now taken from the key ring
+        let mut content = std::fs::read_to_string(&path).unwrap();
+        content.push_str("server_id: srv_e0e77a507412b120\n");
+        std::fs::write(&path, content).unwrap();
+
+        let auth = AdminAuth::new(path.to_str().unwrap(), "abc123");
+
+        assert!(auth.verify(&key));
+    }
+
+    #[test]
+    fn test_rotate_key_replaces_old_key() {
+        let (config, old_key) = AdminConfig::generate("abc123");
+        let auth = AdminAuth::from_config(config);
+        assert!(auth.verify(&old_key));
+
+        let new_key = auth.rotate_key().unwrap();
+
+        assert_ne!(old_key, new_key);
+        assert!(new_key.starts_with("admin_"));
+        assert!(!auth.verify(&old_key));
+        assert!(auth.verify(&new_key));
+    }
+
+    #[test]
+    fn test_admin_config_save_to_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("admin_config.yaml");
+        let (config, key) = AdminConfig::generate("abc123");
+
+        config.save_to(path.to_str().unwrap()).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&key));
+        let loaded: AdminConfig = serde_yaml::from_str(&content).unwrap();
+        assert_eq!(loaded.admin_key_hash, config.admin_key_hash);
+        assert!(loaded.verify(&key));
+    }
+
+    #[test]
+    fn test_hash_is_salted() {
+        let first = hash_admin_key("admin_same");
+        let second = hash_admin_key("admin_same");
+
+        assert_ne!(first, second);
+        assert!(first.starts_with("sha256$"));
+        for hash in [first, second] {
+            let (mut config, _) = AdminConfig::generate("abc123");
+            config.admin_key_hash = hash;
+            assert!(config.verify("admin_same"));
+        }
+    }
+
+    #[test]
+    fn test_wrong_keys_rejected() {
+        let (config, key) = AdminConfig::generate("abc123");
+
+        assert!(!config.verify(&key[..key.len() - 1]));
+        assert!(!config.verify(&format!("{}x", key)));
+        assert!(!config.verify(&key.to_uppercase()));
+        assert!(!config.verify(""));
+    }
+
+    #[test]
+    fn test_legacy_plaintext_key_is_migrated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("admin_config.yaml");
+        std::fs::write(
+            &path,
+            "admin_key: admin_legacy\ncreated_at: 2024-01-01T00:00:00Z\nserver_public_key: abc\n",
+        ).unwrap();
+
+        let auth = AdminAuth::new(path.to_str().unwrap(), "abc123");
+
+        assert!(auth.verify("admin_legacy"));
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("admin_legacy"));
+        assert!(content.contains("admin_key_hash: sha256$"));
+    }
+}
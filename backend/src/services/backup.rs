@@ -0,0 +1,117 @@
+//! Single-file backup and restore of key store state
+
+use argon2::Argon2;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use super::crypto::{CryptoError, EncryptedMessage, SharedSecret};
+use super::keystore::{ClientConfigStore, ServerKeysStore};
+
+/// Current bundle format version
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("Backup is encrypted; a passphrase is required")]
+    PassphraseRequired,
+    #[error("Wrong passphrase or corrupted backup")]
+    InvalidPassphrase,
+    #[error("Backup is too large")]
+    TooLarge,
+    #[error("Unsupported backup version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Key derivation failed")]
+    KeyDerivation,
+    #[error("Backup serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// Everything needed to rebuild the key store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OmniBundle {
+    pub version: u32,
+    pub created_at: String,
+    pub server_keys: ServerKeysStore,
+    pub client_config: ClientConfigStore,
+}
+
+impl OmniBundle {
+    pub fn new(server_keys: ServerKeysStore, client_config: ClientConfigStore) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            server_keys,
+            client_config,
+        }
+    }
+}
+
+/// A bundle as written to disk, optionally sealed with a passphrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum BackupFile {
+    Plain {
+        bundle: OmniBundle,
+    },
+    Sealed {
+        /// Base64-encoded Argon2 salt
+        salt: String,
+        message: EncryptedMessage,
+    },
+}
+
+impl BackupFile {
+    /// Wrap a bundle, encrypting it when a passphrase is given
+    pub fn new(bundle: OmniBundle, passphrase: Option<&str>) -> Result<Self, BackupError> {
+        let Some(passphrase) = passphrase else {
+            return Ok(Self::Plain { bundle });
+        };
+
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+        let json = serde_json::to_vec(&bundle)?;
+        let message = EncryptedMessage::encrypt(&json, &key)
+            .map_err(|_| BackupError::KeyDerivation)?;
+
+        Ok(Self::Sealed {
+            salt: base64::engine::general_purpose::STANDARD.encode(salt),
+            message,
+        })
+    }
+
+    /// Recover the bundle, decrypting with `passphrase` if sealed.
+    /// Sealed bundles over `max_len` bytes are refused.
+    pub fn open(self, passphrase: Option<&str>, max_len: usize) -> Result<OmniBundle, BackupError> {
+        let bundle = match self {
+            Self::Plain { bundle } => bundle,
+            Self::Sealed { salt, message } => {
+                let passphrase = passphrase.ok_or(BackupError::PassphraseRequired)?;
+                let salt = base64::engine::general_purpose::STANDARD
+                    .decode(salt)
+                    .map_err(|_| BackupError::InvalidPassphrase)?;
+                let key = derive_key(passphrase, &salt)?;
+                let json = message.decrypt_with_limit(&key, &[], max_len)
+                    .map_err(|e| match e {
+                        CryptoError::CiphertextTooLarge => BackupError::TooLarge,
+                        _ => BackupError::InvalidPassphrase,
+                    })?;
+                serde_json::from_slice(&json)?
+            }
+        };
+
+        if bundle.version > BUNDLE_VERSION {
+            return Err(BackupError::UnsupportedVersion(bundle.version));
+        }
+        Ok(bundle)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<SharedSecret, BackupError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| BackupError::KeyDerivation)?;
+    Ok(SharedSecret::from(key))
+}
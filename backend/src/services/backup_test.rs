@@ -0,0 +1,88 @@
+//! Tests for backup module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::backup::*;
+    use crate::services::keystore::KeyStoreManager;
+    use tempfile::tempdir;
+
+    fn populated_store() -> KeyStoreManager {
+        let manager = KeyStoreManager::in_memory();
+        for id in ["device-1", "device-2"] {
+            manager.generate_server_key_for_client(id).unwrap();
+        }
+        manager.register_client("device-1", &"a".repeat(64)).unwrap();
+        manager
+    }
+
+    fn assert_same_entries(a: &KeyStoreManager, b: &KeyStoreManager) {
+        let (a_bundle, b_bundle) = (a.export_bundle(), b.export_bundle());
+        assert_eq!(
+            serde_json::to_value(&a_bundle.server_keys).unwrap(),
+            serde_json::to_value(&b_bundle.server_keys).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&a_bundle.client_config).unwrap(),
+            serde_json::to_value(&b_bundle.client_config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let source = populated_store();
+        let dir = tempdir().unwrap();
+        let keys_path = dir.path().join("server_keys.yaml");
+        let clients_path = dir.path().join("client_config.yaml");
+        let target = KeyStoreManager::load_from(
+            keys_path.to_str().unwrap(),
+            clients_path.to_str().unwrap(),
+        ).unwrap();
+
+        target.import_bundle(source.export_bundle()).unwrap();
+
+        assert_same_entries(&source, &target);
+        // Restored secrets still derive the same shared secret
+        assert_eq!(
+            source.derive_shared_secret("device-1"),
+            target.derive_shared_secret("device-1")
+        );
+        // And the restore reached disk
+        let reloaded = KeyStoreManager::load_from(
+            keys_path.to_str().unwrap(),
+            clients_path.to_str().unwrap(),
+        ).unwrap();
+        assert_same_entries(&source, &reloaded);
+    }
+
+    #[test]
+    fn test_sealed_backup_roundtrip() {
+        let source = populated_store();
+
+        let file = BackupFile::new(source.export_bundle(), Some("correct horse")).unwrap();
+        let json = serde_json::to_string(&file).unwrap();
+        assert!(!json.contains(&source.get_server_key("device-1").unwrap().secret_key.0));
+
+        let file: BackupFile = serde_json::from_str(&json).unwrap();
+        let target = KeyStoreManager::in_memory();
+        target.import_bundle(file.open(Some("correct horse"), usize::MAX).unwrap()).unwrap();
+        assert_same_entries(&source, &target);
+    }
+
+    #[test]
+    fn test_sealed_backup_rejects_bad_passphrase() {
+        let file = BackupFile::new(populated_store().export_bundle(), Some("correct horse")).unwrap();
+
+        assert!(matches!(file.clone().open(None, usize::MAX), Err(BackupError::PassphraseRequired)));
+        assert!(matches!(file.clone().open(Some("wrong"), usize::MAX), Err(BackupError::InvalidPassphrase)));
+        assert!(matches!(file.open(Some("correct horse"), 16), Err(BackupError::TooLarge)));
+    }
+
+    #[test]
+    fn test_newer_bundle_version_rejected() {
+        let mut bundle = populated_store().export_bundle();
+        bundle.version = BUNDLE_VERSION + 1;
+
+        let file = BackupFile::new(bundle, None).unwrap();
+        assert!(matches!(file.open(None, usize::MAX), Err(BackupError::UnsupportedVersion(_))));
+    }
+}
@@ -0,0 +1,97 @@
+//! One-time registration challenges
+
+use rand::RngCore;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a client has to complete registration after `/register/init`
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+/// A challenge that has been used, with what its completion handed out
+struct Taken {
+    challenge: String,
+    issued: Instant,
+    /// API key of the session the completion created, and who it went to
+    completion: Option<(String, IpAddr)>,
+}
+
+/// Random challenges handed out by `/register/init`, one per client id.
+///
+/// Each challenge can be taken once; issuing a new one for the same client
+/// replaces the old. Taken challenges are remembered until they expire so a
+/// client can retry a completed registration whose response it never saw.
+#[derive(Clone)]
+pub struct ChallengeStore {
+    pending: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+    taken: Arc<Mutex<HashMap<String, Taken>>>,
+    ttl: Duration,
+}
+
+impl ChallengeStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            pending: Arc::default(),
+            taken: Arc::default(),
+            ttl,
+        }
+    }
+
+    /// Create a fresh hex challenge for `client_id`
+    pub fn issue(&self, client_id: &str) -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let challenge = hex::encode(bytes);
+
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, (_, issued)| now.duration_since(*issued) < self.ttl);
+        pending.insert(client_id.to_string(), (challenge.clone(), now));
+        self.taken.lock().unwrap().retain(|_, taken| now.duration_since(taken.issued) < self.ttl);
+        challenge
+    }
+
+    /// Remove and return the challenge for `client_id`, unless it has expired
+    pub fn take(&self, client_id: &str) -> Option<String> {
+        let (challenge, issued) = self.pending.lock().unwrap().remove(client_id)?;
+        if issued.elapsed() >= self.ttl {
+            return None;
+        }
+        let taken = Taken { challenge: challenge.clone(), issued, completion: None };
+        self.taken.lock().unwrap().insert(client_id.to_string(), taken);
+        Some(challenge)
+    }
+
+    /// The challenge last taken for `client_id`, while it is still within its TTL
+    pub fn last_taken(&self, client_id: &str) -> Option<String> {
+        let taken = self.taken.lock().unwrap();
+        let taken = taken.get(client_id)?;
+        (taken.issued.elapsed() < self.ttl).then(|| taken.challenge.clone())
+    }
+
+    /// Remember the session that completing `client_id`'s last taken challenge
+    /// created, and the address it was handed to
+    pub fn record_completion(&self, client_id: &str, api_key: &str, ip: IpAddr) {
+        if let Some(taken) = self.taken.lock().unwrap().get_mut(client_id) {
+            taken.completion = Some((api_key.to_string(), ip));
+        }
+    }
+
+    /// API key recorded by [`Self::record_completion`], only for a caller at
+    /// the same address and while the challenge is within its TTL
+    pub fn completion_for(&self, client_id: &str, ip: IpAddr) -> Option<String> {
+        let taken = self.taken.lock().unwrap();
+        let taken = taken.get(client_id).filter(|taken| taken.issued.elapsed() < self.ttl)?;
+        match &taken.completion {
+            Some((api_key, completed_by)) if *completed_by == ip => Some(api_key.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ChallengeStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHALLENGE_TTL)
+    }
+}
@@ -0,0 +1,73 @@
+//! Tests for challenge module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::challenge::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_challenge_taken_once() {
+        let store = ChallengeStore::default();
+        let challenge = store.issue("device-1");
+        assert_eq!(challenge.len(), 64);
+
+        assert_eq!(store.take("device-1"), Some(challenge));
+        assert_eq!(store.take("device-1"), None);
+        assert_eq!(store.take("device-2"), None);
+    }
+
+    #[test]
+    fn test_reissue_replaces_challenge() {
+        let store = ChallengeStore::default();
+        let first = store.issue("device-1");
+        let second = store.issue("device-1");
+
+        assert_ne!(first, second);
+        assert_eq!(store.take("device-1"), Some(second));
+    }
+
+    #[test]
+    fn test_expired_challenge_rejected() {
+        let store = ChallengeStore::new(Duration::from_millis(10));
+        store.issue("device-1");
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(store.take("device-1"), None);
+    }
+
+    #[test]
+    fn test_taken_challenge_remembered_for_retry() {
+        let store = ChallengeStore::default();
+        assert_eq!(store.last_taken("device-1"), None);
+
+        let challenge = store.issue("device-1");
+        assert_eq!(store.last_taken("device-1"), None);
+        store.take("device-1");
+
+        assert_eq!(store.last_taken("device-1"), Some(challenge.clone()));
+        // Remembering it does not make it takeable again
+        assert_eq!(store.take("device-1"), None);
+        assert_eq!(store.last_taken("device-1"), Some(challenge));
+    }
+
+    #[test]
+    fn test_completion_only_returned_to_same_address() {
+        let store = ChallengeStore::default();
+        let (ip, other_ip) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        store.record_completion("device-1", "omni_nothing_taken", ip);
+        assert_eq!(store.completion_for("device-1", ip), None);
+
+        store.issue("device-1");
+        store.take("device-1");
+        assert_eq!(store.completion_for("device-1", ip), None);
+        store.record_completion("device-1", "omni_first", ip);
+
+        assert_eq!(store.completion_for("device-1", ip), Some("omni_first".to_string()));
+        assert_eq!(store.completion_for("device-1", other_ip), None);
+
+        // A new challenge starts without a completion
+        store.issue("device-1");
+        store.take("device-1");
+        assert_eq!(store.completion_for("device-1", ip), None);
+    }
+}
@@ -0,0 +1,28 @@
+//! Tests for clock module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::clock::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_mock_clock_moves_only_when_told() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        let shared = clock.clone();
+        shared.advance(Duration::seconds(90));
+        assert_eq!(clock.now(), start + Duration::seconds(90));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+
+    #[test]
+    fn test_system_clock_tracks_utc_now() {
+        let before = Utc::now();
+        let now = SystemClock.now();
+        assert!(now >= before && now <= Utc::now());
+    }
+}
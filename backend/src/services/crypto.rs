@@ -2,12 +2,124 @@
 
 use base64::Engine;
 use chacha20poly1305::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     ChaCha20Poly1305, Nonce,
 };
-use rand::RngCore;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
-use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use subtle::ConstantTimeEq;
+use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret as DhOutput, StaticSecret};
+
+/// Fewest words accepted by [`ServerKeyPair::from_mnemonic`]
+pub const MIN_MNEMONIC_WORDS: usize = 12;
+
+const MNEMONIC_SALT: &[u8] = b"omni-core/server-seed/v1";
+
+/// Declares a 32-byte newtype convertible to and from raw arrays.
+///
+/// Equality is constant-time so secrets can be compared safely.
+macro_rules! bytes32 {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            pub const fn new(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            pub fn to_bytes(self) -> [u8; 32] {
+                self.0
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl AsRef<[u8; 32]> for $name {
+            fn as_ref(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = CryptoError;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, CryptoError> {
+                bytes.try_into().map(Self).map_err(|_| CryptoError::InvalidKey)
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                constant_time_eq(&self.0, &other.0)
+            }
+        }
+
+        impl Eq for $name {}
+    };
+}
+
+bytes32! {
+    /// Symmetric key agreed by X25519 (or derived from one); the only key
+    /// type accepted by the encryption API
+    SharedSecret
+}
+
+bytes32! {
+    /// Raw X25519 public key
+    PublicKeyBytes
+}
+
+bytes32! {
+    /// Raw X25519 secret key
+    SecretKeyBytes
+}
+
+impl std::fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedSecret(..)")
+    }
+}
+
+impl std::fmt::Debug for SecretKeyBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKeyBytes(..)")
+    }
+}
+
+impl std::fmt::Debug for PublicKeyBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PublicKeyBytes({})", hex::encode(self.0))
+    }
+}
 
 /// Server keypair for X25519 key exchange
 #[derive(Clone)]
@@ -18,23 +130,73 @@ pub struct ServerKeyPair {
 
 impl ServerKeyPair {
     pub fn generate() -> Self {
-        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        Self::generate_with_rng(rand::thread_rng())
+    }
+
+    /// Generate from a caller-supplied RNG, e.g. a seeded one in tests
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(rng: R) -> Self {
+        let secret = StaticSecret::random_from_rng(rng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// Rebuild a keypair from a 32-byte seed; the seed is clamped by X25519
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let secret = StaticSecret::from(*seed);
         let public = PublicKey::from(&secret);
         Self { secret, public }
     }
 
-    pub fn public_key_bytes(&self) -> [u8; 32] {
-        self.public.to_bytes()
+    /// Derive a keypair from a BIP39-style word list.
+    ///
+    /// Words are compared case-insensitively and whitespace is normalized,
+    /// so the same phrase always yields the same public key. The seed is
+    /// derived with Argon2 under a fixed salt.
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, CryptoError> {
+        let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+        if words.len() < MIN_MNEMONIC_WORDS {
+            return Err(CryptoError::InvalidMnemonic);
+        }
+
+        let mut seed = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(words.join(" ").as_bytes(), MNEMONIC_SALT, &mut seed)
+            .map_err(|_| CryptoError::InvalidMnemonic)?;
+        Ok(Self::from_seed(&seed))
+    }
+
+    /// Restore a keypair saved with [`ServerKeyPair::secret_bytes`]
+    pub fn from_secret(secret: &SecretKeyBytes) -> Self {
+        Self::from_seed(secret.as_bytes())
+    }
+
+    /// Raw X25519 secret, for persisting; [`ServerKeyPair::from_secret`] restores it
+    pub fn secret_bytes(&self) -> SecretKeyBytes {
+        SecretKeyBytes(self.secret.to_bytes())
+    }
+
+    pub fn public_key_bytes(&self) -> PublicKeyBytes {
+        PublicKeyBytes(self.public.to_bytes())
     }
 
     pub fn public_key_hex(&self) -> String {
         hex::encode(self.public.to_bytes())
     }
 
+    /// Short human-checkable form of the public key
+    pub fn fingerprint(&self) -> String {
+        fingerprint_bytes(&self.public_key_bytes())
+    }
+
+    /// [`derive_server_id`] of this keypair's public key
+    pub fn server_id(&self) -> String {
+        derive_server_id(&self.public_key_hex())
+    }
+
     /// Derive shared secret from client's public key
-    pub fn derive_shared_secret(&self, client_public: &[u8; 32]) -> [u8; 32] {
-        let client_public = PublicKey::from(*client_public);
-        self.secret.diffie_hellman(&client_public).to_bytes()
+    pub fn derive_shared_secret(&self, client_public: &PublicKeyBytes) -> Result<SharedSecret, CryptoError> {
+        let client_public = PublicKey::from(client_public.0);
+        contributory(self.secret.diffie_hellman(&client_public))
     }
 }
 
@@ -51,49 +213,373 @@ impl ClientKeyPair {
         Self { secret, public }
     }
 
-    pub fn public_key_bytes(&self) -> [u8; 32] {
-        self.public.to_bytes()
+    pub fn public_key_bytes(&self) -> PublicKeyBytes {
+        PublicKeyBytes(self.public.to_bytes())
     }
 
     /// Derive shared secret from server's public key
-    pub fn derive_shared_secret(self, server_public: &[u8; 32]) -> [u8; 32] {
-        let server_public = PublicKey::from(*server_public);
-        self.secret.diffie_hellman(&server_public).to_bytes()
+    pub fn derive_shared_secret(self, server_public: &PublicKeyBytes) -> Result<SharedSecret, CryptoError> {
+        let server_public = PublicKey::from(server_public.0);
+        contributory(self.secret.diffie_hellman(&server_public))
     }
 }
 
+/// Reject the all-zero output produced by low-order public keys
+pub(crate) fn contributory(shared: DhOutput) -> Result<SharedSecret, CryptoError> {
+    if shared.was_contributory() {
+        Ok(SharedSecret(shared.to_bytes()))
+    } else {
+        Err(CryptoError::WeakSharedSecret)
+    }
+}
+
+/// Salt used when expanding a Diffie-Hellman output with HKDF
+const HKDF_SALT: &[u8] = b"omni-core/v1";
+
+/// Subkeys expanded from one X25519 shared secret
+#[derive(Clone)]
+pub struct SessionKeys {
+    /// ChaCha20Poly1305 key
+    pub cipher_key: [u8; 32],
+    /// Reserved for message authentication outside the AEAD
+    pub mac_key: [u8; 32],
+}
+
+/// Run a raw shared secret through HKDF-SHA256.
+///
+/// `info` binds the keys to their context (e.g. the client_id), so the same
+/// shared secret yields unrelated keys for different purposes.
+pub fn derive_session_keys(shared_secret: &SharedSecret, info: &[u8]) -> SessionKeys {
+    let hkdf = Hkdf::<Sha256>::new(Some(HKDF_SALT), &shared_secret.0);
+    let mut okm = [0u8; 64];
+    hkdf.expand(info, &mut okm)
+        .expect("64 bytes is a valid HKDF-SHA256 output length");
+
+    let mut keys = SessionKeys {
+        cipher_key: [0u8; 32],
+        mac_key: [0u8; 32],
+    };
+    keys.cipher_key.copy_from_slice(&okm[..32]);
+    keys.mac_key.copy_from_slice(&okm[32..]);
+    keys
+}
+
+/// HKDF info prefix for [`admin_payload_key`]
+const ADMIN_PAYLOAD_INFO: &[u8] = b"omni-core/admin-payload/v1:";
+
+/// Key for encrypted admin responses, bound to one admin session.
+///
+/// `shared_secret` is X25519 between the admin's key and the server key it
+/// logged in against; mixing in the session id means a leaked key from one
+/// session doesn't open another's payloads.
+pub fn admin_payload_key(shared_secret: &SharedSecret, session_id: &str) -> SharedSecret {
+    let info = [ADMIN_PAYLOAD_INFO, session_id.as_bytes()].concat();
+    SharedSecret(derive_session_keys(shared_secret, &info).cipher_key)
+}
+
+/// Mix a fresh ephemeral DH output into the current shared secret.
+///
+/// Both sides run this after exchanging new X25519 public keys, so a session
+/// can rotate keys in place; the old secret cannot be recovered from the new one.
+pub fn ratchet_shared_secret(old: &SharedSecret, new_dh: &SharedSecret) -> SharedSecret {
+    let hkdf = Hkdf::<Sha256>::new(Some(&old.0), &new_dh.0);
+    let mut next = [0u8; 32];
+    hkdf.expand(b"omni-core/rekey/v1", &mut next)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    SharedSecret(next)
+}
+
+/// Advance a symmetric ratchet chain: `HKDF(prev, "omni-ratchet")`.
+///
+/// One-way, so a chain key reveals nothing about the keys before it.
+pub fn ratchet_chain_key(prev: &SharedSecret) -> SharedSecret {
+    expand_label(prev, b"omni-ratchet")
+}
+
+/// Key for the one message at this step of a ratchet chain; kept apart from
+/// the chain key so a leaked message key can't be wound forward
+pub fn ratchet_message_key(chain_key: &SharedSecret) -> SharedSecret {
+    expand_label(chain_key, b"omni-ratchet/message")
+}
+
+fn expand_label(key: &SharedSecret, label: &[u8]) -> SharedSecret {
+    let hkdf = Hkdf::<Sha256>::new(None, &key.0);
+    let mut out = [0u8; 32];
+    hkdf.expand(label, &mut out)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    SharedSecret(out)
+}
+
+/// Proof that a client derived `shared_secret`: HMAC-SHA256 under the secret
+/// over a fixed label and the client's public key
+pub fn key_confirmation_tag(shared_secret: &SharedSecret, client_public: &PublicKeyBytes) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&shared_secret.0)
+        .expect("HMAC accepts any key length");
+    mac.update(b"omni-core/key-confirm/v1");
+    mac.update(&client_public.0);
+    mac.finalize().into_bytes().into()
+}
+
+/// Flag byte for an uncompressed payload in [`EncryptedMessage::encrypt_compressed`]
+pub const PAYLOAD_RAW: u8 = 0;
+/// Flag byte for a deflated payload in [`EncryptedMessage::encrypt_compressed`]
+pub const PAYLOAD_DEFLATE: u8 = 1;
+
+/// Default cap on decoded ciphertext size accepted by decryption (1 MiB)
+pub const DEFAULT_MAX_CIPHERTEXT_LEN: usize = 1024 * 1024;
+
+/// Length of the padded base64 encoding of `len` bytes
+pub fn base64_len(len: usize) -> usize {
+    len.div_ceil(3).saturating_mul(4)
+}
+
+/// Compare secrets without exiting early on the first differing byte.
+/// Lengths are not hidden; compare fixed-size digests when they matter.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Envelope version: ChaCha20-Poly1305 over the plaintext, with the caller's AAD
+pub const ENVELOPE_V1: u8 = 1;
+/// Envelope version: the payload carries a compression flag byte (see
+/// [`PAYLOAD_DEFLATE`]) and the version byte is prepended to the AAD
+pub const ENVELOPE_V2_COMPRESSED: u8 = 2;
+/// Version written by [`EncryptedMessage::encrypt`] and assumed when absent
+pub const CURRENT_ENVELOPE_VERSION: u8 = ENVELOPE_V1;
+
+fn current_envelope_version() -> u8 {
+    CURRENT_ENVELOPE_VERSION
+}
+
 /// Encrypted message envelope
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncryptedMessage {
+    /// Wire format version; payloads from before versioning have none and are version 1
+    #[serde(default = "current_envelope_version")]
+    pub version: u8,
     /// Base64-encoded nonce (12 bytes)
     pub nonce: String,
     /// Base64-encoded ciphertext
     pub ciphertext: String,
 }
 
+/// Options for [`EncryptedMessage::builder`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvelopeBuilder<'a> {
+    compressed: bool,
+    aad: &'a [u8],
+}
+
+impl<'a> EnvelopeBuilder<'a> {
+    /// Deflate the plaintext first; produces an [`ENVELOPE_V2_COMPRESSED`] message
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Authenticate `aad` alongside the plaintext
+    pub fn aad(mut self, aad: &'a [u8]) -> Self {
+        self.aad = aad;
+        self
+    }
+
+    pub fn encrypt(self, plaintext: &[u8], shared_secret: &SharedSecret) -> Result<EncryptedMessage, CryptoError> {
+        if !self.compressed {
+            return EncryptedMessage::encrypt_with_aad(plaintext, shared_secret, self.aad);
+        }
+        let payload = compress_payload(plaintext)?;
+        let aad = versioned_aad(ENVELOPE_V2_COMPRESSED, self.aad);
+        let mut message = EncryptedMessage::encrypt_with_aad(&payload, shared_secret, &aad)?;
+        message.version = ENVELOPE_V2_COMPRESSED;
+        Ok(message)
+    }
+}
+
+/// AAD for versions that bind the version byte, so it cannot be swapped
+fn versioned_aad(version: u8, aad: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(aad.len() + 1);
+    bound.push(version);
+    bound.extend_from_slice(aad);
+    bound
+}
+
+/// Flag byte followed by the deflated plaintext, or the raw plaintext when
+/// deflating would not shrink it
+fn compress_payload(plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut encoder = flate2::write::DeflateEncoder::new(vec![PAYLOAD_DEFLATE], flate2::Compression::default());
+    encoder.write_all(plaintext)?;
+    let compressed = encoder.finish()?;
+
+    if compressed.len() <= plaintext.len() {
+        Ok(compressed)
+    } else {
+        let mut raw = Vec::with_capacity(plaintext.len() + 1);
+        raw.push(PAYLOAD_RAW);
+        raw.extend_from_slice(plaintext);
+        Ok(raw)
+    }
+}
+
+/// Undo [`compress_payload`], refusing to inflate past `max_len` bytes
+fn inflate_payload(payload: &[u8], max_len: usize) -> Result<Vec<u8>, CryptoError> {
+    let (flag, body) = payload.split_first().ok_or(CryptoError::InvalidCiphertext)?;
+
+    match *flag {
+        PAYLOAD_RAW => Ok(body.to_vec()),
+        PAYLOAD_DEFLATE => {
+            let mut inflated = Vec::new();
+            flate2::read::DeflateDecoder::new(body)
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|_| CryptoError::InvalidCiphertext)?;
+            if inflated.len() > max_len {
+                return Err(CryptoError::PayloadTooLarge);
+            }
+            Ok(inflated)
+        }
+        _ => Err(CryptoError::InvalidCiphertext),
+    }
+}
+
 impl EncryptedMessage {
+    /// Start an envelope with non-default options, e.g.
+    /// `EncryptedMessage::builder().compressed(true).aad(b"ctx").encrypt(plaintext, &secret)`
+    pub fn builder<'a>() -> EnvelopeBuilder<'a> {
+        EnvelopeBuilder::default()
+    }
+
     /// Encrypt plaintext using shared secret
-    pub fn encrypt(plaintext: &[u8], shared_secret: &[u8; 32]) -> Result<Self, CryptoError> {
-        let cipher = ChaCha20Poly1305::new_from_slice(shared_secret)
-            .map_err(|_| CryptoError::InvalidKey)?;
+    pub fn encrypt(plaintext: &[u8], shared_secret: &SharedSecret) -> Result<Self, CryptoError> {
+        Self::encrypt_with_aad(plaintext, shared_secret, &[])
+    }
 
+    /// Encrypt plaintext, authenticating `aad` alongside it
+    pub fn encrypt_with_aad(plaintext: &[u8], shared_secret: &SharedSecret, aad: &[u8]) -> Result<Self, CryptoError> {
+        Self::encrypt_with_rng(plaintext, shared_secret, aad, &mut rand::thread_rng())
+    }
+
+    /// Encrypt with a nonce drawn from a caller-supplied RNG
+    pub fn encrypt_with_rng<R: RngCore + CryptoRng>(
+        plaintext: &[u8],
+        shared_secret: &SharedSecret,
+        aad: &[u8],
+        rng: &mut R,
+    ) -> Result<Self, CryptoError> {
         let mut nonce_bytes = [0u8; 12];
-        rand::thread_rng().fill_bytes(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
+        rng.fill_bytes(&mut nonce_bytes);
+        Self::seal(plaintext, shared_secret, nonce_bytes, aad)
+    }
+
+    /// Compact single-string form, `enc:<nonce>:<ciphertext>`, for storing in a scalar field.
+    /// Versions after the first are written as `enc:v<version>:<nonce>:<ciphertext>`.
+    pub fn to_sealed_string(&self) -> String {
+        if self.version == ENVELOPE_V1 {
+            format!("{}{}:{}", SEALED_PREFIX, self.nonce, self.ciphertext)
+        } else {
+            format!("{}v{}:{}:{}", SEALED_PREFIX, self.version, self.nonce, self.ciphertext)
+        }
+    }
+
+    /// Parse [`EncryptedMessage::to_sealed_string`] output; `None` if `s` is not sealed
+    pub fn from_sealed_string(s: &str) -> Option<Self> {
+        let parts: Vec<&str> = s.strip_prefix(SEALED_PREFIX)?.split(':').collect();
+        let (version, nonce, ciphertext) = match parts[..] {
+            [nonce, ciphertext] => (ENVELOPE_V1, nonce, ciphertext),
+            [version, nonce, ciphertext] => (version.strip_prefix('v')?.parse().ok()?, nonce, ciphertext),
+            _ => return None,
+        };
+        Some(Self { version, nonce: nonce.to_string(), ciphertext: ciphertext.to_string() })
+    }
+
+    /// Encrypt with a caller-chosen nonce.
+    ///
+    /// The caller must never use the same nonce twice with the same key;
+    /// doing so breaks both confidentiality and authenticity. Prefer
+    /// [`NonceSequence`] unless the nonce is managed elsewhere.
+    pub fn encrypt_with_nonce(plaintext: &[u8], shared_secret: &SharedSecret, nonce: [u8; 12]) -> Result<Self, CryptoError> {
+        Self::seal(plaintext, shared_secret, nonce, &[])
+    }
 
-        let ciphertext = cipher
-            .encrypt(nonce, plaintext)
-            .map_err(|_| CryptoError::EncryptionFailed)?;
+    fn seal(plaintext: &[u8], shared_secret: &SharedSecret, nonce_bytes: [u8; 12], aad: &[u8]) -> Result<Self, CryptoError> {
+        let ciphertext = aead_encrypt(plaintext, shared_secret, &nonce_bytes, aad)?;
 
         let b64 = base64::engine::general_purpose::STANDARD;
         Ok(Self {
+            version: ENVELOPE_V1,
             nonce: b64.encode(nonce_bytes),
             ciphertext: b64.encode(ciphertext),
         })
     }
 
+    /// Encrypt with a cipher key derived from the shared secret via HKDF
+    pub fn encrypt_hkdf(plaintext: &[u8], shared_secret: &SharedSecret, info: &[u8]) -> Result<Self, CryptoError> {
+        let keys = derive_session_keys(shared_secret, info);
+        Self::encrypt(plaintext, &SharedSecret(keys.cipher_key))
+    }
+
+    /// Decrypt a message produced by [`EncryptedMessage::encrypt_hkdf`]
+    pub fn decrypt_hkdf(&self, shared_secret: &SharedSecret, info: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let keys = derive_session_keys(shared_secret, info);
+        self.decrypt(&SharedSecret(keys.cipher_key))
+    }
+
+    /// Deflate the plaintext, then encrypt it as an [`ENVELOPE_V2_COMPRESSED`] message.
+    ///
+    /// The sealed payload starts with a flag byte: [`PAYLOAD_DEFLATE`] when
+    /// compression helped, [`PAYLOAD_RAW`] when it did not, so incompressible
+    /// input grows by a single byte. [`EncryptedMessage::decrypt`] reads the
+    /// flag and inflates on its own.
+    pub fn encrypt_compressed(plaintext: &[u8], shared_secret: &SharedSecret) -> Result<Self, CryptoError> {
+        Self::builder().compressed(true).encrypt(plaintext, shared_secret)
+    }
+
+    /// Decrypt a compressed message, refusing to inflate past `max_len` bytes.
+    ///
+    /// Also opens version 1 messages whose payload carries the flag byte
+    /// without the envelope saying so.
+    pub fn decrypt_compressed(&self, shared_secret: &SharedSecret, max_len: usize) -> Result<Vec<u8>, CryptoError> {
+        let payload = self.open_payload(shared_secret, &[], DEFAULT_MAX_CIPHERTEXT_LEN)?;
+        inflate_payload(&payload, max_len)
+    }
+
     /// Decrypt ciphertext using shared secret
-    pub fn decrypt(&self, shared_secret: &[u8; 32]) -> Result<Vec<u8>, CryptoError> {
+    pub fn decrypt(&self, shared_secret: &SharedSecret) -> Result<Vec<u8>, CryptoError> {
+        self.decrypt_with_aad(shared_secret, &[])
+    }
+
+    /// Decrypt ciphertext that was sealed with associated data `aad`.
+    ///
+    /// Ciphertexts over [`DEFAULT_MAX_CIPHERTEXT_LEN`] are rejected.
+    pub fn decrypt_with_aad(&self, shared_secret: &SharedSecret, aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.decrypt_with_limit(shared_secret, aad, DEFAULT_MAX_CIPHERTEXT_LEN)
+    }
+
+    /// Like [`EncryptedMessage::decrypt_with_aad`] with an explicit size cap.
+    ///
+    /// `max_ciphertext_len` counts decoded bytes including the 16-byte tag;
+    /// oversized input is refused before it is base64-decoded. Compressed
+    /// envelopes are inflated up to the same limit, so deflating cannot
+    /// carry more plaintext past it.
+    pub fn decrypt_with_limit(&self, shared_secret: &SharedSecret, aad: &[u8], max_ciphertext_len: usize) -> Result<Vec<u8>, CryptoError> {
+        let payload = self.open_payload(shared_secret, aad, max_ciphertext_len)?;
+        match self.version {
+            ENVELOPE_V2_COMPRESSED => inflate_payload(&payload, max_ciphertext_len),
+            _ => Ok(payload),
+        }
+    }
+
+    /// AEAD-decrypt the payload as the envelope's version dictates, without
+    /// undoing compression
+    fn open_payload(&self, shared_secret: &SharedSecret, aad: &[u8], max_ciphertext_len: usize) -> Result<Vec<u8>, CryptoError> {
+        let aad = match self.version {
+            ENVELOPE_V1 => aad.to_vec(),
+            ENVELOPE_V2_COMPRESSED => versioned_aad(self.version, aad),
+            other => return Err(CryptoError::UnsupportedVersion(other)),
+        };
+        if self.ciphertext.len() > base64_len(max_ciphertext_len) {
+            return Err(CryptoError::CiphertextTooLarge);
+        }
+
         let b64 = base64::engine::general_purpose::STANDARD;
 
         let nonce_bytes: [u8; 12] = b64
@@ -105,16 +591,303 @@ impl EncryptedMessage {
         let ciphertext = b64
             .decode(&self.ciphertext)
             .map_err(|_| CryptoError::InvalidCiphertext)?;
+        if ciphertext.len() > max_ciphertext_len {
+            return Err(CryptoError::CiphertextTooLarge);
+        }
+
+        aead_decrypt(&ciphertext, shared_secret, &nonce_bytes, &aad)
+    }
+}
+
+fn aead_encrypt(plaintext: &[u8], shared_secret: &SharedSecret, nonce: &[u8; 12], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    ChaCha20Poly1305::new_from_slice(&shared_secret.0)
+        .map_err(|_| CryptoError::InvalidKey)?
+        .encrypt(Nonce::from_slice(nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| CryptoError::EncryptionFailed)
+}
+
+fn aead_decrypt(ciphertext: &[u8], shared_secret: &SharedSecret, nonce: &[u8; 12], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    ChaCha20Poly1305::new_from_slice(&shared_secret.0)
+        .map_err(|_| CryptoError::InvalidKey)?
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| CryptoError::DecryptionFailed)
+}
+
+/// Encrypt to raw `nonce || ciphertext` bytes, for binary transports
+pub fn seal_raw(plaintext: &[u8], shared_secret: &SharedSecret, aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = aead_encrypt(plaintext, shared_secret, &nonce, aad)?;
+
+    let mut sealed = Vec::with_capacity(nonce.len() + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt [`seal_raw`] output, refusing ciphertext over `max_ciphertext_len` bytes
+pub fn open_raw(sealed: &[u8], shared_secret: &SharedSecret, aad: &[u8], max_ciphertext_len: usize) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < 12 {
+        return Err(CryptoError::InvalidNonce);
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    if ciphertext.len() > max_ciphertext_len {
+        return Err(CryptoError::CiphertextTooLarge);
+    }
+    let nonce: &[u8; 12] = nonce.try_into().map_err(|_| CryptoError::InvalidNonce)?;
+    aead_decrypt(ciphertext, shared_secret, nonce, aad)
+}
+
+/// An [`EncryptedMessage`] signed by its sender with Ed25519.
+///
+/// The signature covers the raw nonce followed by the raw ciphertext and is
+/// checked before any decryption is attempted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedEncryptedMessage {
+    pub message: EncryptedMessage,
+    /// Base64-encoded Ed25519 signature
+    pub signature: String,
+}
+
+impl SignedEncryptedMessage {
+    /// Encrypt with the shared secret, then sign with the sender's key
+    pub fn seal(plaintext: &[u8], shared_secret: &SharedSecret, signing_key: &SigningKey) -> Result<Self, CryptoError> {
+        let message = EncryptedMessage::encrypt(plaintext, shared_secret)?;
+        let signature = signing_key.sign(&Self::signed_bytes(&message)?);
+
+        Ok(Self {
+            message,
+            signature: base64::engine::general_purpose::STANDARD.encode(signature.to_bytes()),
+        })
+    }
+
+    /// Verify the sender's signature, then decrypt
+    pub fn open(&self, shared_secret: &SharedSecret, sender_verify_key: &VerifyingKey) -> Result<Vec<u8>, CryptoError> {
+        let signature: [u8; 64] = base64::engine::general_purpose::STANDARD
+            .decode(&self.signature)
+            .map_err(|_| CryptoError::InvalidSignature)?
+            .try_into()
+            .map_err(|_| CryptoError::InvalidSignature)?;
+
+        sender_verify_key
+            .verify_strict(&Self::signed_bytes(&self.message)?, &Signature::from_bytes(&signature))
+            .map_err(|_| CryptoError::InvalidSignature)?;
+
+        self.message.decrypt(shared_secret)
+    }
+
+    fn signed_bytes(message: &EncryptedMessage) -> Result<Vec<u8>, CryptoError> {
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let mut bytes = b64.decode(&message.nonce).map_err(|_| CryptoError::InvalidNonce)?;
+        bytes.extend(b64.decode(&message.ciphertext).map_err(|_| CryptoError::InvalidCiphertext)?);
+        Ok(bytes)
+    }
+}
+
+/// Counter nonces for high-volume encryption under a single key.
+///
+/// Random nonces risk a birthday collision after roughly 2^48 messages; a
+/// counter never repeats. Each nonce is the 64-bit counter big-endian in the
+/// last 8 bytes, with the first 4 bytes zero. Use one sequence per key and do
+/// not mix it with random-nonce encryption under that key. Test builds also
+/// remember every (key, nonce) pair sealed and panic on a repeat.
+pub struct NonceSequence {
+    next: u64,
+    #[cfg(test)]
+    seen: std::collections::HashSet<([u8; 32], [u8; 12])>,
+}
+
+impl NonceSequence {
+    /// Start a sequence at counter 0
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// Resume a sequence, e.g. after persisting the last counter used
+    pub fn starting_at(counter: u64) -> Self {
+        Self {
+            next: counter,
+            #[cfg(test)]
+            seen: std::collections::HashSet::new(),
+        }
+    }
+
+    /// The counter the next call to [`NonceSequence::encrypt`] will use
+    pub fn next_counter(&self) -> u64 {
+        self.next
+    }
+
+    /// Build the 96-bit nonce for a counter value
+    pub fn nonce_for(counter: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+
+    /// Encrypt with the next counter nonce
+    pub fn encrypt(&mut self, plaintext: &[u8], shared_secret: &SharedSecret) -> Result<EncryptedMessage, CryptoError> {
+        self.encrypt_at(self.next, plaintext, shared_secret)
+    }
+
+    /// Encrypt with an explicit counter supplied by the caller.
+    ///
+    /// Moves the sequence past `counter`, so a later [`NonceSequence::encrypt`]
+    /// cannot reuse it.
+    pub fn encrypt_at(&mut self, counter: u64, plaintext: &[u8], shared_secret: &SharedSecret) -> Result<EncryptedMessage, CryptoError> {
+        let after = counter.checked_add(1).ok_or(CryptoError::NonceExhausted)?;
+        self.next = self.next.max(after);
+        let nonce = Self::nonce_for(counter);
+        #[cfg(test)]
+        {
+            let key_id: [u8; 32] = Sha256::digest(shared_secret.0).into();
+            assert!(self.seen.insert((key_id, nonce)), "nonce reused for key at counter {counter}");
+        }
+        EncryptedMessage::encrypt_with_nonce(plaintext, shared_secret, nonce)
+    }
+}
+
+impl Default for NonceSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Largest plaintext chunk accepted by [`EncryptedStream`] (16 MiB)
+pub const MAX_STREAM_CHUNK_SIZE: usize = 16 * 1024 * 1024;
 
-        let cipher = ChaCha20Poly1305::new_from_slice(shared_secret)
+const CHUNK_FLAG_MORE: u8 = 0;
+const CHUNK_FLAG_FINAL: u8 = 1;
+
+/// Chunked encryption for payloads too large to hold in memory.
+///
+/// Wire format: a random 12-byte base nonce, followed by frames of
+/// `flag (1 byte) || ciphertext length (u32 BE) || ciphertext`. Each chunk is
+/// sealed with the base nonce XORed with its chunk index, and the flag byte is
+/// authenticated as associated data. The last frame carries the final flag, so
+/// a stream that ends without one is reported as truncated.
+pub struct EncryptedStream;
+
+impl EncryptedStream {
+    /// Encrypt everything from `reader` into `writer` in `chunk_size` pieces
+    pub fn encrypt_chunks<R: Read, W: Write>(
+        mut reader: R,
+        mut writer: W,
+        shared_secret: &SharedSecret,
+        chunk_size: usize,
+    ) -> Result<(), CryptoError> {
+        if chunk_size == 0 || chunk_size > MAX_STREAM_CHUNK_SIZE {
+            return Err(CryptoError::InvalidChunkSize);
+        }
+        let cipher = ChaCha20Poly1305::new_from_slice(&shared_secret.0)
             .map_err(|_| CryptoError::InvalidKey)?;
 
-        let nonce = Nonce::from_slice(&nonce_bytes);
+        let mut base_nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut base_nonce);
+        writer.write_all(&base_nonce)?;
+
+        // Read one chunk ahead so the last chunk can be flagged as final
+        let mut current = read_chunk(&mut reader, chunk_size)?;
+        let mut index: u64 = 0;
+        loop {
+            let next = if current.len() < chunk_size {
+                Vec::new()
+            } else {
+                read_chunk(&mut reader, chunk_size)?
+            };
+            let flag = if next.is_empty() { CHUNK_FLAG_FINAL } else { CHUNK_FLAG_MORE };
+
+            let nonce = chunk_nonce(&base_nonce, index);
+            let ciphertext = cipher
+                .encrypt(Nonce::from_slice(&nonce), Payload { msg: &current, aad: &[flag] })
+                .map_err(|_| CryptoError::EncryptionFailed)?;
 
-        cipher
-            .decrypt(nonce, ciphertext.as_ref())
-            .map_err(|_| CryptoError::DecryptionFailed)
+            writer.write_all(&[flag])?;
+            writer.write_all(&(ciphertext.len() as u32).to_be_bytes())?;
+            writer.write_all(&ciphertext)?;
+
+            if flag == CHUNK_FLAG_FINAL {
+                break;
+            }
+            current = next;
+            index += 1;
+        }
+
+        writer.flush()?;
+        Ok(())
     }
+
+    /// Decrypt a stream produced by [`EncryptedStream::encrypt_chunks`]
+    pub fn decrypt_chunks<R: Read, W: Write>(
+        mut reader: R,
+        mut writer: W,
+        shared_secret: &SharedSecret,
+    ) -> Result<(), CryptoError> {
+        let cipher = ChaCha20Poly1305::new_from_slice(&shared_secret.0)
+            .map_err(|_| CryptoError::InvalidKey)?;
+
+        let mut base_nonce = [0u8; 12];
+        read_exact_or_truncated(&mut reader, &mut base_nonce)?;
+
+        let mut index: u64 = 0;
+        loop {
+            let mut header = [0u8; 5];
+            read_exact_or_truncated(&mut reader, &mut header)?;
+            let flag = header[0];
+            if flag != CHUNK_FLAG_MORE && flag != CHUNK_FLAG_FINAL {
+                return Err(CryptoError::InvalidCiphertext);
+            }
+            let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            // Ciphertext is the plaintext chunk plus a 16-byte tag
+            if !(16..=MAX_STREAM_CHUNK_SIZE + 16).contains(&len) {
+                return Err(CryptoError::InvalidCiphertext);
+            }
+
+            let mut ciphertext = vec![0u8; len];
+            read_exact_or_truncated(&mut reader, &mut ciphertext)?;
+
+            let nonce = chunk_nonce(&base_nonce, index);
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &[flag] })
+                .map_err(|_| CryptoError::DecryptionFailed)?;
+            writer.write_all(&plaintext)?;
+
+            if flag == CHUNK_FLAG_FINAL {
+                break;
+            }
+            index += 1;
+        }
+
+        // Nothing may follow the final chunk
+        let mut trailing = [0u8; 1];
+        if reader.read(&mut trailing)? != 0 {
+            return Err(CryptoError::InvalidCiphertext);
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn chunk_nonce(base: &[u8; 12], index: u64) -> [u8; 12] {
+    let mut nonce = *base;
+    for (byte, counter) in nonce[4..].iter_mut().zip(index.to_be_bytes()) {
+        *byte ^= counter;
+    }
+    nonce
+}
+
+/// Fill a buffer of up to `chunk_size` bytes, stopping early only at EOF
+fn read_chunk<R: Read>(reader: &mut R, chunk_size: usize) -> std::io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(chunk_size);
+    reader.take(chunk_size as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn read_exact_or_truncated<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), CryptoError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => CryptoError::TruncatedStream,
+        _ => CryptoError::Io(e),
+    })
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -131,13 +904,170 @@ pub enum CryptoError {
     DecryptionFailed,
     #[error("Invalid public key")]
     InvalidPublicKey,
+    #[error("Invalid chunk size")]
+    InvalidChunkSize,
+    #[error("Encrypted stream is truncated")]
+    TruncatedStream,
+    #[error("Nonce sequence exhausted")]
+    NonceExhausted,
+    #[error("Decompressed payload too large")]
+    PayloadTooLarge,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Mnemonic must have at least 12 words")]
+    InvalidMnemonic,
+    #[error("Public key produces a weak shared secret")]
+    WeakSharedSecret,
+    #[error("Ciphertext too large")]
+    CiphertextTooLarge,
+    #[error("Unsupported envelope version {0}")]
+    UnsupportedVersion(u8),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Fingerprint a hex-encoded public key as `AB12-CD34-EF56-7890`.
+///
+/// The groups are the first 8 bytes of SHA-256 over the raw 32 key bytes.
+pub fn fingerprint(public_key_hex: &str) -> Result<String, CryptoError> {
+    Ok(fingerprint_bytes(&parse_public_key(public_key_hex)?))
+}
+
+fn fingerprint_bytes(public_key: &PublicKeyBytes) -> String {
+    let digest = Sha256::digest(public_key.0);
+    digest[..8]
+        .chunks(2)
+        .map(hex::encode_upper)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Hex digits of the SHA-256 digest kept in a server id
+const SERVER_ID_HEX_LEN: usize = 16;
+
+/// Stable server id, `srv_` plus the first 8 bytes of SHA-256 over the key.
+///
+/// Valid keys hash their raw 32 bytes, so the id doesn't depend on hex case.
+/// Anything else hashes the trimmed string instead of failing, which keeps
+/// the id defined for hand-edited or legacy configs.
+pub fn derive_server_id(public_key_hex: &str) -> String {
+    let digest = match parse_public_key(public_key_hex) {
+        Ok(key) => Sha256::digest(key.0),
+        Err(_) => Sha256::digest(public_key_hex.trim().as_bytes()),
+    };
+    let mut id = hex::encode(digest);
+    id.truncate(SERVER_ID_HEX_LEN);
+    format!("srv_{}", id)
+}
+
+/// Marks a string scalar holding an [`EncryptedMessage`]
+pub const SEALED_PREFIX: &str = "enc:";
+
+/// Associated data for values sealed by [`Encrypted`]
+const FIELD_AAD: &[u8] = b"omni-core/encrypted-field";
+
+thread_local! {
+    static FIELD_KEY: Cell<Option<[u8; 32]>> = const { Cell::new(None) };
+}
+
+static PLAINTEXT_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Puts the previous field key back when a [`Encrypted::with_master_key`] scope ends, even on panic
+struct FieldKeyGuard(Option<[u8; 32]>);
+
+impl Drop for FieldKeyGuard {
+    fn drop(&mut self) {
+        FIELD_KEY.with(|k| k.set(self.0));
+    }
+}
+
+/// A field encrypted when serialized with a master key in scope.
+///
+/// Serde has no way to pass context, so the key is installed for the
+/// current thread with [`Encrypted::with_master_key`]. Without a key the
+/// value is written as plaintext (and a warning is logged once), which keeps
+/// development setups readable. Sealed values are stored as
+/// `enc:<nonce>:<ciphertext>` over the value's JSON encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encrypted<T>(pub T);
+
+impl<T> Encrypted<T> {
+    /// Run `f` with `key` used to seal and open every `Encrypted` field on this thread
+    pub fn with_master_key<R>(key: Option<[u8; 32]>, f: impl FnOnce() -> R) -> R {
+        let _restore = FieldKeyGuard(FIELD_KEY.with(|k| k.replace(key)));
+        f()
+    }
+
+    /// Whether a serialized scalar is a sealed value rather than plaintext
+    pub fn is_sealed(value: &str) -> bool {
+        EncryptedMessage::from_sealed_string(value).is_some()
+    }
+}
+
+impl<T: Serialize> Serialize for Encrypted<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let Some(key) = FIELD_KEY.with(Cell::get) else {
+            if !PLAINTEXT_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!("Writing encrypted fields as plaintext: no master key is set");
+            }
+            return self.0.serialize(serializer);
+        };
+        let json = serde_json::to_vec(&self.0).map_err(S::Error::custom)?;
+        let message = EncryptedMessage::encrypt_with_aad(&json, &SharedSecret::from(key), FIELD_AAD).map_err(S::Error::custom)?;
+        serializer.serialize_str(&message.to_sealed_string())
+    }
+}
+
+impl<'de, T: serde::de::DeserializeOwned> Deserialize<'de> for Encrypted<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let sealed = value.as_str().and_then(EncryptedMessage::from_sealed_string);
+        let Some(message) = sealed else {
+            return serde_json::from_value(value).map(Self).map_err(D::Error::custom);
+        };
+
+        let key = FIELD_KEY.with(Cell::get)
+            .ok_or_else(|| D::Error::custom("field is encrypted but no master key is set"))?;
+        let json = message.decrypt_with_aad(&SharedSecret::from(key), FIELD_AAD)
+            .map_err(|_| D::Error::custom("cannot decrypt field; wrong master key?"))?;
+        serde_json::from_slice(&json).map(Self).map_err(D::Error::custom)
+    }
+}
+
+/// Text encodings accepted for 32-byte public keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEncoding {
+    /// Hex, either case
+    Hex,
+    /// Standard padded base64
+    Base64,
+    /// Bitcoin-alphabet base58
+    Base58,
+}
+
+/// Parse a public key given as hex, base64 or base58, tried in that order
+pub fn parse_public_key(key: &str) -> Result<PublicKeyBytes, CryptoError> {
+    [KeyEncoding::Hex, KeyEncoding::Base64, KeyEncoding::Base58]
+        .into_iter()
+        .find_map(|encoding| parse_public_key_with(key, encoding).ok())
+        .ok_or(CryptoError::InvalidPublicKey)
 }
 
-/// Parse hex-encoded public key
-pub fn parse_public_key(hex_key: &str) -> Result<[u8; 32], CryptoError> {
-    let bytes = hex::decode(hex_key).map_err(|_| CryptoError::InvalidPublicKey)?;
+/// Parse a public key in exactly one encoding
+pub fn parse_public_key_with(key: &str, encoding: KeyEncoding) -> Result<PublicKeyBytes, CryptoError> {
+    let bytes = match encoding {
+        KeyEncoding::Hex => hex::decode(key).ok(),
+        KeyEncoding::Base64 => base64::engine::general_purpose::STANDARD.decode(key).ok(),
+        KeyEncoding::Base58 => bs58::decode(key).into_vec().ok(),
+    };
     bytes
+        .ok_or(CryptoError::InvalidPublicKey)?
         .try_into()
+        .map(PublicKeyBytes)
         .map_err(|_| CryptoError::InvalidPublicKey)
 }
 
@@ -156,8 +1086,8 @@ mod tests {
         let client_public = client.public_key_bytes();
 
         // Both derive the same shared secret
-        let server_shared = server.derive_shared_secret(&client_public);
-        let client_shared = client.derive_shared_secret(&server_public);
+        let server_shared = server.derive_shared_secret(&client_public).unwrap();
+        let client_shared = client.derive_shared_secret(&server_public).unwrap();
 
         assert_eq!(server_shared, client_shared);
 
@@ -3,6 +3,7 @@
 #[cfg(test)]
 mod tests {
     use crate::services::crypto::*;
+    use base64::Engine;
 
     #[test]
     fn test_server_keypair_generation() {
@@ -23,14 +24,14 @@ mod tests {
         use x25519_dalek::{EphemeralSecret, PublicKey};
         let client_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
         let client_public = PublicKey::from(&client_secret);
-        let client_public_bytes = client_public.to_bytes();
+        let client_public_bytes = PublicKeyBytes::from(client_public.to_bytes());
 
         // Server derives shared secret
-        let server_shared = server.derive_shared_secret(&client_public_bytes);
+        let server_shared = server.derive_shared_secret(&client_public_bytes).unwrap();
 
         // Client derives shared secret
-        let server_public_key = PublicKey::from(server_public);
-        let client_shared = client_secret.diffie_hellman(&server_public_key).to_bytes();
+        let server_public_key = PublicKey::from(server_public.to_bytes());
+        let client_shared = SharedSecret::from(client_secret.diffie_hellman(&server_public_key).to_bytes());
 
         // Both should be identical
         assert_eq!(server_shared, client_shared);
@@ -38,7 +39,7 @@ mod tests {
 
     #[test]
     fn test_encrypt_decrypt_roundtrip() {
-        let shared_secret = [42u8; 32];
+        let shared_secret = SharedSecret::from([42u8; 32]);
         let plaintext = b"Hello, encrypted world!";
 
         let encrypted = EncryptedMessage::encrypt(plaintext, &shared_secret).unwrap();
@@ -49,8 +50,8 @@ mod tests {
 
     #[test]
     fn test_decrypt_with_wrong_key_fails() {
-        let shared_secret = [42u8; 32];
-        let wrong_secret = [99u8; 32];
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let wrong_secret = SharedSecret::from([99u8; 32]);
         let plaintext = b"Secret message";
 
         let encrypted = EncryptedMessage::encrypt(plaintext, &shared_secret).unwrap();
@@ -59,6 +60,18 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_encrypt_decrypt_with_aad() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let plaintext = b"Bound message";
+
+        let encrypted = EncryptedMessage::encrypt_with_aad(plaintext, &shared_secret, b"seq-1").unwrap();
+
+        assert_eq!(encrypted.decrypt_with_aad(&shared_secret, b"seq-1").unwrap(), plaintext.to_vec());
+        assert!(encrypted.decrypt_with_aad(&shared_secret, b"seq-2").is_err());
+        assert!(encrypted.decrypt(&shared_secret).is_err());
+    }
+
     #[test]
     fn test_parse_public_key_valid() {
         let valid_hex = "a".repeat(64);
@@ -82,7 +95,7 @@ mod tests {
 
     #[test]
     fn test_encrypted_message_different_nonces() {
-        let shared_secret = [42u8; 32];
+        let shared_secret = SharedSecret::from([42u8; 32]);
         let plaintext = b"Same message";
 
         let encrypted1 = EncryptedMessage::encrypt(plaintext, &shared_secret).unwrap();
@@ -93,4 +106,618 @@ mod tests {
         // Ciphertexts should be different due to different nonces
         assert_ne!(encrypted1.ciphertext, encrypted2.ciphertext);
     }
+
+    #[test]
+    fn test_stream_multi_chunk_roundtrip() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let plaintext: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut encrypted = Vec::new();
+        EncryptedStream::encrypt_chunks(&plaintext[..], &mut encrypted, &shared_secret, 1024).unwrap();
+
+        let mut decrypted = Vec::new();
+        EncryptedStream::decrypt_chunks(&encrypted[..], &mut decrypted, &shared_secret).unwrap();
+
+        assert_eq!(plaintext, decrypted);
+    }
+
+    #[test]
+    fn test_stream_exact_multiple_of_chunk_size() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let plaintext = vec![7u8; 4096];
+
+        let mut encrypted = Vec::new();
+        EncryptedStream::encrypt_chunks(&plaintext[..], &mut encrypted, &shared_secret, 1024).unwrap();
+
+        let mut decrypted = Vec::new();
+        EncryptedStream::decrypt_chunks(&encrypted[..], &mut decrypted, &shared_secret).unwrap();
+
+        assert_eq!(plaintext, decrypted);
+    }
+
+    #[test]
+    fn test_stream_empty_roundtrip() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+
+        let mut encrypted = Vec::new();
+        EncryptedStream::encrypt_chunks(&[][..], &mut encrypted, &shared_secret, 1024).unwrap();
+
+        let mut decrypted = Vec::new();
+        EncryptedStream::decrypt_chunks(&encrypted[..], &mut decrypted, &shared_secret).unwrap();
+
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn test_stream_truncated_fails() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let plaintext = vec![1u8; 5000];
+
+        let mut encrypted = Vec::new();
+        EncryptedStream::encrypt_chunks(&plaintext[..], &mut encrypted, &shared_secret, 1024).unwrap();
+
+        // Drop the final frame: 12-byte nonce + 5 frames of (5 + 1024 + 16)
+        let cut = 12 + 4 * (5 + 1024 + 16);
+        let mut decrypted = Vec::new();
+        let result = EncryptedStream::decrypt_chunks(&encrypted[..cut], &mut decrypted, &shared_secret);
+
+        assert!(matches!(result, Err(CryptoError::TruncatedStream)));
+    }
+
+    #[test]
+    fn test_derive_session_keys_matches_on_both_ends() {
+        let server = ServerKeyPair::generate();
+        let client = ClientKeyPair::generate();
+        let client_public = client.public_key_bytes();
+
+        let server_keys = derive_session_keys(&server.derive_shared_secret(&client_public).unwrap(), b"client-1");
+        let client_keys = derive_session_keys(&client.derive_shared_secret(&server.public_key_bytes()).unwrap(), b"client-1");
+
+        assert_eq!(server_keys.cipher_key, client_keys.cipher_key);
+        assert_eq!(server_keys.mac_key, client_keys.mac_key);
+        assert_ne!(server_keys.cipher_key, server_keys.mac_key);
+    }
+
+    #[test]
+    fn test_derive_session_keys_vector() {
+        // HKDF-SHA256(salt = "omni-core/v1", ikm = [42; 32], info = "client-1"), L = 64
+        let keys = derive_session_keys(&SharedSecret::from([42u8; 32]), b"client-1");
+
+        assert_eq!(hex::encode(keys.cipher_key), "b6e49df3c65f93aedde5ee16a52a35ab9390ae610b588a06fef9ed7aeaab8a12");
+        assert_eq!(hex::encode(keys.mac_key), "72234111c194cbe34d3045ac7325a5c2a55bd4a71fd1b71bf6d5e7be43335ff0");
+    }
+
+    #[test]
+    fn test_derive_session_keys_binds_info() {
+        let secret = SharedSecret::from([42u8; 32]);
+        let a = derive_session_keys(&secret, b"client-1");
+        let b = derive_session_keys(&secret, b"client-2");
+
+        assert_ne!(a.cipher_key, b.cipher_key);
+        // The derived key differs from the raw DH output
+        assert_ne!(a.cipher_key, secret.to_bytes());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_hkdf_roundtrip() {
+        let shared_secret = SharedSecret::from([42u8; 32]);
+        let plaintext = b"Derived key message";
+
+        let encrypted = EncryptedMessage::encrypt_hkdf(plaintext, &shared_secret, b"client-1").unwrap();
+
+        assert_eq!(encrypted.decrypt_hkdf(&shared_secret, b"client-1").unwrap(), plaintext.to_vec());
+        assert!(encrypted.decrypt_hkdf(&shared_secret, b"client-2").is_err());
+        // Raw-key messages keep working alongside
+        assert!(encrypted.decrypt(&shared_secret).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_known_key() {
+        let key = "aa".repeat(32);
+
+        assert_eq!(fingerprint(&key).unwrap(), "E0E7-7A50-7412-B120");
+        // Case of the hex input does not matter
+        assert_eq!(fingerprint(&key.to_uppercase()).unwrap(), "E0E7-7A50-7412-B120");
+    }
+
+    #[test]
+    fn test_fingerprint_matches_keypair_method() {
+        let keypair = ServerKeyPair::generate();
+        let fp = keypair.fingerprint();
+
+        assert_eq!(fp, fingerprint(&keypair.public_key_hex()).unwrap());
+        assert_eq!(fp.len(), 19);
+        assert_eq!(fp.matches('-').count(), 3);
+    }
+
+    #[test]
+    fn test_fingerprint_malformed_hex() {
+        assert!(matches!(fingerprint("not-hex"), Err(CryptoError::InvalidPublicKey)));
+        assert!(matches!(fingerprint("abcd"), Err(CryptoError::InvalidPublicKey)));
+    }
+
+    #[test]
+    fn test_server_id_is_stable_hash_of_key() {
+        let key = "aa".repeat(32);
+
+        // Same digest as the fingerprint, in a fixed-length id form
+        assert_eq!(derive_server_id(&key), "srv_e0e77a507412b120");
+        assert_eq!(derive_server_id(&key.to_uppercase()), derive_server_id(&key));
+
+        let other = ServerKeyPair::generate().public_key_hex();
+        assert_ne!(derive_server_id(&other), derive_server_id(&key));
+        assert_eq!(derive_server_id(&other), derive_server_id(&other));
+    }
+
+    #[test]
+    fn test_server_id_tolerates_malformed_keys() {
+        for key in ["", "abc", "not-hex", "zz".repeat(32).as_str()] {
+            let id = derive_server_id(key);
+            assert!(id.starts_with("srv_"));
+            assert_eq!(id.len(), 20);
+        }
+        assert_ne!(derive_server_id("abc"), derive_server_id("abd"));
+    }
+
+    #[test]
+    fn test_encrypt_with_nonce_uses_given_nonce() {
+        let shared_secret = SharedSecret::from([9u8; 32]);
+        let nonce = [7u8; 12];
+
+        let a = EncryptedMessage::encrypt_with_nonce(b"hello", &shared_secret, nonce).unwrap();
+        let b = EncryptedMessage::encrypt_with_nonce(b"hello", &shared_secret, nonce).unwrap();
+
+        let b64 = base64::engine::general_purpose::STANDARD;
+        assert_eq!(a.nonce, b64.encode(nonce));
+        // Same key and nonce is deterministic, which is why reuse is dangerous
+        assert_eq!(a.ciphertext, b.ciphertext);
+        assert_eq!(a.decrypt(&shared_secret).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_nonce_sequence_counts_up() {
+        let shared_secret = SharedSecret::from([3u8; 32]);
+        let mut sequence = NonceSequence::starting_at(41);
+
+        let first = sequence.encrypt(b"one", &shared_secret).unwrap();
+        let second = sequence.encrypt(b"two", &shared_secret).unwrap();
+
+        let b64 = base64::engine::general_purpose::STANDARD;
+        assert_eq!(first.nonce, b64.encode(NonceSequence::nonce_for(41)));
+        assert_eq!(second.nonce, b64.encode(NonceSequence::nonce_for(42)));
+        assert_eq!(sequence.next_counter(), 43);
+        assert_eq!(second.decrypt(&shared_secret).unwrap(), b"two");
+    }
+
+    #[test]
+    fn test_nonce_sequence_exhausted() {
+        let mut sequence = NonceSequence::starting_at(u64::MAX);
+
+        assert!(matches!(
+            sequence.encrypt(b"last", &SharedSecret::from([1u8; 32])),
+            Err(CryptoError::NonceExhausted)
+        ));
+    }
+
+    #[test]
+    fn test_nonce_sequence_skips_explicit_counters() {
+        let shared_secret = SharedSecret::from([3u8; 32]);
+        let mut sequence = NonceSequence::new();
+
+        let explicit = sequence.encrypt_at(5, b"explicit", &shared_secret).unwrap();
+        assert_eq!(sequence.next_counter(), 6);
+        let next = sequence.encrypt(b"next", &shared_secret).unwrap();
+        assert_ne!(explicit.nonce, next.nonce);
+
+        // An earlier counter does not move the sequence back
+        sequence.encrypt_at(2, b"earlier", &shared_secret).unwrap();
+        assert_eq!(sequence.next_counter(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "nonce reused")]
+    fn test_nonce_sequence_panics_on_reuse() {
+        let shared_secret = SharedSecret::from([5u8; 32]);
+        let mut sequence = NonceSequence::new();
+
+        sequence.encrypt_at(7, b"first", &shared_secret).unwrap();
+        sequence.encrypt_at(7, b"second", &shared_secret).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_roundtrip_shrinks_repetitive_json() {
+        let shared_secret = SharedSecret::from([4u8; 32]);
+        let payload = r#"{"client_id":"device-1","status":"ok"}"#.repeat(200);
+
+        let compressed = EncryptedMessage::encrypt_compressed(payload.as_bytes(), &shared_secret).unwrap();
+        let plain = EncryptedMessage::encrypt(payload.as_bytes(), &shared_secret).unwrap();
+
+        assert!(compressed.ciphertext.len() < plain.ciphertext.len() / 4);
+        assert_eq!(compressed.decrypt(&shared_secret).unwrap(), payload.as_bytes());
+        assert_eq!(compressed.decrypt_compressed(&shared_secret, DEFAULT_MAX_CIPHERTEXT_LEN).unwrap(), payload.as_bytes());
+    }
+
+    #[test]
+    fn test_compressed_incompressible_grows_by_one_byte() {
+        let shared_secret = SharedSecret::from([4u8; 32]);
+        let mut payload = vec![0u8; 4096];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut payload);
+
+        let encrypted = EncryptedMessage::encrypt_compressed(&payload, &shared_secret).unwrap();
+        let sealed = base64::engine::general_purpose::STANDARD.decode(&encrypted.ciphertext).unwrap();
+
+        // Flag byte plus the 16-byte tag
+        assert_eq!(sealed.len(), payload.len() + 1 + 16);
+        assert_eq!(encrypted.decrypt(&shared_secret).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_decrypt_compressed_reads_unversioned_flag_byte() {
+        let shared_secret = SharedSecret::from([4u8; 32]);
+        let mut payload = vec![PAYLOAD_RAW];
+        payload.extend_from_slice(b"flagged");
+
+        // A version 1 envelope around a flagged payload
+        let encrypted = EncryptedMessage::encrypt(&payload, &shared_secret).unwrap();
+
+        assert_eq!(encrypted.decrypt_compressed(&shared_secret, DEFAULT_MAX_CIPHERTEXT_LEN).unwrap(), b"flagged");
+        assert_eq!(encrypted.decrypt(&shared_secret).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_compressed_inflate_cap() {
+        let shared_secret = SharedSecret::from([4u8; 32]);
+        let bomb = vec![0u8; 1024 * 1024];
+
+        let encrypted = EncryptedMessage::encrypt_compressed(&bomb, &shared_secret).unwrap();
+
+        assert!(matches!(
+            encrypted.decrypt_compressed(&shared_secret, 64 * 1024),
+            Err(CryptoError::PayloadTooLarge)
+        ));
+        // The ciphertext cap also bounds what a compressed envelope inflates to
+        assert!(matches!(
+            encrypted.decrypt_with_limit(&shared_secret, &[], 64 * 1024),
+            Err(CryptoError::PayloadTooLarge)
+        ));
+        assert_eq!(encrypted.decrypt(&shared_secret).unwrap().len(), bomb.len());
+    }
+
+    #[test]
+    fn test_signed_message_roundtrip() {
+        let shared_secret = SharedSecret::from([6u8; 32]);
+        let sender = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+
+        let sealed = SignedEncryptedMessage::seal(b"hello peer", &shared_secret, &sender).unwrap();
+
+        assert_eq!(sealed.open(&shared_secret, &sender.verifying_key()).unwrap(), b"hello peer");
+    }
+
+    #[test]
+    fn test_signed_message_tampered_ciphertext_rejected() {
+        let shared_secret = SharedSecret::from([6u8; 32]);
+        let sender = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+        let mut sealed = SignedEncryptedMessage::seal(b"hello peer", &shared_secret, &sender).unwrap();
+
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let mut ciphertext = b64.decode(&sealed.message.ciphertext).unwrap();
+        ciphertext[0] ^= 0x01;
+        sealed.message.ciphertext = b64.encode(ciphertext);
+
+        assert!(matches!(
+            sealed.open(&shared_secret, &sender.verifying_key()),
+            Err(CryptoError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_signed_message_wrong_signer_rejected() {
+        let shared_secret = SharedSecret::from([6u8; 32]);
+        let sender = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+        let impostor = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+
+        let sealed = SignedEncryptedMessage::seal(b"hello peer", &shared_secret, &impostor).unwrap();
+
+        assert!(matches!(
+            sealed.open(&shared_secret, &sender.verifying_key()),
+            Err(CryptoError::InvalidSignature)
+        ));
+    }
+
+    const PHRASE: &str = "abandon ability able about above absent absorb abstract absurd abuse access accident";
+
+    #[test]
+    fn test_from_seed_is_deterministic() {
+        let a = ServerKeyPair::from_seed(&[42u8; 32]);
+        let b = ServerKeyPair::from_seed(&[42u8; 32]);
+        let c = ServerKeyPair::from_seed(&[43u8; 32]);
+
+        assert_eq!(a.public_key_hex(), b.public_key_hex());
+        assert_ne!(a.public_key_hex(), c.public_key_hex());
+    }
+
+    #[test]
+    fn test_from_mnemonic_is_deterministic() {
+        let a = ServerKeyPair::from_mnemonic(PHRASE).unwrap();
+        // Case and spacing do not change the key
+        let b = ServerKeyPair::from_mnemonic(&format!("  {}\n", PHRASE.to_uppercase().replace(' ', "   "))).unwrap();
+        let other = ServerKeyPair::from_mnemonic(&PHRASE.replace("accident", "account")).unwrap();
+
+        assert_eq!(a.public_key_hex(), b.public_key_hex());
+        assert_ne!(a.public_key_hex(), other.public_key_hex());
+    }
+
+    #[test]
+    fn test_from_mnemonic_rejects_short_phrase() {
+        assert!(matches!(
+            ServerKeyPair::from_mnemonic("too short"),
+            Err(CryptoError::InvalidMnemonic)
+        ));
+    }
+
+    #[test]
+    fn test_low_order_public_key_rejected() {
+        let server = ServerKeyPair::generate();
+        // The identity point: every scalar maps it to the all-zero output
+        let low_order = PublicKeyBytes::from([0u8; 32]);
+        // u = 1, a point of order 4
+        let mut order_four = [0u8; 32];
+        order_four[0] = 1;
+        let order_four = PublicKeyBytes::from(order_four);
+
+        assert!(matches!(server.derive_shared_secret(&low_order), Err(CryptoError::WeakSharedSecret)));
+        assert!(matches!(server.derive_shared_secret(&order_four), Err(CryptoError::WeakSharedSecret)));
+        assert!(matches!(
+            ClientKeyPair::generate().derive_shared_secret(&low_order),
+            Err(CryptoError::WeakSharedSecret)
+        ));
+        assert!(server.derive_shared_secret(&ServerKeyPair::generate().public_key_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_decrypt_size_limit() {
+        let shared_secret = SharedSecret::from([8u8; 32]);
+        // 1008 bytes of plaintext seal to exactly 1024 bytes with the tag
+        let encrypted = EncryptedMessage::encrypt(&[0u8; 1008], &shared_secret).unwrap();
+
+        assert!(encrypted.decrypt_with_limit(&shared_secret, &[], 1024).is_ok());
+        assert!(matches!(
+            encrypted.decrypt_with_limit(&shared_secret, &[], 1023),
+            Err(CryptoError::CiphertextTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_oversized_ciphertext_rejected_before_decoding() {
+        let encrypted = EncryptedMessage {
+            version: ENVELOPE_V1,
+            nonce: String::new(),
+            // Not valid base64, so only the length check can reject it
+            ciphertext: "!".repeat(base64_len(DEFAULT_MAX_CIPHERTEXT_LEN) + 1),
+        };
+
+        assert!(matches!(encrypted.decrypt(&SharedSecret::from([0u8; 32])), Err(CryptoError::CiphertextTooLarge)));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"same", b"same"));
+        assert!(!constant_time_eq(b"same", b"sane"));
+        assert!(!constant_time_eq(b"same", b"same!"));
+        assert!(!constant_time_eq(b"", b"x"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_parse_public_key_encodings() {
+        let key = ServerKeyPair::generate().public_key_bytes();
+        let hex_lower = hex::encode(key);
+        let base64 = base64::engine::general_purpose::STANDARD.encode(key);
+        let base58 = bs58::encode(key).into_string();
+
+        for encoded in [hex_lower.clone(), hex_lower.to_uppercase(), base64.clone(), base58.clone()] {
+            assert_eq!(parse_public_key(&encoded).unwrap(), key);
+        }
+
+        assert_eq!(parse_public_key_with(&base64, KeyEncoding::Base64).unwrap(), key);
+        assert_eq!(parse_public_key_with(&base58, KeyEncoding::Base58).unwrap(), key);
+        // Strict parsing refuses other encodings
+        assert!(parse_public_key_with(&base64, KeyEncoding::Hex).is_err());
+        assert!(parse_public_key_with(&hex_lower, KeyEncoding::Base58).is_err());
+
+        assert!(parse_public_key("not a key!").is_err());
+        assert!(parse_public_key(&bs58::encode([1u8; 31]).into_string()).is_err());
+    }
+
+    #[test]
+    fn test_seeded_rng_is_reproducible() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+
+        let a = ServerKeyPair::generate_with_rng(ChaCha20Rng::seed_from_u64(7));
+        let b = ServerKeyPair::generate_with_rng(ChaCha20Rng::seed_from_u64(7));
+        let c = ServerKeyPair::generate_with_rng(ChaCha20Rng::seed_from_u64(8));
+        assert_eq!(a.public_key_hex(), b.public_key_hex());
+        assert_ne!(a.public_key_hex(), c.public_key_hex());
+
+        let key = SharedSecret::from([3u8; 32]);
+        let first = EncryptedMessage::encrypt_with_rng(b"hello", &key, b"", &mut ChaCha20Rng::seed_from_u64(7)).unwrap();
+        let second = EncryptedMessage::encrypt_with_rng(b"hello", &key, b"", &mut ChaCha20Rng::seed_from_u64(7)).unwrap();
+        assert_eq!(first.nonce, second.nonce);
+        assert_eq!(first.ciphertext, second.ciphertext);
+        assert_eq!(first.decrypt(&key).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_ratchet_shared_secret() {
+        let server = ServerKeyPair::generate();
+        let client = ServerKeyPair::generate();
+        let initial = server.derive_shared_secret(&client.public_key_bytes()).unwrap();
+
+        // Each side contributes a fresh ephemeral key for the rekey
+        let server_eph = ServerKeyPair::generate();
+        let client_eph = ServerKeyPair::generate();
+        let server_next = ratchet_shared_secret(&initial, &server_eph.derive_shared_secret(&client_eph.public_key_bytes()).unwrap());
+        let client_next = ratchet_shared_secret(&initial, &client_eph.derive_shared_secret(&server_eph.public_key_bytes()).unwrap());
+        assert_eq!(server_next, client_next);
+        assert_ne!(server_next, initial);
+
+        let before = EncryptedMessage::encrypt(b"before", &initial).unwrap();
+        let after = EncryptedMessage::encrypt(b"after", &server_next).unwrap();
+        assert_eq!(before.decrypt(&initial).unwrap(), b"before");
+        assert_eq!(after.decrypt(&client_next).unwrap(), b"after");
+        assert!(before.decrypt(&client_next).is_err());
+        assert!(after.decrypt(&initial).is_err());
+    }
+
+    #[test]
+    fn test_raw_seal_roundtrip_and_limits() {
+        let secret = SharedSecret::from([7u8; 32]);
+        let sealed = seal_raw(b"hello", &secret, b"aad").unwrap();
+        assert_eq!(sealed.len(), 12 + 5 + 16);
+
+        assert_eq!(open_raw(&sealed, &secret, b"aad", 1024).unwrap(), b"hello");
+        assert!(matches!(open_raw(&sealed, &secret, b"other", 1024), Err(CryptoError::DecryptionFailed)));
+        assert!(matches!(open_raw(&sealed, &secret, b"aad", 8), Err(CryptoError::CiphertextTooLarge)));
+        assert!(matches!(open_raw(&sealed[..8], &secret, b"aad", 1024), Err(CryptoError::InvalidNonce)));
+    }
+
+    #[test]
+    fn test_key_newtype_conversions() {
+        let raw = [5u8; 32];
+        let secret = SharedSecret::from(raw);
+        assert_eq!(secret.as_bytes(), &raw);
+        assert_eq!(<[u8; 32]>::from(secret), raw);
+        assert_eq!(AsRef::<[u8]>::as_ref(&secret), &raw[..]);
+
+        let public = PublicKeyBytes::try_from(&raw[..]).unwrap();
+        assert_eq!(public.to_bytes(), raw);
+        assert!(matches!(PublicKeyBytes::try_from(&raw[..31]), Err(CryptoError::InvalidKey)));
+
+        // Secrets never print their bytes
+        assert_eq!(format!("{:?}", secret), "SharedSecret(..)");
+        assert_eq!(format!("{:?}", SecretKeyBytes::from(raw)), "SecretKeyBytes(..)");
+        assert!(format!("{:?}", public).contains(&hex::encode(raw)));
+    }
+
+    #[test]
+    fn test_secret_bytes_restore_keypair() {
+        let keypair = ServerKeyPair::generate();
+        let restored = ServerKeyPair::from_secret(&keypair.secret_bytes());
+        assert_eq!(restored.public_key_bytes(), keypair.public_key_bytes());
+    }
+
+    #[test]
+    fn test_encrypt_takes_shared_secret() {
+        let server = ServerKeyPair::generate();
+        let client = ClientKeyPair::generate();
+        let client_public: PublicKeyBytes = client.public_key_bytes();
+        let shared: SharedSecret = client.derive_shared_secret(&server.public_key_bytes()).unwrap();
+
+        let encrypted = EncryptedMessage::encrypt(b"typed", &shared).unwrap();
+        let server_shared = server.derive_shared_secret(&client_public).unwrap();
+        assert_eq!(encrypted.decrypt(&server_shared).unwrap(), b"typed");
+    }
+
+    #[test]
+    fn test_envelope_without_version_is_v1() {
+        let shared_secret = SharedSecret::from([11u8; 32]);
+        let encrypted = EncryptedMessage::encrypt(b"legacy", &shared_secret).unwrap();
+        assert_eq!(encrypted.version, CURRENT_ENVELOPE_VERSION);
+
+        let legacy = serde_json::json!({ "nonce": encrypted.nonce, "ciphertext": encrypted.ciphertext });
+        let parsed: EncryptedMessage = serde_json::from_value(legacy).unwrap();
+        assert_eq!(parsed.version, ENVELOPE_V1);
+        assert_eq!(parsed.decrypt(&shared_secret).unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn test_builder_versions_decode() {
+        let shared_secret = SharedSecret::from([12u8; 32]);
+        let plaintext = "repeat ".repeat(200);
+
+        let plain = EncryptedMessage::builder().aad(b"ctx").encrypt(plaintext.as_bytes(), &shared_secret).unwrap();
+        assert_eq!(plain.version, ENVELOPE_V1);
+        assert_eq!(plain.decrypt_with_aad(&shared_secret, b"ctx").unwrap(), plaintext.as_bytes());
+
+        let compressed = EncryptedMessage::builder()
+            .compressed(true)
+            .aad(b"ctx")
+            .encrypt(plaintext.as_bytes(), &shared_secret)
+            .unwrap();
+        assert_eq!(compressed.version, ENVELOPE_V2_COMPRESSED);
+        assert!(compressed.ciphertext.len() < plain.ciphertext.len());
+        assert_eq!(compressed.decrypt_with_aad(&shared_secret, b"ctx").unwrap(), plaintext.as_bytes());
+        assert!(compressed.decrypt_with_aad(&shared_secret, b"other").is_err());
+
+        // The version is authenticated: relabelling it breaks decryption
+        let mut relabelled = compressed.clone();
+        relabelled.version = ENVELOPE_V1;
+        assert!(matches!(relabelled.decrypt_with_aad(&shared_secret, b"ctx"), Err(CryptoError::DecryptionFailed)));
+
+        // Sealed strings keep the version
+        let sealed = compressed.to_sealed_string();
+        assert!(sealed.starts_with("enc:v2:"));
+        let reopened = EncryptedMessage::from_sealed_string(&sealed).unwrap();
+        assert_eq!(reopened.decrypt_with_aad(&shared_secret, b"ctx").unwrap(), plaintext.as_bytes());
+        assert_eq!(EncryptedMessage::from_sealed_string(&plain.to_sealed_string()).unwrap().version, ENVELOPE_V1);
+    }
+
+    #[test]
+    fn test_unknown_envelope_version_rejected() {
+        let shared_secret = SharedSecret::from([13u8; 32]);
+        let mut encrypted = EncryptedMessage::encrypt(b"future", &shared_secret).unwrap();
+        encrypted.version = 9;
+
+        assert!(matches!(encrypted.decrypt(&shared_secret), Err(CryptoError::UnsupportedVersion(9))));
+        assert!(matches!(encrypted.decrypt_compressed(&shared_secret, DEFAULT_MAX_CIPHERTEXT_LEN), Err(CryptoError::UnsupportedVersion(9))));
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Secrets {
+        name: String,
+        token: Encrypted<String>,
+    }
+
+    #[test]
+    fn test_encrypted_field_round_trip_with_key() {
+        let key = Some([9u8; 32]);
+        let secrets = Secrets { name: "device-1".to_string(), token: Encrypted("hunter2".to_string()) };
+
+        let yaml = Encrypted::<String>::with_master_key(key, || serde_yaml::to_string(&secrets).unwrap());
+        assert!(yaml.contains("name: device-1"));
+        assert!(yaml.contains("token: enc:"));
+        assert!(!yaml.contains("hunter2"));
+
+        let loaded: Secrets = Encrypted::<String>::with_master_key(key, || serde_yaml::from_str(&yaml).unwrap());
+        assert_eq!(loaded, secrets);
+
+        // Sealed fields need the same key to load
+        assert!(serde_yaml::from_str::<Secrets>(&yaml).is_err());
+        let wrong = Encrypted::<String>::with_master_key(Some([1u8; 32]), || serde_yaml::from_str::<Secrets>(&yaml));
+        assert!(wrong.is_err());
+    }
+
+    #[test]
+    fn test_encrypted_field_plaintext_without_key() {
+        let secrets = Secrets { name: "device-1".to_string(), token: Encrypted("hunter2".to_string()) };
+
+        let yaml = serde_yaml::to_string(&secrets).unwrap();
+        assert!(yaml.contains("token: hunter2"));
+
+        // Plaintext still loads once a key is configured
+        let loaded: Secrets = Encrypted::<String>::with_master_key(Some([9u8; 32]), || serde_yaml::from_str(&yaml).unwrap());
+        assert_eq!(loaded, secrets);
+    }
+
+    #[test]
+    fn test_encrypted_field_key_scope_ends_on_panic() {
+        let result = std::panic::catch_unwind(|| {
+            Encrypted::<String>::with_master_key(Some([9u8; 32]), || panic!("serializer failed"))
+        });
+        assert!(result.is_err());
+
+        let yaml = serde_yaml::to_string(&Encrypted("hunter2".to_string())).unwrap();
+        assert!(yaml.contains("hunter2"));
+    }
 }
@@ -1,14 +1,204 @@
 //! YAML-based key storage for server and client keys
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use x25519_dalek::{PublicKey, StaticSecret};
 
-const SERVER_KEYS_FILE: &str = "data/server_keys.yaml";
-const CLIENT_CONFIG_FILE: &str = "data/client_config.yaml";
+use super::backup::OmniBundle;
+use super::crypto::{contributory, parse_public_key, Encrypted, SharedSecret};
+use super::storage::atomic_write;
+use crate::config::Paths;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyStoreError {
+    #[error("Key store IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Key store serialization error: {0}")]
+    Serialization(#[from] serde_yaml::Error),
+    #[error("No server key for client '{0}'")]
+    MissingServerKey(String),
+    #[error("Client '{0}' already registered")]
+    AlreadyRegistered(String),
+    #[error("Client '{0}' has a registration in progress")]
+    RegistrationPending(String),
+    #[error("Invalid public key for client '{0}'")]
+    InvalidPublicKey(String),
+    #[error("Secret key for client '{0}' is encrypted; a master key is required")]
+    MasterKeyRequired(String),
+    #[error("Cannot decrypt secret key for client '{0}'; wrong master key?")]
+    InvalidMasterKey(String),
+    #[error("Stored public key for client '{0}' does not match its secret key")]
+    KeyMismatch(String),
+    #[error("Client '{0}' exists in both key stores with different keys")]
+    MergeConflict(String),
+    #[error("Stored secret key for '{0}' is not 32 hex-encoded bytes")]
+    InvalidSecretKey(String),
+    #[error("Malformed public key line {0}; expected '<client_id> <hex_public_key>'")]
+    MalformedKeyLine(usize),
+}
+
+/// What [`KeyStoreManager::merge_from`] does when both stores hold a client id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave our entry in place
+    KeepExisting,
+    /// Take whichever side was registered (or keyed) more recently
+    PreferNewer,
+    /// Abort the whole merge without changing anything
+    Fail,
+}
+
+/// How far ahead of our clock a merged timestamp may be before it is clamped
+pub const DEFAULT_MAX_CLOCK_SKEW_HOURS: i64 = 24;
+
+/// Outcome of [`KeyStoreManager::merge_from`], counted per client id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Only present in the other store
+    pub added: usize,
+    /// Conflicts resolved in favour of the other store
+    pub replaced: usize,
+    /// Identical in both, or conflicts resolved in favour of ours
+    pub skipped: usize,
+    /// Ids present in both stores with different keys
+    pub conflicted: usize,
+    /// Malformed in the other store and left out
+    pub rejected: usize,
+}
+
+/// Outcome of [`KeyStoreManager::import_bundle`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub clients: usize,
+    pub server_keys: usize,
+    /// Ids left out because their entries would not load as a working client
+    pub rejected: Vec<String>,
+}
+
+/// Reset timestamps in `bundle` that lie more than `skew` ahead of now
+fn clamp_future_timestamps(bundle: &mut OmniBundle, skew: chrono::Duration) {
+    let now = chrono::Utc::now();
+    let clamp = |id: &str, field: &str, stamp: &mut String| {
+        let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(stamp) else {
+            return;
+        };
+        if parsed > now + skew {
+            tracing::warn!("Clamping {} for '{}' from {} to now: too far in the future", field, id, stamp);
+            *stamp = now.to_rfc3339();
+        }
+    };
+
+    for (id, client) in bundle.client_config.clients.iter_mut() {
+        clamp(id, "registered_at", &mut client.registered_at);
+        if let Some(last_seen) = client.last_seen.as_mut() {
+            clamp(id, "last_seen", last_seen);
+        }
+    }
+    for (id, key) in bundle.server_keys.keys.iter_mut() {
+        clamp(id, "created_at", &mut key.created_at);
+    }
+}
+
+/// Why an incoming entry for `id` can't be merged, if it can't
+fn invalid_entry(id: &str, key: Option<&ServerKeyEntry>, client: Option<&ClientEntry>) -> Option<&'static str> {
+    let Some(key) = key else {
+        return Some("client has no server key");
+    };
+    if key.client_id != id {
+        return Some("server key is filed under another id");
+    }
+    if key.validate().is_err() {
+        return Some("server public key does not match its secret");
+    }
+    if let Some(client) = client {
+        if client.client_id != id || client.server_key_id != id {
+            return Some("client is filed under another id");
+        }
+        let key_bytes = hex::decode(&client.client_public_key).ok().filter(|bytes| bytes.len() == 32);
+        if key_bytes.is_none() {
+            return Some("client public key is not 32 hex-encoded bytes");
+        }
+    }
+    None
+}
+
+/// Remove entries from `bundle` that would not load as a working client, so
+/// a bad or hostile source can't plant them; returns the ids that were dropped
+fn drop_invalid_entries(bundle: &mut OmniBundle) -> Vec<String> {
+    let mut ids: Vec<String> = bundle.server_keys.keys.keys()
+        .chain(bundle.client_config.clients.keys())
+        .cloned()
+        .collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut dropped = Vec::new();
+    for id in ids {
+        let reason = invalid_entry(&id, bundle.server_keys.get_key(&id), bundle.client_config.get_client(&id));
+        if let Some(reason) = reason {
+            tracing::warn!("Dropping '{}' from incoming keys: {}", id, reason);
+            bundle.server_keys.keys.remove(&id);
+            bundle.client_config.clients.remove(&id);
+            dropped.push(id);
+        }
+    }
+    dropped
+}
+
+/// Least time between writes made only to record `last_seen`; touches in
+/// between are held in memory and reach disk with the next due touch, prune
+/// or flush
+const LAST_SEEN_WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `last_seen` times not yet folded into the client config.
+///
+/// Lock this before the client config when taking both, never after.
+#[derive(Default)]
+struct LastSeenCache {
+    /// When a touch last wrote the client config
+    written: Option<Instant>,
+    /// client id -> last_seen newer than the stored one
+    pending: HashMap<String, String>,
+}
+
+impl LastSeenCache {
+    /// Move pending times into `store`, for clients it still holds
+    fn apply(&mut self, store: &mut ClientConfigStore) {
+        for (client_id, seen) in self.pending.drain() {
+            if let Some(client) = store.clients.get_mut(&client_id) {
+                client.last_seen = Some(seen);
+            }
+        }
+    }
+
+    /// `entry` with its pending last_seen, if any
+    fn overlay(&self, mut entry: ClientEntry) -> ClientEntry {
+        if let Some(seen) = self.pending.get(&entry.client_id) {
+            entry.last_seen = Some(seen.clone());
+        }
+        entry
+    }
+}
+
+/// Lowercase client public key -> client id
+fn index_by_public_key(store: &ClientConfigStore) -> HashMap<String, String> {
+    store.clients.values()
+        .map(|c| (c.client_public_key.to_ascii_lowercase(), c.client_id.clone()))
+        .collect()
+}
+
+/// Derive the key store master key from the configured secret
+pub fn derive_master_key(secret: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"omni-core/master-key/v1");
+    hasher.update(secret.as_bytes());
+    hasher.finalize().into()
+}
 
 /// A server keypair for a specific client
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,8 +208,8 @@ pub struct ServerKeyEntry {
     #[serde(skip_serializing, skip_deserializing)]
     #[serde(default)]
     secret_key_bytes: Option<[u8; 32]>,
-    /// Hex-encoded secret key (stored encrypted in production)
-    pub secret_key: String,
+    /// Hex-encoded secret key; on disk it is sealed when a master key is set
+    pub secret_key: Encrypted<String>,
     pub created_at: String,
 }
 
@@ -32,7 +222,7 @@ impl ServerKeyEntry {
             client_id: client_id.to_string(),
             public_key: hex::encode(public.to_bytes()),
             secret_key_bytes: Some(secret.to_bytes()),
-            secret_key: hex::encode(secret.to_bytes()),
+            secret_key: Encrypted(hex::encode(secret.to_bytes())),
             created_at: chrono::Utc::now().to_rfc3339(),
         }
     }
@@ -42,17 +232,28 @@ impl ServerKeyEntry {
             Some(StaticSecret::from(bytes))
         } else {
             // Parse from hex string
-            let bytes = hex::decode(&self.secret_key).ok()?;
+            let bytes = hex::decode(&self.secret_key.0).ok()?;
             let arr: [u8; 32] = bytes.try_into().ok()?;
             Some(StaticSecret::from(arr))
         }
     }
 
-    pub fn derive_shared_secret(&self, client_public_hex: &str) -> Option<[u8; 32]> {
+    /// Check that `public_key` is the one derived from the stored secret
+    pub fn validate(&self) -> Result<(), KeyStoreError> {
+        let secret = self.get_secret()
+            .ok_or_else(|| KeyStoreError::KeyMismatch(self.client_id.clone()))?;
+        if hex::encode(PublicKey::from(&secret).as_bytes()) != self.public_key.to_lowercase() {
+            return Err(KeyStoreError::KeyMismatch(self.client_id.clone()));
+        }
+        Ok(())
+    }
+
+    /// Shared secret with a client, or `None` for malformed or low-order keys
+    pub fn derive_shared_secret(&self, client_public_hex: &str) -> Option<SharedSecret> {
         let secret = self.get_secret()?;
         let client_bytes: [u8; 32] = hex::decode(client_public_hex).ok()?.try_into().ok()?;
         let client_public = PublicKey::from(client_bytes);
-        Some(secret.diffie_hellman(&client_public).to_bytes())
+        contributory(secret.diffie_hellman(&client_public)).ok()
     }
 }
 
@@ -64,6 +265,99 @@ pub struct ClientEntry {
     pub server_key_id: String,
     pub registered_at: String,
     pub last_seen: Option<String>,
+    #[serde(default, skip_serializing_if = "ClientMetadata::is_empty")]
+    pub metadata: ClientMetadata,
+}
+
+/// Descriptive fields a client may set about itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+impl ClientMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Overwrite the fields set in `update`, keeping the rest
+    pub fn merge(&mut self, update: ClientMetadata) {
+        if update.name.is_some() {
+            self.name = update.name;
+        }
+        if update.device_type.is_some() {
+            self.device_type = update.device_type;
+        }
+        if update.tags.is_some() {
+            self.tags = update.tags;
+        }
+    }
+}
+
+/// Read the string-keyed map under `field` in a YAML file, one entry at a time.
+///
+/// An unreadable or non-YAML file is an error; entries that fail to
+/// deserialize are left out and described in the returned list instead,
+/// unless `fatal` returns an error for them.
+fn load_entries<T: DeserializeOwned>(
+    path: &str,
+    field: &str,
+    fatal: impl Fn(&str, &serde_yaml::Value) -> Option<KeyStoreError>,
+) -> Result<(HashMap<String, T>, Vec<String>), KeyStoreError> {
+    let mut entries = HashMap::new();
+    let mut issues = Vec::new();
+    if !Path::new(path).exists() {
+        return Ok((entries, issues));
+    }
+
+    let content = fs::read_to_string(path)?;
+    let root: serde_yaml::Value = serde_yaml::from_str(&content)?;
+    let Some(map) = root.get(field).and_then(serde_yaml::Value::as_mapping) else {
+        if !root.is_null() {
+            issues.push(format!("missing '{}' map", field));
+        }
+        return Ok((entries, issues));
+    };
+
+    for (key, value) in map {
+        let Some(id) = key.as_str() else {
+            issues.push(format!("non-string key {:?}", key));
+            continue;
+        };
+        match serde_yaml::from_value(value.clone()) {
+            Ok(entry) => {
+                entries.insert(id.to_string(), entry);
+            }
+            Err(e) => match fatal(id, value) {
+                Some(error) => return Err(error),
+                None => issues.push(format!("entry '{}': {}", id, e)),
+            },
+        }
+    }
+    Ok((entries, issues))
+}
+
+/// Error for a sealed `secret` that can't be opened with the master key in
+/// scope, or `None` if the secret isn't the problem.
+///
+/// Such entries must fail the load rather than be skipped, or the next save
+/// would drop the key for good.
+pub(super) fn sealed_secret_error(name: &str, secret: Option<&serde_yaml::Value>, has_master_key: bool) -> Option<KeyStoreError> {
+    let secret = secret?;
+    if !secret.as_str().is_some_and(Encrypted::<String>::is_sealed)
+        || serde_yaml::from_value::<Encrypted<String>>(secret.clone()).is_ok()
+    {
+        return None;
+    }
+    Some(match has_master_key {
+        true => KeyStoreError::InvalidMasterKey(name.to_string()),
+        false => KeyStoreError::MasterKeyRequired(name.to_string()),
+    })
 }
 
 /// Server keys storage (server_keys.yaml)
@@ -73,31 +367,32 @@ pub struct ServerKeysStore {
 }
 
 impl ServerKeysStore {
-    pub fn load() -> Self {
-        Self::load_from(SERVER_KEYS_FILE)
+    pub fn load_from(path: &str) -> Result<Self, KeyStoreError> {
+        Ok(Self::load_from_reporting(path)?.0)
     }
 
-    pub fn load_from(path: &str) -> Self {
-        if Path::new(path).exists() {
-            let content = fs::read_to_string(path).unwrap_or_default();
-            serde_yaml::from_str(&content).unwrap_or_default()
-        } else {
-            Self::default()
-        }
+    /// Load, skipping entries that fail to parse and describing each one
+    pub fn load_from_reporting(path: &str) -> Result<(Self, Vec<String>), KeyStoreError> {
+        Self::load_sealed(path, None)
     }
 
-    pub fn save(&self) -> std::io::Result<()> {
-        self.save_to(SERVER_KEYS_FILE)
+    /// Load, opening secret keys sealed under `master_key`. A sealed key that
+    /// can't be opened fails the load.
+    fn load_sealed(path: &str, master_key: Option<&[u8; 32]>) -> Result<(Self, Vec<String>), KeyStoreError> {
+        let fatal = |id: &str, value: &serde_yaml::Value| sealed_secret_error(id, value.get("secret_key"), master_key.is_some());
+        let (keys, issues) = Encrypted::<String>::with_master_key(master_key.copied(), || load_entries(path, "keys", fatal))?;
+        Ok((Self { keys }, issues))
     }
 
-    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
-        if let Some(parent) = Path::new(path).parent() {
-            fs::create_dir_all(parent)?;
-        }
-        let yaml = serde_yaml::to_string(self).map_err(|e| {
-            std::io::Error::new(std::io::ErrorKind::Other, e)
-        })?;
-        fs::write(path, yaml)
+    pub fn save_to(&self, path: &str) -> Result<(), KeyStoreError> {
+        let yaml = serde_yaml::to_string(self)?;
+        atomic_write(path, yaml)?;
+        Ok(())
+    }
+
+    /// Save with every secret key encrypted under `master_key`, if one is given
+    fn save_sealed(&self, path: &str, master_key: Option<&[u8; 32]>) -> Result<(), KeyStoreError> {
+        Encrypted::<String>::with_master_key(master_key.copied(), || self.save_to(path))
     }
 
     pub fn add_key(&mut self, entry: ServerKeyEntry) {
@@ -116,31 +411,20 @@ pub struct ClientConfigStore {
 }
 
 impl ClientConfigStore {
-    pub fn load() -> Self {
-        Self::load_from(CLIENT_CONFIG_FILE)
-    }
-
-    pub fn load_from(path: &str) -> Self {
-        if Path::new(path).exists() {
-            let content = fs::read_to_string(path).unwrap_or_default();
-            serde_yaml::from_str(&content).unwrap_or_default()
-        } else {
-            Self::default()
-        }
+    pub fn load_from(path: &str) -> Result<Self, KeyStoreError> {
+        Ok(Self::load_from_reporting(path)?.0)
     }
 
-    pub fn save(&self) -> std::io::Result<()> {
-        self.save_to(CLIENT_CONFIG_FILE)
+    /// Load, skipping entries that fail to parse and describing each one
+    pub fn load_from_reporting(path: &str) -> Result<(Self, Vec<String>), KeyStoreError> {
+        let (clients, issues) = load_entries(path, "clients", |_, _| None)?;
+        Ok((Self { clients }, issues))
     }
 
-    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
-        if let Some(parent) = Path::new(path).parent() {
-            fs::create_dir_all(parent)?;
-        }
-        let yaml = serde_yaml::to_string(self).map_err(|e| {
-            std::io::Error::new(std::io::ErrorKind::Other, e)
-        })?;
-        fs::write(path, yaml)
+    pub fn save_to(&self, path: &str) -> Result<(), KeyStoreError> {
+        let yaml = serde_yaml::to_string(self)?;
+        atomic_write(path, yaml)?;
+        Ok(())
     }
 
     pub fn add_client(&mut self, entry: ClientEntry) {
@@ -157,25 +441,155 @@ impl ClientConfigStore {
 pub struct KeyStoreManager {
     server_keys: Arc<RwLock<ServerKeysStore>>,
     client_config: Arc<RwLock<ClientConfigStore>>,
+    /// Client ids by public key, rebuilt whenever the client config changes
+    by_public_key: Arc<RwLock<HashMap<String, String>>>,
+    /// Recent activity, written out in batches
+    last_seen: Arc<Mutex<LastSeenCache>>,
+    /// Backing files; `None` keeps everything in memory
+    paths: Option<StorePaths>,
+    /// Encrypts secret keys in server_keys.yaml when set
+    master_key: Option<Arc<[u8; 32]>>,
+    /// Entries dropped while loading, with the file they came from
+    load_issues: Arc<Vec<(PathBuf, String)>>,
+    /// Merged timestamps further in the future than this are clamped to now
+    max_clock_skew: chrono::Duration,
+}
+
+#[derive(Clone)]
+struct StorePaths {
+    server_keys: String,
+    client_config: String,
 }
 
 impl KeyStoreManager {
-    pub fn new() -> Self {
+    /// Load stores from the data root in `paths`
+    pub fn new(paths: &Paths) -> Result<Self, KeyStoreError> {
+        Self::load_from(&paths.server_keys(), &paths.client_config())
+    }
+
+    /// Load stores from the data root, keeping server secret keys encrypted on disk.
+    /// Existing plaintext secrets are read as-is and encrypted on the next save.
+    pub fn with_master_key(paths: &Paths, master_key: [u8; 32]) -> Result<Self, KeyStoreError> {
+        Self::open(&paths.server_keys(), &paths.client_config(), Some(master_key))
+    }
+
+    /// Load stores from explicit file paths
+    pub fn load_from(server_keys_path: &str, client_config_path: &str) -> Result<Self, KeyStoreError> {
+        Self::open(server_keys_path, client_config_path, None)
+    }
+
+    fn open(server_keys_path: &str, client_config_path: &str, master_key: Option<[u8; 32]>) -> Result<Self, KeyStoreError> {
+        let (mut server_keys, mut key_issues) = ServerKeysStore::load_sealed(server_keys_path, master_key.as_ref())?;
+        // A bad manual edit must not silently yield wrong shared secrets
+        server_keys.keys.retain(|_, entry| match entry.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                key_issues.push(e.to_string());
+                false
+            }
+        });
+        let (client_config, client_issues) = ClientConfigStore::load_from_reporting(client_config_path)?;
+
+        let load_issues: Vec<(PathBuf, String)> = key_issues.into_iter()
+            .map(|issue| (PathBuf::from(server_keys_path), issue))
+            .chain(client_issues.into_iter().map(|issue| (PathBuf::from(client_config_path), issue)))
+            .collect();
+        for (path, issue) in &load_issues {
+            tracing::warn!("Skipping entry in {}: {}", path.display(), issue);
+        }
+
+        Ok(Self {
+            server_keys: Arc::new(RwLock::new(server_keys)),
+            by_public_key: Arc::new(RwLock::new(index_by_public_key(&client_config))),
+            client_config: Arc::new(RwLock::new(client_config)),
+            last_seen: Arc::default(),
+            paths: Some(StorePaths {
+                server_keys: server_keys_path.to_string(),
+                client_config: client_config_path.to_string(),
+            }),
+            master_key: master_key.map(Arc::new),
+            load_issues: Arc::new(load_issues),
+            max_clock_skew: chrono::Duration::hours(DEFAULT_MAX_CLOCK_SKEW_HOURS),
+        })
+    }
+
+    /// Key store that never reads or writes files
+    pub fn in_memory() -> Self {
         Self {
-            server_keys: Arc::new(RwLock::new(ServerKeysStore::load())),
-            client_config: Arc::new(RwLock::new(ClientConfigStore::load())),
+            server_keys: Arc::new(RwLock::new(ServerKeysStore::default())),
+            client_config: Arc::new(RwLock::new(ClientConfigStore::default())),
+            by_public_key: Arc::default(),
+            last_seen: Arc::default(),
+            paths: None,
+            master_key: None,
+            load_issues: Arc::default(),
+            max_clock_skew: chrono::Duration::hours(DEFAULT_MAX_CLOCK_SKEW_HOURS),
+        }
+    }
+
+    /// Change how far in the future [`KeyStoreManager::merge_from`] lets
+    /// incoming timestamps be
+    pub fn with_max_clock_skew(mut self, skew: chrono::Duration) -> Self {
+        self.max_clock_skew = skew;
+        self
+    }
+
+    /// Entries that were skipped when the stores were loaded, and why
+    pub fn load_issues(&self) -> Vec<(PathBuf, String)> {
+        self.load_issues.as_ref().clone()
+    }
+
+    /// Whether changes are written to disk
+    pub fn is_persistent(&self) -> bool {
+        self.paths.is_some()
+    }
+
+    fn save_server_keys(&self, store: &ServerKeysStore) -> Result<(), KeyStoreError> {
+        match &self.paths {
+            Some(paths) => store.save_sealed(&paths.server_keys, self.master_key.as_deref()),
+            None => Ok(()),
+        }
+    }
+
+    /// Bring the public key index in line with `store`
+    fn reindex(&self, store: &ClientConfigStore) {
+        *self.by_public_key.write().unwrap() = index_by_public_key(store);
+    }
+
+    fn save_client_config(&self, store: &ClientConfigStore) -> Result<(), KeyStoreError> {
+        self.reindex(store);
+        match &self.paths {
+            Some(paths) => store.save_to(&paths.client_config),
+            None => Ok(()),
         }
     }
 
     /// Generate a new server keypair for a client
-    pub fn generate_server_key_for_client(&self, client_id: &str) -> ServerKeyEntry {
+    pub fn generate_server_key_for_client(&self, client_id: &str) -> Result<ServerKeyEntry, KeyStoreError> {
         let entry = ServerKeyEntry::generate(client_id);
-        {
-            let mut store = self.server_keys.write().unwrap();
-            store.add_key(entry.clone());
-            let _ = store.save();
+        let mut store = self.server_keys.write().unwrap();
+        let previous = store.keys.insert(client_id.to_string(), entry.clone());
+        if let Err(e) = self.save_server_keys(&store) {
+            // Keep memory consistent with what is on disk
+            match previous {
+                Some(previous) => store.add_key(previous),
+                None => {
+                    store.keys.remove(client_id);
+                }
+            }
+            return Err(e);
         }
-        entry
+        Ok(entry)
+    }
+
+    /// Re-check every stored keypair, sorted by client id
+    pub fn validate_all(&self) -> Vec<(String, bool)> {
+        let store = self.server_keys.read().unwrap();
+        let mut results: Vec<(String, bool)> = store.keys.iter()
+            .map(|(client_id, entry)| (client_id.clone(), entry.validate().is_ok()))
+            .collect();
+        results.sort();
+        results
     }
 
     /// Get server key for a client
@@ -185,35 +599,291 @@ impl KeyStoreManager {
     }
 
     /// Register a client with their public key
-    pub fn register_client(&self, client_id: &str, client_public_key: &str) -> Option<ClientEntry> {
+    pub fn register_client(&self, client_id: &str, client_public_key: &str) -> Result<ClientEntry, KeyStoreError> {
         // Ensure server key exists for this client
-        let server_key = self.get_server_key(client_id)?;
-        
+        if self.get_server_key(client_id).is_none() {
+            return Err(KeyStoreError::MissingServerKey(client_id.to_string()));
+        }
+
         let entry = ClientEntry {
             client_id: client_id.to_string(),
             client_public_key: client_public_key.to_string(),
             server_key_id: client_id.to_string(),
             registered_at: chrono::Utc::now().to_rfc3339(),
             last_seen: Some(chrono::Utc::now().to_rfc3339()),
+            metadata: ClientMetadata::default(),
         };
 
-        {
-            let mut store = self.client_config.write().unwrap();
-            store.add_client(entry.clone());
-            let _ = store.save();
+        let mut store = self.client_config.write().unwrap();
+        let previous = store.clients.insert(client_id.to_string(), entry.clone());
+        if let Err(e) = self.save_client_config(&store) {
+            match previous {
+                Some(previous) => store.add_client(previous),
+                None => {
+                    store.clients.remove(client_id);
+                }
+            }
+            self.reindex(&store);
+            return Err(e);
+        }
+
+        Ok(entry)
+    }
+
+    /// Register a client unless it already is, for retries of a completed registration.
+    ///
+    /// Repeating the stored public key returns the existing entry and `false`;
+    /// a different key is [`KeyStoreError::AlreadyRegistered`].
+    pub fn register_client_once(&self, client_id: &str, client_public_key: &str) -> Result<(ClientEntry, bool), KeyStoreError> {
+        if let Some(existing) = self.get_client(client_id) {
+            if !existing.client_public_key.eq_ignore_ascii_case(client_public_key) {
+                return Err(KeyStoreError::AlreadyRegistered(client_id.to_string()));
+            }
+            return Ok((existing, false));
         }
+        self.register_client(client_id, client_public_key).map(|entry| (entry, true))
+    }
+
+    /// Create server keys for and register many clients at once.
+    ///
+    /// Takes each lock once and writes each file once. Results are in input
+    /// order; an entry fails on its own if the id is taken (or repeated in
+    /// the batch), already has a pending `/register/init` server key, or the
+    /// public key doesn't parse or is a low-order point. Keys are stored as
+    /// lowercase hex whatever encoding they came in. If saving fails, nothing
+    /// from the batch is kept and every entry reports it.
+    pub fn register_clients_batch(&self, entries: Vec<(String, String)>) -> Vec<Result<ClientEntry, KeyStoreError>> {
+        self.register_with_server_keys(entries)
+            .into_iter()
+            .map(|result| result.map(|(entry, _)| entry))
+            .collect()
+    }
+
+    /// [`KeyStoreManager::register_clients_batch`], also returning the server
+    /// key generated for each client
+    fn register_with_server_keys(&self, entries: Vec<(String, String)>) -> Vec<Result<(ClientEntry, ServerKeyEntry), KeyStoreError>> {
+        let mut clients = self.client_config.write().unwrap();
+        let mut keys = self.server_keys.write().unwrap();
+        let clients_before = clients.clone();
+        let keys_before = keys.clone();
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let results: Vec<Result<(ClientEntry, ServerKeyEntry), KeyStoreError>> = entries
+            .into_iter()
+            .map(|(client_id, client_public_key)| {
+                if clients.clients.contains_key(&client_id) {
+                    return Err(KeyStoreError::AlreadyRegistered(client_id));
+                }
+                // Replacing the key would break the client's in-progress challenge
+                if keys.keys.contains_key(&client_id) {
+                    return Err(KeyStoreError::RegistrationPending(client_id));
+                }
+                let Ok(client_public) = parse_public_key(&client_public_key) else {
+                    return Err(KeyStoreError::InvalidPublicKey(client_id));
+                };
+                let client_public_key = hex::encode(client_public);
+                let server_key = ServerKeyEntry::generate(&client_id);
+                if server_key.derive_shared_secret(&client_public_key).is_none() {
+                    return Err(KeyStoreError::InvalidPublicKey(client_id));
+                }
+
+                keys.add_key(server_key.clone());
+                let entry = ClientEntry {
+                    client_id: client_id.clone(),
+                    client_public_key,
+                    server_key_id: client_id,
+                    registered_at: now.clone(),
+                    last_seen: Some(now.clone()),
+                    metadata: ClientMetadata::default(),
+                };
+                clients.add_client(entry.clone());
+                Ok((entry, server_key))
+            })
+            .collect();
+
+        if !results.iter().any(Result::is_ok) {
+            return results;
+        }
+        let saved = self.save_server_keys(&keys)
+            .and_then(|_| self.save_client_config(&clients));
+        if let Err(e) = saved {
+            *clients = clients_before;
+            *keys = keys_before;
+            self.reindex(&clients);
+            // Best effort to put the files back in step with memory
+            let _ = self.save_server_keys(&keys);
+            let message = e.to_string();
+            return results
+                .into_iter()
+                .map(|r| r.and_then(|_| Err(std::io::Error::other(message.clone()).into())))
+                .collect();
+        }
+        results
+    }
 
-        Some(entry)
+    /// Create a client's server key and register it in one step, for clients
+    /// that can send their public key up front.
+    ///
+    /// Either both are stored or neither is; the errors are those of
+    /// [`KeyStoreManager::register_clients_batch`].
+    pub fn bootstrap_client(&self, client_id: &str, client_public_key: &str) -> Result<(ClientEntry, ServerKeyEntry), KeyStoreError> {
+        self.register_with_server_keys(vec![(client_id.to_string(), client_public_key.to_string())])
+            .pop()
+            .expect("one result per entry")
+    }
+
+    /// All client public keys as `client_id <hex_public_key>` lines, sorted by id
+    pub fn export_public_keys(&self) -> String {
+        let store = self.client_config.read().unwrap();
+        let mut lines: Vec<String> = store.clients.values()
+            .map(|c| format!("{} {}\n", c.client_id, c.client_public_key))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Register every client listed in [`KeyStoreManager::export_public_keys`] format.
+    ///
+    /// Blank lines and `#` comments are skipped. A malformed line rejects the
+    /// whole input; otherwise each client is registered as in
+    /// [`KeyStoreManager::register_clients_batch`], so keys may use any
+    /// encoding [`parse_public_key`] accepts and are stored as hex.
+    pub fn import_public_keys(&self, text: &str) -> Result<Vec<Result<ClientEntry, KeyStoreError>>, KeyStoreError> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(client_id), Some(public_key), None) => {
+                    entries.push((client_id.to_string(), public_key.to_string()));
+                }
+                _ => return Err(KeyStoreError::MalformedKeyLine(index + 1)),
+            }
+        }
+        Ok(self.register_clients_batch(entries))
     }
 
     /// Get client configuration
     pub fn get_client(&self, client_id: &str) -> Option<ClientEntry> {
-        let store = self.client_config.read().unwrap();
-        store.get_client(client_id).cloned()
+        let entry = self.client_config.read().unwrap().get_client(client_id).cloned()?;
+        Some(self.last_seen.lock().unwrap().overlay(entry))
+    }
+
+    /// Find the registered client using a given public key (hex, any case)
+    pub fn find_client_by_public_key(&self, client_public_key: &str) -> Option<ClientEntry> {
+        let client_id = self.by_public_key.read().unwrap()
+            .get(&client_public_key.to_ascii_lowercase())
+            .cloned()?;
+        let entry = self.client_config.read().unwrap()
+            .get_client(&client_id)
+            .filter(|c| c.client_public_key.eq_ignore_ascii_case(client_public_key))
+            .cloned()?;
+        Some(self.last_seen.lock().unwrap().overlay(entry))
+    }
+
+    /// Record activity for a client, returning false if it is not registered.
+    ///
+    /// Only takes the client config write lock when it writes to disk, at
+    /// most once per [`LAST_SEEN_WRITE_INTERVAL`]; other touches stay in memory.
+    pub fn touch_client(&self, client_id: &str) -> Result<bool, KeyStoreError> {
+        if !self.client_config.read().unwrap().clients.contains_key(client_id) {
+            return Ok(false);
+        }
+        let mut cache = self.last_seen.lock().unwrap();
+        cache.pending.insert(client_id.to_string(), chrono::Utc::now().to_rfc3339());
+        if cache.written.is_some_and(|at| at.elapsed() < LAST_SEEN_WRITE_INTERVAL) {
+            return Ok(true);
+        }
+
+        let mut store = self.client_config.write().unwrap();
+        let before = store.clone();
+        let pending = cache.pending.clone();
+        cache.apply(&mut store);
+        if let Err(e) = self.save_client_config(&store) {
+            *store = before;
+            cache.pending = pending;
+            return Err(e);
+        }
+        cache.written = Some(Instant::now());
+        Ok(true)
+    }
+
+    /// Merge `metadata` into a client's stored metadata.
+    ///
+    /// Returns the updated entry, or `None` if the client is not registered.
+    pub fn update_metadata(&self, client_id: &str, metadata: ClientMetadata) -> Result<Option<ClientEntry>, KeyStoreError> {
+        let mut store = self.client_config.write().unwrap();
+        let Some(client) = store.clients.get_mut(client_id) else {
+            return Ok(None);
+        };
+        let previous = client.metadata.clone();
+        client.metadata.merge(metadata);
+        let updated = client.clone();
+
+        if let Err(e) = self.save_client_config(&store) {
+            if let Some(client) = store.clients.get_mut(client_id) {
+                client.metadata = previous;
+            }
+            return Err(e);
+        }
+        Ok(Some(updated))
+    }
+
+    /// Remove clients (and their server keys) not seen for `max_idle`.
+    ///
+    /// Clients that were never seen are judged by their registration time.
+    /// A `max_idle` reaching back past the earliest representable time
+    /// removes nothing. Returns the ids of the clients removed.
+    pub fn prune_idle(&self, max_idle: chrono::Duration) -> Result<Vec<String>, KeyStoreError> {
+        let Some(cutoff) = chrono::Utc::now().checked_sub_signed(max_idle) else {
+            return Ok(Vec::new());
+        };
+        let mut cache = self.last_seen.lock().unwrap();
+        let mut clients = self.client_config.write().unwrap();
+        let mut keys = self.server_keys.write().unwrap();
+        // Judge clients by their latest activity, even if not yet written
+        let pending = cache.pending.clone();
+        let stored = clients.clone();
+        cache.apply(&mut clients);
+
+        let idle: Vec<String> = clients.clients.values()
+            .filter(|c| {
+                let seen = c.last_seen.as_deref().unwrap_or(&c.registered_at);
+                chrono::DateTime::parse_from_rfc3339(seen)
+                    .map(|t| t < cutoff)
+                    .unwrap_or(false)
+            })
+            .map(|c| c.client_id.clone())
+            .collect();
+        if idle.is_empty() {
+            // Nothing to write; leave the applied times for the next write
+            return Ok(idle);
+        }
+
+        let keys_before = keys.clone();
+        for client_id in &idle {
+            clients.clients.remove(client_id);
+            keys.keys.remove(client_id);
+        }
+
+        let saved = self.save_client_config(&clients)
+            .and_then(|_| self.save_server_keys(&keys));
+        if let Err(e) = saved {
+            *clients = stored;
+            *keys = keys_before;
+            cache.pending = pending;
+            // Best effort to put the files back in step with memory
+            let _ = self.save_client_config(&clients);
+            return Err(e);
+        }
+        Ok(idle)
     }
 
     /// Derive shared secret for a client
-    pub fn derive_shared_secret(&self, client_id: &str) -> Option<[u8; 32]> {
+    pub fn derive_shared_secret(&self, client_id: &str) -> Option<SharedSecret> {
         let server_key = self.get_server_key(client_id)?;
         let client = self.get_client(client_id)?;
         server_key.derive_shared_secret(&client.client_public_key)
@@ -221,8 +891,242 @@ impl KeyStoreManager {
 
     /// List all registered clients
     pub fn list_clients(&self) -> Vec<ClientEntry> {
-        let store = self.client_config.read().unwrap();
-        store.clients.values().cloned().collect()
+        let clients: Vec<ClientEntry> = self.client_config.read().unwrap().clients.values().cloned().collect();
+        let cache = self.last_seen.lock().unwrap();
+        clients.into_iter().map(|c| cache.overlay(c)).collect()
+    }
+
+    /// List one page of clients ordered by client_id, with the total match count.
+    ///
+    /// `search` is a case-insensitive substring matched against the client_id.
+    pub fn list_clients_paginated(&self, offset: usize, limit: usize, search: Option<&str>) -> (Vec<ClientEntry>, usize) {
+        let (page, total) = {
+            let store = self.client_config.read().unwrap();
+            let search = search.map(str::to_lowercase);
+            let mut matching: Vec<&ClientEntry> = store.clients.values()
+                .filter(|c| match &search {
+                    Some(term) => c.client_id.to_lowercase().contains(term),
+                    None => true,
+                })
+                .collect();
+            matching.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+
+            let total = matching.len();
+            let page: Vec<ClientEntry> = matching.into_iter().skip(offset).take(limit).cloned().collect();
+            (page, total)
+        };
+        let cache = self.last_seen.lock().unwrap();
+        (page.into_iter().map(|c| cache.overlay(c)).collect(), total)
+    }
+
+    /// Write both stores to disk
+    pub fn flush(&self) -> Result<(), KeyStoreError> {
+        self.save_server_keys(&self.server_keys.read().unwrap())?;
+
+        let mut cache = self.last_seen.lock().unwrap();
+        let mut store = self.client_config.write().unwrap();
+        let pending = cache.pending.clone();
+        cache.apply(&mut store);
+        if let Err(e) = self.save_client_config(&store) {
+            // Stay pending rather than claim they reached disk
+            cache.pending = pending;
+            return Err(e);
+        }
+        cache.written = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Snapshot both stores into a backup bundle
+    pub fn export_bundle(&self) -> OmniBundle {
+        let cache = self.last_seen.lock().unwrap();
+        let mut clients = self.client_config.read().unwrap().clone();
+        let keys = self.server_keys.read().unwrap();
+        for client in clients.clients.values_mut() {
+            *client = cache.overlay(client.clone());
+        }
+        OmniBundle::new(keys.clone(), clients)
+    }
+
+    /// Server keys from `/register/init` whose registration was never
+    /// completed, sorted by client id
+    pub fn list_pending(&self) -> Vec<ServerKeyEntry> {
+        let clients = self.client_config.read().unwrap();
+        let keys = self.server_keys.read().unwrap();
+        let mut pending: Vec<ServerKeyEntry> = keys.keys.values()
+            .filter(|k| !clients.clients.contains_key(&k.client_id))
+            .cloned()
+            .collect();
+        pending.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        pending
+    }
+
+    /// Remove pending registrations whose server key is older than `older_than`.
+    ///
+    /// Completed registrations are never touched, and an `older_than` reaching
+    /// back past the earliest representable time removes nothing. Returns the
+    /// number removed.
+    pub fn prune_pending(&self, older_than: chrono::Duration) -> Result<usize, KeyStoreError> {
+        let Some(cutoff) = chrono::Utc::now().checked_sub_signed(older_than) else {
+            return Ok(0);
+        };
+        let clients = self.client_config.read().unwrap();
+        let mut keys = self.server_keys.write().unwrap();
+
+        let stale: Vec<String> = keys.keys.values()
+            .filter(|k| !clients.clients.contains_key(&k.client_id))
+            .filter(|k| {
+                chrono::DateTime::parse_from_rfc3339(&k.created_at)
+                    .map(|t| t < cutoff)
+                    .unwrap_or(false)
+            })
+            .map(|k| k.client_id.clone())
+            .collect();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+
+        let keys_before = keys.clone();
+        for client_id in &stale {
+            keys.keys.remove(client_id);
+        }
+        if let Err(e) = self.save_server_keys(&keys) {
+            *keys = keys_before;
+            return Err(e);
+        }
+        Ok(stale.len())
+    }
+
+    /// Copy another store's clients and server keys into this one and persist.
+    ///
+    /// A client id's server key and registration move together. Ids whose
+    /// keys match on both sides are skipped rather than treated as conflicts.
+    /// Incoming timestamps more than the allowed clock skew ahead are clamped
+    /// to now, so a store with a wrong clock cannot always win `PreferNewer`.
+    /// Entries the other store holds that don't validate are dropped before
+    /// comparing and counted as rejected.
+    pub fn merge_from(&self, other: &KeyStoreManager, on_conflict: ConflictPolicy) -> Result<MergeReport, KeyStoreError> {
+        // Snapshot first so merging a manager into itself cannot deadlock
+        let mut incoming = other.export_bundle();
+        let rejected = drop_invalid_entries(&mut incoming).len();
+        clamp_future_timestamps(&mut incoming, self.max_clock_skew);
+
+        let mut clients = self.client_config.write().unwrap();
+        let mut keys = self.server_keys.write().unwrap();
+
+        let mut ids: Vec<&String> = incoming.server_keys.keys.keys()
+            .chain(incoming.client_config.clients.keys())
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        let mut report = MergeReport { rejected, ..MergeReport::default() };
+        let mut take = Vec::new();
+        for id in ids {
+            let (ours_key, theirs_key) = (keys.get_key(id), incoming.server_keys.get_key(id));
+            let (ours_client, theirs_client) = (clients.get_client(id), incoming.client_config.get_client(id));
+            if ours_key.is_none() && ours_client.is_none() {
+                report.added += 1;
+                take.push(id);
+                continue;
+            }
+
+            let same = ours_key.map(|k| &k.public_key) == theirs_key.map(|k| &k.public_key)
+                && ours_client.map(|c| &c.client_public_key) == theirs_client.map(|c| &c.client_public_key);
+            if same {
+                report.skipped += 1;
+                continue;
+            }
+
+            report.conflicted += 1;
+            match on_conflict {
+                ConflictPolicy::Fail => return Err(KeyStoreError::MergeConflict(id.clone())),
+                ConflictPolicy::KeepExisting => report.skipped += 1,
+                ConflictPolicy::PreferNewer => {
+                    let stamp = |client: Option<&ClientEntry>, key: Option<&ServerKeyEntry>| {
+                        client.map(|c| c.registered_at.as_str())
+                            .or(key.map(|k| k.created_at.as_str()))
+                            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    };
+                    if stamp(theirs_client, theirs_key) > stamp(ours_client, ours_key) {
+                        report.replaced += 1;
+                        take.push(id);
+                    } else {
+                        report.skipped += 1;
+                    }
+                }
+            }
+        }
+
+        let clients_before = clients.clone();
+        let keys_before = keys.clone();
+        for id in take {
+            // Take both halves from the same side so keys stay paired
+            keys.keys.remove(id);
+            clients.clients.remove(id);
+            if let Some(key) = incoming.server_keys.get_key(id) {
+                keys.add_key(key.clone());
+            }
+            if let Some(client) = incoming.client_config.get_client(id) {
+                clients.add_client(client.clone());
+            }
+        }
+
+        let saved = self.save_client_config(&clients)
+            .and_then(|_| self.save_server_keys(&keys));
+        if let Err(e) = saved {
+            *clients = clients_before;
+            *keys = keys_before;
+            let _ = self.save_client_config(&clients);
+            return Err(e);
+        }
+        Ok(report)
+    }
+
+    /// Replace both stores with the contents of a backup bundle, leaving out
+    /// entries that fail the same checks as [`Self::merge_from`]
+    pub fn import_bundle(&self, mut bundle: OmniBundle) -> Result<ImportReport, KeyStoreError> {
+        let rejected = drop_invalid_entries(&mut bundle);
+        let report = ImportReport {
+            clients: bundle.client_config.clients.len(),
+            server_keys: bundle.server_keys.keys.len(),
+            rejected,
+        };
+
+        let mut clients = self.client_config.write().unwrap();
+        let mut keys = self.server_keys.write().unwrap();
+
+        let clients_before = std::mem::replace(&mut *clients, bundle.client_config);
+        let keys_before = std::mem::replace(&mut *keys, bundle.server_keys);
+
+        let saved = self.save_client_config(&clients)
+            .and_then(|_| self.save_server_keys(&keys));
+        if let Err(e) = saved {
+            *clients = clients_before;
+            *keys = keys_before;
+            // Best effort to put the files back in step with memory
+            let _ = self.save_client_config(&clients);
+            return Err(e);
+        }
+        Ok(report)
+    }
+
+    /// Whether secret keys are sealed with a master key on disk
+    pub fn has_master_key(&self) -> bool {
+        self.master_key.is_some()
+    }
+
+    /// Check that the directory holding the key files accepts writes
+    pub fn is_writable(&self) -> bool {
+        let Some(paths) = &self.paths else {
+            return true;
+        };
+        let dir = Path::new(&paths.server_keys)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+        let probe = dir.join(".write_probe");
+        let ok = atomic_write(&probe, b"ok").is_ok();
+        let _ = fs::remove_file(&probe);
+        ok
     }
 
     /// List all server keys
@@ -233,9 +1137,3 @@ impl KeyStoreManager {
             .collect()
     }
 }
-
-impl Default for KeyStoreManager {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -12,7 +12,7 @@ mod tests {
         
         assert_eq!(entry.client_id, "test-client");
         assert_eq!(entry.public_key.len(), 64); // 32 bytes hex
-        assert_eq!(entry.secret_key.len(), 64); // 32 bytes hex
+        assert_eq!(entry.secret_key.0.len(), 64); // 32 bytes hex
     }
 
     #[test]
@@ -27,7 +27,14 @@ mod tests {
         
         let shared = entry.derive_shared_secret(&client_public_hex);
         assert!(shared.is_some());
-        assert_eq!(shared.unwrap().len(), 32);
+        assert_eq!(shared.unwrap().as_bytes().len(), 32);
+    }
+
+    #[test]
+    fn test_server_key_entry_rejects_low_order_key() {
+        let entry = ServerKeyEntry::generate("test-client");
+
+        assert!(entry.derive_shared_secret(&"00".repeat(32)).is_none());
     }
 
     #[test]
@@ -42,7 +49,7 @@ mod tests {
         
         store.save_to(path_str).unwrap();
         
-        let loaded = ServerKeysStore::load_from(path_str);
+        let loaded = ServerKeysStore::load_from(path_str).unwrap();
         assert!(loaded.get_key("client-1").is_some());
         assert_eq!(loaded.get_key("client-1").unwrap().public_key, entry.public_key);
     }
@@ -60,22 +67,23 @@ mod tests {
             server_key_id: "client-1".to_string(),
             registered_at: "2024-01-01T00:00:00Z".to_string(),
             last_seen: None,
+            metadata: ClientMetadata::default(),
         };
         store.add_client(entry.clone());
         
         store.save_to(path_str).unwrap();
         
-        let loaded = ClientConfigStore::load_from(path_str);
+        let loaded = ClientConfigStore::load_from(path_str).unwrap();
         assert!(loaded.get_client("client-1").is_some());
         assert_eq!(loaded.get_client("client-1").unwrap().client_public_key, "abc123");
     }
 
     #[test]
     fn test_key_store_manager_generate_and_register() {
-        let manager = KeyStoreManager::new();
+        let manager = KeyStoreManager::in_memory();
         
         // Generate server key
-        let server_key = manager.generate_server_key_for_client("test-device");
+        let server_key = manager.generate_server_key_for_client("test-device").unwrap();
         assert_eq!(server_key.client_id, "test-device");
         
         // Verify it's stored
@@ -84,7 +92,7 @@ mod tests {
         
         // Register client
         let client = manager.register_client("test-device", "abc123def456abc123def456abc123def456abc123def456abc123def456abcd");
-        assert!(client.is_some());
+        assert!(client.is_ok());
         
         // Verify client is stored
         let retrieved_client = manager.get_client("test-device");
@@ -93,12 +101,727 @@ mod tests {
 
     #[test]
     fn test_key_store_manager_list_operations() {
-        let manager = KeyStoreManager::new();
+        let manager = KeyStoreManager::in_memory();
         
-        manager.generate_server_key_for_client("device-1");
-        manager.generate_server_key_for_client("device-2");
+        manager.generate_server_key_for_client("device-1").unwrap();
+        manager.generate_server_key_for_client("device-2").unwrap();
         
         let keys = manager.list_server_keys();
-        assert!(keys.len() >= 2);
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    fn test_key_store_manager_surfaces_write_errors() {
+        let dir = tempdir().unwrap();
+        // A regular file where the data directory should be makes every
+        // write fail, even when running as root
+        let blocker = dir.path().join("data");
+        fs::write(&blocker, "not a directory").unwrap();
+        let server_keys = blocker.join("server_keys.yaml");
+        let client_config = blocker.join("client_config.yaml");
+
+        let manager = KeyStoreManager::load_from(
+            server_keys.to_str().unwrap(),
+            client_config.to_str().unwrap(),
+        ).unwrap();
+
+        let result = manager.generate_server_key_for_client("device-1");
+        assert!(matches!(result, Err(KeyStoreError::Io(_))));
+        // Failed writes must not leave the key behind in memory
+        assert!(manager.get_server_key("device-1").is_none());
+
+        let result = manager.register_client("device-1", &"a".repeat(64));
+        assert!(matches!(result, Err(KeyStoreError::MissingServerKey(_))));
+    }
+
+    fn manager_with_clients(dir: &std::path::Path, ids: &[&str]) -> KeyStoreManager {
+        let manager = KeyStoreManager::load_from(
+            dir.join("server_keys.yaml").to_str().unwrap(),
+            dir.join("client_config.yaml").to_str().unwrap(),
+        ).unwrap();
+        for id in ids {
+            manager.generate_server_key_for_client(id).unwrap();
+            manager.register_client(id, &"a".repeat(64)).unwrap();
+        }
+        manager
+    }
+
+    #[test]
+    fn test_list_clients_paginated_limit_and_offset() {
+        let dir = tempdir().unwrap();
+        let manager = manager_with_clients(dir.path(), &["c", "a", "e", "b", "d"]);
+
+        let (page, total) = manager.list_clients_paginated(0, 2, None);
+        assert_eq!(total, 5);
+        let ids: Vec<_> = page.iter().map(|c| c.client_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+
+        let (page, _) = manager.list_clients_paginated(4, 2, None);
+        let ids: Vec<_> = page.iter().map(|c| c.client_id.as_str()).collect();
+        assert_eq!(ids, ["e"]);
+
+        let (page, total) = manager.list_clients_paginated(10, 2, None);
+        assert!(page.is_empty());
+        assert_eq!(total, 5);
+    }
+
+    #[test]
+    fn test_list_clients_paginated_search() {
+        let dir = tempdir().unwrap();
+        let manager = manager_with_clients(dir.path(), &["phone-1", "laptop-1", "Phone-2"]);
+
+        let (page, total) = manager.list_clients_paginated(0, 10, Some("phone"));
+        assert_eq!(total, 2);
+        let ids: Vec<_> = page.iter().map(|c| c.client_id.as_str()).collect();
+        assert_eq!(ids, ["Phone-2", "phone-1"]);
+
+        let (page, total) = manager.list_clients_paginated(0, 10, Some("tablet"));
+        assert!(page.is_empty());
+        assert_eq!(total, 0);
+    }
+
+    #[test]
+    fn test_in_memory_manager_writes_nothing() {
+        let manager = KeyStoreManager::in_memory();
+        assert!(!manager.is_persistent());
+
+        manager.generate_server_key_for_client("in-memory-client").unwrap();
+        manager.register_client("in-memory-client", &"a".repeat(64)).unwrap();
+        assert!(manager.get_client("in-memory-client").is_some());
+
+        // The default on-disk stores never see the client
+        let paths = crate::config::Paths::default();
+        let on_disk_keys = ServerKeysStore::load_from(&paths.server_keys()).unwrap();
+        assert!(on_disk_keys.get_key("in-memory-client").is_none());
+        let on_disk_clients = ClientConfigStore::load_from(&paths.client_config()).unwrap();
+        assert!(on_disk_clients.get_client("in-memory-client").is_none());
+    }
+
+    #[test]
+    fn test_touch_client_updates_last_seen() {
+        let manager = KeyStoreManager::in_memory();
+        manager.generate_server_key_for_client("device-1").unwrap();
+        let registered = manager.register_client("device-1", &"a".repeat(64)).unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(manager.touch_client("device-1").unwrap());
+        assert!(!manager.touch_client("unknown").unwrap());
+
+        let touched = manager.get_client("device-1").unwrap();
+        assert!(touched.last_seen > registered.last_seen);
+    }
+
+    #[test]
+    fn test_touch_client_coalesces_writes() {
+        let dir = tempdir().unwrap();
+        let clients_path = dir.path().join("client_config.yaml");
+        let manager = KeyStoreManager::load_from(
+            dir.path().join("server_keys.yaml").to_str().unwrap(),
+            clients_path.to_str().unwrap(),
+        ).unwrap();
+        manager.generate_server_key_for_client("device-1").unwrap();
+        manager.register_client("device-1", &"a".repeat(64)).unwrap();
+        let on_disk = || ClientConfigStore::load_from(clients_path.to_str().unwrap()).unwrap()
+            .get_client("device-1").unwrap().last_seen.clone();
+
+        assert!(manager.touch_client("device-1").unwrap());
+        let first = on_disk();
+        assert_eq!(first, manager.get_client("device-1").unwrap().last_seen);
+
+        // A second touch right away only updates memory
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(manager.touch_client("device-1").unwrap());
+        let latest = manager.get_client("device-1").unwrap().last_seen;
+        assert!(latest > first);
+        assert_eq!(on_disk(), first);
+
+        // Until something else writes the file
+        manager.flush().unwrap();
+        assert_eq!(on_disk(), latest);
+    }
+
+    #[test]
+    fn test_prune_idle_sees_unwritten_touches() {
+        let dir = tempdir().unwrap();
+        let server_keys = dir.path().join("server_keys.yaml");
+        let client_config = dir.path().join("client_config.yaml");
+
+        let mut keys = ServerKeysStore::default();
+        keys.add_key(ServerKeyEntry::generate("old"));
+        keys.save_to(server_keys.to_str().unwrap()).unwrap();
+        let mut clients = ClientConfigStore::default();
+        clients.add_client(ClientEntry {
+            client_id: "old".to_string(),
+            client_public_key: "a".repeat(64),
+            server_key_id: "old".to_string(),
+            registered_at: "2020-01-01T00:00:00Z".to_string(),
+            last_seen: Some("2020-01-02T00:00:00Z".to_string()),
+            metadata: ClientMetadata::default(),
+        });
+        clients.save_to(client_config.to_str().unwrap()).unwrap();
+        let manager = KeyStoreManager::load_from(
+            server_keys.to_str().unwrap(),
+            client_config.to_str().unwrap(),
+        ).unwrap();
+
+        // The first touch writes; the second is only held in memory
+        manager.generate_server_key_for_client("other").unwrap();
+        manager.register_client("other", &"b".repeat(64)).unwrap();
+        assert!(manager.touch_client("other").unwrap());
+        assert!(manager.touch_client("old").unwrap());
+
+        assert!(manager.prune_idle(chrono::Duration::days(30)).unwrap().is_empty());
+        assert!(manager.get_client("old").is_some());
+    }
+
+    #[test]
+    fn test_find_client_by_public_key() {
+        let manager = KeyStoreManager::in_memory();
+        manager.generate_server_key_for_client("device-1").unwrap();
+        manager.register_client("device-1", &"ab".repeat(32)).unwrap();
+
+        let found = manager.find_client_by_public_key(&"AB".repeat(32));
+        assert_eq!(found.unwrap().client_id, "device-1");
+        assert!(manager.find_client_by_public_key(&"cd".repeat(32)).is_none());
+
+        // The index follows wholesale replacement of the clients
+        manager.import_bundle(KeyStoreManager::in_memory().export_bundle()).unwrap();
+        assert!(manager.find_client_by_public_key(&"ab".repeat(32)).is_none());
+    }
+
+    #[test]
+    fn test_prune_idle_removes_only_stale_clients() {
+        let dir = tempdir().unwrap();
+        let server_keys = dir.path().join("server_keys.yaml");
+        let client_config = dir.path().join("client_config.yaml");
+
+        // A client last seen long ago, written straight to disk
+        let mut keys = ServerKeysStore::default();
+        keys.add_key(ServerKeyEntry::generate("stale"));
+        keys.save_to(server_keys.to_str().unwrap()).unwrap();
+        let mut clients = ClientConfigStore::default();
+        clients.add_client(ClientEntry {
+            client_id: "stale".to_string(),
+            client_public_key: "a".repeat(64),
+            server_key_id: "stale".to_string(),
+            registered_at: "2020-01-01T00:00:00Z".to_string(),
+            last_seen: Some("2020-01-02T00:00:00Z".to_string()),
+            metadata: ClientMetadata::default(),
+        });
+        clients.save_to(client_config.to_str().unwrap()).unwrap();
+
+        let manager = KeyStoreManager::load_from(
+            server_keys.to_str().unwrap(),
+            client_config.to_str().unwrap(),
+        ).unwrap();
+        manager.generate_server_key_for_client("fresh").unwrap();
+        manager.register_client("fresh", &"b".repeat(64)).unwrap();
+
+        let removed = manager.prune_idle(chrono::Duration::days(30)).unwrap();
+
+        assert_eq!(removed, ["stale"]);
+        assert!(manager.get_client("stale").is_none());
+        assert!(manager.get_server_key("stale").is_none());
+        assert!(manager.get_client("fresh").is_some());
+
+        // A window older than any representable time removes nothing
+        assert!(manager.prune_idle(chrono::Duration::MAX).unwrap().is_empty());
+
+        // Disk agrees with memory
+        let on_disk = ClientConfigStore::load_from(client_config.to_str().unwrap()).unwrap();
+        assert!(on_disk.get_client("stale").is_none());
+        assert!(on_disk.get_client("fresh").is_some());
+    }
+
+    #[test]
+    fn test_register_client_once_is_idempotent() {
+        let manager = KeyStoreManager::in_memory();
+        manager.generate_server_key_for_client("device-1").unwrap();
+
+        let (first, created) = manager.register_client_once("device-1", &"a".repeat(64)).unwrap();
+        assert!(created);
+        let (again, created) = manager.register_client_once("device-1", &"A".repeat(64)).unwrap();
+        assert!(!created);
+        assert_eq!(again.registered_at, first.registered_at);
+
+        assert!(matches!(
+            manager.register_client_once("device-1", &"b".repeat(64)),
+            Err(KeyStoreError::AlreadyRegistered(_))
+        ));
+        assert_eq!(manager.get_client("device-1").unwrap().client_public_key, "a".repeat(64));
+    }
+
+    #[test]
+    fn test_pending_registrations_listed_and_pruned() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+
+        // One pending and one completed key old enough to prune, written straight to disk
+        let mut keys = ServerKeysStore::default();
+        for id in ["stale", "old-complete"] {
+            let mut entry = ServerKeyEntry::generate(id);
+            entry.created_at = (chrono::Utc::now() - chrono::Duration::days(2)).to_rfc3339();
+            keys.add_key(entry);
+        }
+        keys.save_to(&paths.server_keys()).unwrap();
+
+        let manager = KeyStoreManager::new(&paths).unwrap();
+        manager.register_client("old-complete", &"b".repeat(64)).unwrap();
+        manager.generate_server_key_for_client("fresh").unwrap();
+        manager.generate_server_key_for_client("complete").unwrap();
+        manager.register_client("complete", &"a".repeat(64)).unwrap();
+
+        let pending: Vec<String> = manager.list_pending().into_iter().map(|k| k.client_id).collect();
+        assert_eq!(pending, ["fresh", "stale"]);
+
+        assert_eq!(manager.prune_pending(chrono::Duration::MAX).unwrap(), 0);
+        assert_eq!(manager.prune_pending(chrono::Duration::days(1)).unwrap(), 1);
+        let pending: Vec<String> = manager.list_pending().into_iter().map(|k| k.client_id).collect();
+        assert_eq!(pending, ["fresh"]);
+        assert!(manager.derive_shared_secret("old-complete").is_some());
+
+        let reloaded = KeyStoreManager::new(&paths).unwrap();
+        assert!(reloaded.get_server_key("stale").is_none());
+        assert!(reloaded.get_server_key("fresh").is_some());
+        assert_eq!(reloaded.prune_pending(chrono::Duration::days(1)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_register_clients_batch() {
+        use base64::Engine;
+
+        let dir = tempdir().unwrap();
+        let keys_path = dir.path().join("server_keys.yaml");
+        let clients_path = dir.path().join("client_config.yaml");
+        let manager = KeyStoreManager::load_from(
+            keys_path.to_str().unwrap(),
+            clients_path.to_str().unwrap(),
+        ).unwrap();
+        manager.generate_server_key_for_client("existing").unwrap();
+        manager.register_client("existing", &"a".repeat(64)).unwrap();
+
+        let results = manager.register_clients_batch(vec![
+            ("device-1".to_string(), "b".repeat(64)),
+            ("existing".to_string(), "c".repeat(64)),
+            ("device-2".to_string(), "not-hex".to_string()),
+            ("device-3".to_string(), "d".repeat(64)),
+            ("device-3".to_string(), "e".repeat(64)),
+            ("device-4".to_string(), "0".repeat(64)),
+            ("device-5".to_string(), base64::engine::general_purpose::STANDARD.encode([0xf0u8; 32])),
+            ("device-6".to_string(), "F".repeat(64)),
+        ]);
+
+        assert_eq!(results.len(), 8);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(KeyStoreError::AlreadyRegistered(_))));
+        assert!(matches!(results[2], Err(KeyStoreError::InvalidPublicKey(_))));
+        assert!(results[3].is_ok());
+        // Repeating an id inside the batch counts as already registered
+        assert!(matches!(results[4], Err(KeyStoreError::AlreadyRegistered(_))));
+        // The all-zero key is low order and would give an all-zero shared secret
+        assert!(matches!(results[5], Err(KeyStoreError::InvalidPublicKey(_))));
+        // Other encodings are stored as lowercase hex
+        assert_eq!(results[6].as_ref().unwrap().client_public_key, "f0".repeat(32));
+        assert_eq!(results[7].as_ref().unwrap().client_public_key, "f".repeat(64));
+
+        // Successful entries are on disk with their server keys
+        let reloaded = KeyStoreManager::load_from(
+            keys_path.to_str().unwrap(),
+            clients_path.to_str().unwrap(),
+        ).unwrap();
+        for id in ["device-1", "device-3"] {
+            assert!(reloaded.get_client(id).is_some());
+            assert!(reloaded.derive_shared_secret(id).is_some());
+        }
+        assert_eq!(reloaded.get_client("device-3").unwrap().client_public_key, "d".repeat(64));
+        assert!(reloaded.get_client("device-2").is_none());
+        assert!(reloaded.get_server_key("device-2").is_none());
+    }
+
+    #[test]
+    fn test_bootstrap_client_stores_key_and_client() {
+        let manager = KeyStoreManager::in_memory();
+
+        let (client, server_key) = manager.bootstrap_client("device-1", &"b".repeat(64)).unwrap();
+
+        assert_eq!(client.client_public_key, "b".repeat(64));
+        assert_eq!(manager.get_server_key("device-1").unwrap().public_key, server_key.public_key);
+        assert!(matches!(
+            manager.bootstrap_client("device-1", &"c".repeat(64)),
+            Err(KeyStoreError::AlreadyRegistered(_))
+        ));
+        // A bad key leaves no pending server key behind
+        assert!(manager.bootstrap_client("device-2", "not-hex").is_err());
+        assert!(manager.get_server_key("device-2").is_none());
+    }
+
+    #[test]
+    fn test_bootstrap_client_keeps_pending_server_key() {
+        let manager = KeyStoreManager::in_memory();
+        let pending = manager.generate_server_key_for_client("device-1").unwrap();
+
+        assert!(matches!(
+            manager.bootstrap_client("device-1", &"b".repeat(64)),
+            Err(KeyStoreError::RegistrationPending(_))
+        ));
+        assert_eq!(manager.get_server_key("device-1").unwrap().public_key, pending.public_key);
+        assert!(manager.get_client("device-1").is_none());
+    }
+
+    #[test]
+    fn test_register_clients_batch_rolls_back_on_save_failure() {
+        let dir = tempdir().unwrap();
+        // A file where the data directory should be makes every save fail
+        let blocker = dir.path().join("data");
+        std::fs::write(&blocker, b"").unwrap();
+        let manager = KeyStoreManager::load_from(
+            blocker.join("server_keys.yaml").to_str().unwrap(),
+            blocker.join("client_config.yaml").to_str().unwrap(),
+        ).unwrap();
+
+        let results = manager.register_clients_batch(vec![("device-1".to_string(), "b".repeat(64))]);
+
+        assert!(matches!(results[0], Err(KeyStoreError::Io(_))));
+        assert!(manager.get_client("device-1").is_none());
+        assert!(manager.get_server_key("device-1").is_none());
+    }
+
+    #[test]
+    fn test_master_key_encrypts_secret_keys_at_rest() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let master_key = derive_master_key("correct horse");
+        let client_public = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::random_from_rng(rand::thread_rng()));
+        let client_hex = hex::encode(client_public.as_bytes());
+
+        let manager = KeyStoreManager::with_master_key(&paths, master_key).unwrap();
+        let entry = manager.generate_server_key_for_client("device-1").unwrap();
+        let expected = entry.derive_shared_secret(&client_hex).unwrap();
+
+        // Only the secret is sealed; the public key stays readable
+        let yaml = fs::read_to_string(paths.server_keys()).unwrap();
+        assert!(!yaml.contains(&entry.secret_key.0));
+        assert!(yaml.contains("secret_key: enc:"));
+        assert!(yaml.contains(&entry.public_key));
+
+        let reloaded = KeyStoreManager::with_master_key(&paths, master_key).unwrap();
+        let shared = reloaded.get_server_key("device-1").unwrap().derive_shared_secret(&client_hex);
+        assert_eq!(shared, Some(expected));
+
+        assert!(matches!(KeyStoreManager::new(&paths), Err(KeyStoreError::MasterKeyRequired(_))));
+        assert!(matches!(
+            KeyStoreManager::with_master_key(&paths, derive_master_key("wrong")),
+            Err(KeyStoreError::InvalidMasterKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_master_key_reads_and_migrates_plaintext_secrets() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let entry = KeyStoreManager::new(&paths).unwrap()
+            .generate_server_key_for_client("device-1").unwrap();
+
+        let master_key = derive_master_key("correct horse");
+        let manager = KeyStoreManager::with_master_key(&paths, master_key).unwrap();
+        assert_eq!(manager.get_server_key("device-1").unwrap().secret_key, entry.secret_key);
+
+        // The next save seals entries that were plaintext
+        manager.generate_server_key_for_client("device-2").unwrap();
+        let yaml = fs::read_to_string(paths.server_keys()).unwrap();
+        assert!(!yaml.contains(&entry.secret_key.0));
+        assert_eq!(yaml.matches("secret_key: enc:").count(), 2);
+    }
+
+    #[test]
+    fn test_server_key_entry_validate() {
+        let entry = ServerKeyEntry::generate("good");
+        assert!(entry.validate().is_ok());
+
+        let mut tampered = entry.clone();
+        tampered.public_key = ServerKeyEntry::generate("other").public_key;
+        assert!(matches!(tampered.validate(), Err(KeyStoreError::KeyMismatch(_))));
+    }
+
+    #[test]
+    fn test_inconsistent_keys_skipped_on_load() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let manager = KeyStoreManager::new(&paths).unwrap();
+        manager.generate_server_key_for_client("good").unwrap();
+        let bad = manager.generate_server_key_for_client("bad").unwrap();
+        assert_eq!(manager.validate_all(), vec![("bad".to_string(), true), ("good".to_string(), true)]);
+
+        // Simulate a bad manual edit of the public key
+        let yaml = fs::read_to_string(paths.server_keys()).unwrap();
+        let other = ServerKeyEntry::generate("other").public_key;
+        fs::write(paths.server_keys(), yaml.replace(&bad.public_key, &other)).unwrap();
+
+        let reloaded = KeyStoreManager::new(&paths).unwrap();
+        assert!(reloaded.get_server_key("bad").is_none());
+        assert!(reloaded.get_server_key("good").is_some());
+        assert_eq!(reloaded.validate_all(), vec![("good".to_string(), true)]);
+    }
+
+    #[test]
+    fn test_update_metadata_merges_fields() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let manager = KeyStoreManager::new(&paths).unwrap();
+        manager.generate_server_key_for_client("device-1").unwrap();
+        manager.register_client("device-1", &"a".repeat(64)).unwrap();
+
+        let updated = manager.update_metadata("device-1", ClientMetadata {
+            name: Some("Kitchen tablet".to_string()),
+            device_type: Some("tablet".to_string()),
+            tags: Some(vec!["home".to_string()]),
+        }).unwrap().unwrap();
+        assert_eq!(updated.metadata.name.as_deref(), Some("Kitchen tablet"));
+
+        // Fields left out of an update are kept
+        let updated = manager.update_metadata("device-1", ClientMetadata {
+            name: Some("Hall tablet".to_string()),
+            ..Default::default()
+        }).unwrap().unwrap();
+        assert_eq!(updated.metadata.name.as_deref(), Some("Hall tablet"));
+        assert_eq!(updated.metadata.device_type.as_deref(), Some("tablet"));
+        assert_eq!(updated.metadata.tags, Some(vec!["home".to_string()]));
+
+        let reloaded = KeyStoreManager::new(&paths).unwrap();
+        assert_eq!(reloaded.get_client("device-1").unwrap().metadata, updated.metadata);
+
+        assert!(manager.update_metadata("unknown", ClientMetadata::default()).unwrap().is_none());
+    }
+
+    /// Manager with `ids` registered, each with fresh keys
+    fn manager_with(ids: &[&str]) -> KeyStoreManager {
+        let manager = KeyStoreManager::in_memory();
+        for id in ids {
+            manager.generate_server_key_for_client(id).unwrap();
+            manager.register_client(id, &crate::services::ServerKeyPair::generate().public_key_hex()).unwrap();
+        }
+        manager
+    }
+
+    #[test]
+    fn test_merge_from_adds_and_skips_identical() {
+        let ours = manager_with(&["a"]);
+        let theirs = KeyStoreManager::in_memory();
+        // An id that is the same on both sides is not a conflict
+        theirs.import_bundle(ours.export_bundle()).unwrap();
+        theirs.generate_server_key_for_client("b").unwrap();
+
+        let report = ours.merge_from(&theirs, ConflictPolicy::Fail).unwrap();
+
+        assert_eq!(report, MergeReport { added: 1, replaced: 0, skipped: 1, conflicted: 0, rejected: 0 });
+        assert!(ours.get_server_key("b").is_some());
+    }
+
+    #[test]
+    fn test_merge_from_conflict_policies() {
+        let older = manager_with(&["device-1"]);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let newer = manager_with(&["device-1"]);
+        let older_key = older.get_server_key("device-1").unwrap().public_key;
+        let newer_key = newer.get_server_key("device-1").unwrap().public_key;
+
+        let err = older.merge_from(&newer, ConflictPolicy::Fail).unwrap_err();
+        assert!(matches!(err, KeyStoreError::MergeConflict(id) if id == "device-1"));
+        assert_eq!(older.get_server_key("device-1").unwrap().public_key, older_key);
+
+        let report = older.merge_from(&newer, ConflictPolicy::KeepExisting).unwrap();
+        assert_eq!(report, MergeReport { added: 0, replaced: 0, skipped: 1, conflicted: 1, rejected: 0 });
+        assert_eq!(older.get_server_key("device-1").unwrap().public_key, older_key);
+
+        // The newer side wins in either direction
+        let report = newer.merge_from(&older, ConflictPolicy::PreferNewer).unwrap();
+        assert_eq!(report.replaced, 0);
+        assert_eq!(newer.get_server_key("device-1").unwrap().public_key, newer_key);
+
+        let report = older.merge_from(&newer, ConflictPolicy::PreferNewer).unwrap();
+        assert_eq!(report, MergeReport { added: 0, replaced: 1, skipped: 0, conflicted: 1, rejected: 0 });
+        assert_eq!(older.get_server_key("device-1").unwrap().public_key, newer_key);
+        assert!(older.derive_shared_secret("device-1").is_some());
+    }
+
+    #[test]
+    fn test_merge_from_persists() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let ours = KeyStoreManager::new(&paths).unwrap();
+
+        ours.merge_from(&manager_with(&["device-1", "device-2"]), ConflictPolicy::Fail).unwrap();
+
+        let reloaded = KeyStoreManager::new(&paths).unwrap();
+        assert_eq!(reloaded.list_clients().len(), 2);
+        assert!(reloaded.derive_shared_secret("device-2").is_some());
+    }
+
+    #[test]
+    fn test_merge_from_clamps_far_future_timestamps() {
+        let source = manager_with(&["skewed", "fine"]);
+        let mut bundle = source.export_bundle();
+        let soon = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        for (id, stamp) in [("skewed", "2999-01-01T00:00:00+00:00".to_string()), ("fine", soon.clone())] {
+            let client = bundle.client_config.clients.get_mut(id).unwrap();
+            client.registered_at = stamp.clone();
+            client.last_seen = Some(stamp.clone());
+            bundle.server_keys.keys.get_mut(id).unwrap().created_at = stamp;
+        }
+        let theirs = KeyStoreManager::in_memory();
+        theirs.import_bundle(bundle).unwrap();
+
+        let ours = KeyStoreManager::in_memory();
+        ours.merge_from(&theirs, ConflictPolicy::Fail).unwrap();
+
+        let not_future = |stamp: &str| chrono::DateTime::parse_from_rfc3339(stamp).unwrap() <= chrono::Utc::now();
+        let skewed = ours.get_client("skewed").unwrap();
+        assert!(not_future(&skewed.registered_at));
+        assert!(not_future(skewed.last_seen.as_deref().unwrap()));
+        assert!(not_future(&ours.get_server_key("skewed").unwrap().created_at));
+
+        // Within the skew window, timestamps pass through unchanged
+        let fine = ours.get_client("fine").unwrap();
+        assert_eq!(fine.registered_at, soon);
+        assert_eq!(fine.last_seen, Some(soon.clone()));
+        assert_eq!(ours.get_server_key("fine").unwrap().created_at, soon);
+
+        // A tighter window clamps the same entry
+        let strict = KeyStoreManager::in_memory().with_max_clock_skew(chrono::Duration::minutes(5));
+        strict.merge_from(&theirs, ConflictPolicy::Fail).unwrap();
+        assert_ne!(strict.get_client("fine").unwrap().registered_at, soon);
+    }
+
+    #[test]
+    fn test_merge_from_drops_malformed_entries() {
+        let source = manager_with(&["good", "wrong-key", "bad-client", "no-key", "misfiled"]);
+        source.generate_server_key_for_client("pending").unwrap();
+        let mut bundle = source.export_bundle();
+        bundle.server_keys.keys.get_mut("wrong-key").unwrap().public_key = "ab".repeat(32);
+        bundle.client_config.clients.get_mut("bad-client").unwrap().client_public_key = "abcd".to_string();
+        bundle.server_keys.keys.remove("no-key");
+        bundle.client_config.clients.get_mut("misfiled").unwrap().server_key_id = "good".to_string();
+        // Files edited by hand; importing would already drop the bad entries
+        let dir = tempdir().unwrap();
+        let keys_path = dir.path().join("server_keys.yaml");
+        let clients_path = dir.path().join("client_config.yaml");
+        bundle.server_keys.save_to(keys_path.to_str().unwrap()).unwrap();
+        bundle.client_config.save_to(clients_path.to_str().unwrap()).unwrap();
+        let theirs = KeyStoreManager::load_from(keys_path.to_str().unwrap(), clients_path.to_str().unwrap()).unwrap();
+
+        let ours = KeyStoreManager::in_memory();
+        let report = ours.merge_from(&theirs, ConflictPolicy::Fail).unwrap();
+
+        assert_eq!(report, MergeReport { added: 2, replaced: 0, skipped: 0, conflicted: 0, rejected: 4 });
+        assert!(ours.get_client("good").is_some());
+        // A server key on its own is a pending registration, which is fine
+        assert!(ours.get_server_key("pending").is_some());
+        for id in ["wrong-key", "bad-client", "no-key", "misfiled"] {
+            assert!(ours.get_client(id).is_none(), "{} was merged", id);
+            assert!(ours.get_server_key(id).is_none(), "{} key was merged", id);
+        }
+    }
+
+    #[test]
+    fn test_import_bundle_drops_malformed_entries() {
+        let source = manager_with(&["good", "wrong-key", "bad-client"]);
+        let mut bundle = source.export_bundle();
+        bundle.server_keys.keys.get_mut("wrong-key").unwrap().public_key = "ab".repeat(32);
+        bundle.client_config.clients.get_mut("bad-client").unwrap().client_public_key = "abcd".to_string();
+
+        let target = KeyStoreManager::in_memory();
+        let report = target.import_bundle(bundle).unwrap();
+
+        assert_eq!(report, ImportReport {
+            clients: 1,
+            server_keys: 1,
+            rejected: vec!["bad-client".to_string(), "wrong-key".to_string()],
+        });
+        assert!(target.get_client("good").is_some());
+        assert!(target.get_server_key("wrong-key").is_none());
+        assert!(target.get_client("bad-client").is_none());
+    }
+
+    #[test]
+    fn test_public_keys_export_import_roundtrip() {
+        let dir = tempdir().unwrap();
+        let manager = KeyStoreManager::load_from(
+            dir.path().join("server_keys.yaml").to_str().unwrap(),
+            dir.path().join("client_config.yaml").to_str().unwrap(),
+        ).unwrap();
+        for (id, key) in [("beta", "b"), ("alpha", "a")] {
+            manager.generate_server_key_for_client(id).unwrap();
+            manager.register_client(id, &key.repeat(64)).unwrap();
+        }
+
+        let exported = manager.export_public_keys();
+        assert_eq!(exported, format!("alpha {}\nbeta {}\n", "a".repeat(64), "b".repeat(64)));
+
+        let fresh = KeyStoreManager::in_memory();
+        let results = fresh.import_public_keys(&format!("# exported\n\n{}", exported)).unwrap();
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(fresh.export_public_keys(), exported);
+        for id in ["alpha", "beta"] {
+            assert!(fresh.get_server_key(id).is_some());
+        }
+    }
+
+    #[test]
+    fn test_import_public_keys_normalizes_encodings() {
+        let manager = KeyStoreManager::in_memory();
+        let key = crate::services::ServerKeyPair::generate();
+        let base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, hex::decode(key.public_key_hex()).unwrap());
+        let text = format!("alpha {}\nbeta {}\ngamma {}\n", base64, "AB".repeat(32), "0".repeat(64));
+
+        let results = manager.import_public_keys(&text).unwrap();
+        assert_eq!(results[0].as_ref().unwrap().client_public_key, key.public_key_hex());
+        assert_eq!(results[1].as_ref().unwrap().client_public_key, "ab".repeat(32));
+        assert!(matches!(results[2], Err(KeyStoreError::InvalidPublicKey(_))));
+    }
+
+    #[test]
+    fn test_import_public_keys_rejects_malformed_line() {
+        let manager = KeyStoreManager::in_memory();
+        let text = format!("alpha {}\nbeta\n", "a".repeat(64));
+
+        assert!(matches!(manager.import_public_keys(&text), Err(KeyStoreError::MalformedKeyLine(2))));
+        assert!(manager.list_clients().is_empty());
+    }
+
+    #[test]
+    fn test_broken_entries_reported_on_load() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        let manager = KeyStoreManager::new(&paths).unwrap();
+        for id in ["good", "broken"] {
+            manager.generate_server_key_for_client(id).unwrap();
+            manager.register_client(id, &"a".repeat(64)).unwrap();
+        }
+        assert!(manager.load_issues().is_empty());
+
+        // Break one client entry by hand; the file is still valid YAML
+        let yaml = fs::read_to_string(paths.client_config()).unwrap();
+        let mut root: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        root["clients"]["broken"] = serde_yaml::Value::String("not a client".to_string());
+        fs::write(paths.client_config(), serde_yaml::to_string(&root).unwrap()).unwrap();
+
+        let reloaded = KeyStoreManager::new(&paths).unwrap();
+        assert!(reloaded.get_client("good").is_some());
+        assert!(reloaded.get_client("broken").is_none());
+
+        let issues = reloaded.load_issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].0, std::path::PathBuf::from(paths.client_config()));
+        assert!(issues[0].1.contains("'broken'"));
+    }
+
+    #[test]
+    fn test_unparseable_file_still_fails_load() {
+        let dir = tempdir().unwrap();
+        let paths = crate::config::Paths::new(dir.path());
+        fs::create_dir_all(dir.path()).unwrap();
+        fs::write(paths.client_config(), "clients: [unterminated").unwrap();
+
+        assert!(matches!(KeyStoreManager::new(&paths), Err(KeyStoreError::Serialization(_))));
     }
 }
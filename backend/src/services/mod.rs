@@ -1,45 +1,181 @@
 //! Application services
 
 mod admin;
+mod backup;
+mod challenge;
+mod clock;
 mod crypto;
 mod keystore;
+mod rate_limit;
+mod ratchet;
+mod replay;
+mod server_key;
 mod session;
+mod session_events;
+mod storage;
 
+#[cfg(test)]
+mod admin_test;
+#[cfg(test)]
+mod backup_test;
+#[cfg(test)]
+mod challenge_test;
+#[cfg(test)]
+mod clock_test;
 #[cfg(test)]
 mod crypto_test;
 #[cfg(test)]
 mod keystore_test;
 #[cfg(test)]
+mod rate_limit_test;
+#[cfg(test)]
+mod ratchet_test;
+#[cfg(test)]
+mod replay_test;
+#[cfg(test)]
+mod server_key_test;
+#[cfg(test)]
 mod session_test;
+#[cfg(test)]
+mod session_events_test;
+#[cfg(test)]
+mod storage_test;
 
 use crate::config::Config;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub use admin::AdminAuth;
-pub use crypto::{parse_public_key, CryptoError, EncryptedMessage, ServerKeyPair};
-pub use keystore::{ClientEntry, KeyStoreManager, ServerKeyEntry};
-pub use session::{Session, SessionStore};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use backup::{BackupError, BackupFile};
+pub use challenge::ChallengeStore;
+pub use crypto::{
+    admin_payload_key, base64_len, constant_time_eq, fingerprint, key_confirmation_tag, open_raw, parse_public_key,
+    parse_public_key_with, ratchet_chain_key, ratchet_message_key, ratchet_shared_secret, seal_raw, ClientKeyPair, CryptoError,
+    EncryptedMessage, EncryptedStream, KeyEncoding, NonceSequence, PublicKeyBytes, SecretKeyBytes, ServerKeyPair, SharedSecret,
+    SignedEncryptedMessage, MAX_STREAM_CHUNK_SIZE,
+};
+pub use keystore::{ClientEntry, ClientMetadata, ConflictPolicy, ImportReport, KeyStoreError, KeyStoreManager, MergeReport};
+pub use rate_limit::RateLimiter;
+pub use ratchet::{RatchetChain, RatchetError, RatchetStore};
+pub use replay::ReplayGuard;
+pub use server_key::ServerKeyRing;
+pub use session::{Session, SessionStats, SessionStore};
+pub use session_events::{
+    FileEventSink, MemoryEventSink, NoopEventSink, SessionEvent, SessionEventKind, SessionEventSink,
+    DEFAULT_EVENT_LOG_MAX_BYTES,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
     pub sessions: SessionStore,
-    pub server_keypair: Arc<ServerKeyPair>,
+    /// Default keypair for `/keys/*`; per-client keys live in `keystore`
+    pub server_key: ServerKeyRing,
     pub keystore: KeyStoreManager,
     pub admin: AdminAuth,
+    pub replay_guard: ReplayGuard,
+    /// Chains started by `/keys/ratchet` for ratcheted `/keys/send` messages
+    pub ratchets: RatchetStore,
+    /// Outstanding `/register/init` challenges
+    pub challenges: ChallengeStore,
+    pub register_limiter: RateLimiter,
+    /// Set once startup has finished; gates the readiness probe
+    ready: Arc<AtomicBool>,
 }
 
 impl AppState {
-    pub fn new(config: Config) -> Self {
-        let server_keypair = Arc::new(ServerKeyPair::generate());
-        let admin = AdminAuth::new(&server_keypair.public_key_hex());
+    pub fn new(config: Config) -> Result<Self, KeyStoreError> {
+        let master_key = config.master_key.as_deref().map(keystore::derive_master_key);
+        let server_key = ServerKeyRing::load_or_generate(
+            &config.paths.server_key(),
+            master_key,
+            Duration::from_secs(config.server_key_grace_secs),
+        )?;
+        let admin = AdminAuth::new(&config.paths.admin_config(), &server_key.current().public_key_hex());
+        let register_limiter = RateLimiter::per_minute(config.register_rate_per_min);
+        let keystore = match master_key {
+            Some(master_key) => KeyStoreManager::with_master_key(&config.paths, master_key)?,
+            None => KeyStoreManager::new(&config.paths)?,
+        };
+        let mut sessions = SessionStore::with_secret(&config.secret_key);
+        if let Some(max_lifetime) = config.session_max_lifetime_secs {
+            sessions = sessions.with_sliding_renewal(max_lifetime);
+        }
+        if config.session_event_log {
+            sessions = sessions.with_event_sink(FileEventSink::new(
+                config.paths.session_events(),
+                config.session_event_log_max_bytes,
+            ));
+        }
         
-        Self {
+        Ok(Self {
             config: Arc::new(config),
+            sessions,
+            server_key,
+            keystore,
+            admin,
+            replay_guard: ReplayGuard::default(),
+            ratchets: RatchetStore::default(),
+            challenges: ChallengeStore::default(),
+            register_limiter,
+            ready: Arc::default(),
+        })
+    }
+
+    /// Mark startup as finished so `/ready` can report success
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State backed by files under `dir`, for handler tests
+    pub fn for_tests(dir: &std::path::Path) -> Self {
+        let server_keypair = ServerKeyPair::generate();
+        let (admin_config, _) = admin::AdminConfig::generate(&server_keypair.public_key_hex());
+        let admin = AdminAuth::from_config(admin_config);
+        let paths = crate::config::Paths::new(dir);
+        let keystore = KeyStoreManager::new(&paths).unwrap();
+
+        Self {
+            config: Arc::new(Config {
+                port: 0,
+                secret_key: "test-secret".to_string(),
+                session_ttl_secs: 3600,
+                admin_session_ttl_secs: 24 * 3600,
+                session_max_lifetime_secs: None,
+                register_rate_per_min: 60,
+                tls: None,
+                paths,
+                audit_log: None,
+                max_ciphertext_len: crate::services::crypto::DEFAULT_MAX_CIPHERTEXT_LEN,
+                max_backup_len: 64 * 1024 * 1024,
+                master_key: None,
+                cors_allowed_origins: Vec::new(),
+                bind_sessions_to_ip: false,
+                server_key_grace_secs: 300,
+                compress_responses: true,
+                compression_min_size: crate::compression::DEFAULT_MIN_SIZE,
+                bind_addrs: Vec::new(),
+                session_event_log: false,
+                session_event_log_max_bytes: DEFAULT_EVENT_LOG_MAX_BYTES,
+            }),
             sessions: SessionStore::new(),
-            server_keypair,
-            keystore: KeyStoreManager::new(),
+            server_key: ServerKeyRing::in_memory(server_keypair, Duration::from_secs(300)),
+            keystore,
             admin,
+            replay_guard: ReplayGuard::default(),
+            ratchets: RatchetStore::default(),
+            challenges: ChallengeStore::default(),
+            register_limiter: RateLimiter::per_minute(60),
+            ready: Arc::default(),
         }
     }
 }
@@ -0,0 +1,231 @@
+//! Per-message keys for the encrypted channel
+//!
+//! A chain is rooted in the static X25519 shared secret mixed with DH against
+//! a server ephemeral key that is discarded once the root is derived, then
+//! stepped once per message with [`ratchet_chain_key`]. A later leak of the
+//! server's long-term key alone doesn't open ratcheted traffic; the client's
+//! key does, since both DH halves use it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::crypto::{ratchet_chain_key, ratchet_message_key, CryptoError, SharedSecret};
+
+/// How far ahead of the next expected index a message may be
+pub const DEFAULT_RATCHET_WINDOW: u64 = 32;
+
+/// Default number of clients whose chains are kept
+pub const DEFAULT_RATCHET_CAPACITY: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RatchetError {
+    #[error("Ratchet index {index} is more than {window} ahead of {next}")]
+    TooFarAhead { index: u64, next: u64, window: u64 },
+    #[error("Ratchet index {0} was already used or has been dropped")]
+    KeyUsed(u64),
+    #[error("Unknown or expired ratchet; start a new one")]
+    UnknownChain,
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// One side's view of a ratchet chain.
+///
+/// Message keys for indexes that were skipped over are cached (at most
+/// `window` of them), so messages arriving out of order still open once.
+#[derive(Clone)]
+pub struct RatchetChain {
+    chain_key: SharedSecret,
+    /// Index the current chain key belongs to
+    next_index: u64,
+    skipped: BTreeMap<u64, SharedSecret>,
+    window: u64,
+}
+
+impl RatchetChain {
+    pub fn new(shared_secret: &SharedSecret) -> Self {
+        Self::with_window(shared_secret, DEFAULT_RATCHET_WINDOW)
+    }
+
+    pub fn with_window(shared_secret: &SharedSecret, window: u64) -> Self {
+        Self {
+            chain_key: *shared_secret,
+            next_index: 0,
+            skipped: BTreeMap::new(),
+            window,
+        }
+    }
+
+    /// Index the next sent message will use
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    /// Key and index for the next outgoing message
+    pub fn next_send_key(&mut self) -> (u64, SharedSecret) {
+        let index = self.next_index;
+        (index, self.step())
+    }
+
+    /// Key for an incoming message at `index`; each index yields a key once
+    pub fn key_for(&mut self, index: u64) -> Result<SharedSecret, RatchetError> {
+        if index < self.next_index {
+            return self.skipped.remove(&index).ok_or(RatchetError::KeyUsed(index));
+        }
+        if index - self.next_index > self.window {
+            return Err(RatchetError::TooFarAhead { index, next: self.next_index, window: self.window });
+        }
+        while self.next_index < index {
+            let skipped_index = self.next_index;
+            let key = self.step();
+            self.skipped.insert(skipped_index, key);
+        }
+        // Forget the oldest skipped keys once the cache is full
+        while self.skipped.len() as u64 > self.window {
+            self.skipped.pop_first();
+        }
+        Ok(self.step())
+    }
+
+    /// The key [`RatchetChain::key_for`] would return for `index`, without
+    /// moving the chain
+    pub fn peek_key(&self, index: u64) -> Result<SharedSecret, RatchetError> {
+        if index < self.next_index {
+            return self.skipped.get(&index).copied().ok_or(RatchetError::KeyUsed(index));
+        }
+        if index - self.next_index > self.window {
+            return Err(RatchetError::TooFarAhead { index, next: self.next_index, window: self.window });
+        }
+        let mut chain_key = self.chain_key;
+        for _ in self.next_index..index {
+            chain_key = ratchet_chain_key(&chain_key);
+        }
+        Ok(ratchet_message_key(&chain_key))
+    }
+
+    /// Message key for the current index, then move the chain on
+    fn step(&mut self) -> SharedSecret {
+        let message_key = ratchet_message_key(&self.chain_key);
+        self.chain_key = ratchet_chain_key(&self.chain_key);
+        self.next_index += 1;
+        message_key
+    }
+}
+
+struct ChainSlot {
+    client_id: String,
+    chain: Arc<Mutex<RatchetChain>>,
+    last_used: u64,
+}
+
+struct RatchetState {
+    /// ratchet key -> owning client's chain
+    chains: HashMap<String, ChainSlot>,
+    tick: u64,
+}
+
+/// Server-side ratchet chains, keyed by the ephemeral public key each was
+/// started with.
+///
+/// Chains only exist in memory and are never recreated from their root: past
+/// `capacity` chains the least recently used is dropped, and after that (or a
+/// restart) its messages are refused and the client has to start a new one.
+/// Each chain has its own lock, so messages on different chains open in
+/// parallel.
+#[derive(Clone)]
+pub struct RatchetStore {
+    state: Arc<Mutex<RatchetState>>,
+    capacity: usize,
+    window: u64,
+}
+
+impl RatchetStore {
+    pub fn new(capacity: usize, window: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RatchetState {
+                chains: HashMap::new(),
+                tick: 0,
+            })),
+            capacity: capacity.max(1),
+            window,
+        }
+    }
+
+    /// Start a chain for `client_id` from `root`, under `ratchet_key`.
+    ///
+    /// Returns false if `ratchet_key` was already used; a root is only ever
+    /// taken once, so its chain can't be wound back to index 0.
+    pub fn start(&self, client_id: &str, ratchet_key: &str, root: &SharedSecret) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.chains.contains_key(ratchet_key) {
+            return false;
+        }
+        state.tick += 1;
+        let tick = state.tick;
+
+        if state.chains.len() >= self.capacity {
+            let oldest = state.chains.iter()
+                .min_by_key(|(_, slot)| slot.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.chains.remove(&oldest);
+            }
+        }
+        let slot = ChainSlot {
+            client_id: client_id.to_string(),
+            chain: Arc::new(Mutex::new(RatchetChain::with_window(root, self.window))),
+            last_used: tick,
+        };
+        state.chains.insert(ratchet_key.to_string(), slot);
+        true
+    }
+
+    /// The chain under `ratchet_key` if `client_id` owns it, marked as used
+    fn chain(&self, client_id: &str, ratchet_key: &str) -> Result<Arc<Mutex<RatchetChain>>, RatchetError> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        match state.chains.get_mut(ratchet_key) {
+            Some(slot) if slot.client_id == client_id => {
+                slot.last_used = tick;
+                Ok(slot.chain.clone())
+            }
+            _ => Err(RatchetError::UnknownChain),
+        }
+    }
+
+    /// Open message `index` from `client_id` on the chain under `ratchet_key`.
+    ///
+    /// `decrypt` runs without any lock held, and the chain only moves if it
+    /// succeeds, so forged messages can't burn keys or push the chain out of
+    /// the window. If two messages race for one index, only the first to
+    /// finish is accepted. Returns the plaintext and the message key, which
+    /// the reply is sealed under.
+    pub fn open<T>(
+        &self,
+        client_id: &str,
+        ratchet_key: &str,
+        index: u64,
+        decrypt: impl FnOnce(&SharedSecret) -> Result<T, CryptoError>,
+    ) -> Result<(T, SharedSecret), RatchetError> {
+        let chain = self.chain(client_id, ratchet_key)?;
+        let key = chain.lock().unwrap().peek_key(index)?;
+        let plaintext = decrypt(&key)?;
+
+        chain.lock().unwrap().key_for(index)?;
+        Ok((plaintext, key))
+    }
+
+    /// Next index expected on a chain, if it is still kept
+    pub fn next_index(&self, ratchet_key: &str) -> Option<u64> {
+        let chain = self.state.lock().unwrap().chains.get(ratchet_key)?.chain.clone();
+        let next = chain.lock().unwrap().next_index();
+        Some(next)
+    }
+}
+
+impl Default for RatchetStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATCHET_CAPACITY, DEFAULT_RATCHET_WINDOW)
+    }
+}
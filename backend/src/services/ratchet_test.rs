@@ -0,0 +1,160 @@
+//! Tests for ratchet module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::crypto::*;
+    use crate::services::ratchet::*;
+
+    fn secret() -> SharedSecret {
+        SharedSecret::from([7u8; 32])
+    }
+
+    #[test]
+    fn test_in_order_keys_match_and_change() {
+        let mut sender = RatchetChain::new(&secret());
+        let mut receiver = RatchetChain::new(&secret());
+
+        let mut seen = Vec::new();
+        for expected in 0..5 {
+            let (index, key) = sender.next_send_key();
+            assert_eq!(index, expected);
+            assert_eq!(receiver.key_for(index).unwrap().as_bytes(), key.as_bytes());
+            seen.push(key.to_bytes());
+        }
+        seen.dedup();
+        assert_eq!(seen.len(), 5);
+        // No message is encrypted under the shared secret itself
+        assert!(!seen.contains(secret().as_bytes()));
+    }
+
+    #[test]
+    fn test_chain_step_is_hkdf_of_previous() {
+        let mut chain = RatchetChain::new(&secret());
+        let (_, first) = chain.next_send_key();
+        let (_, second) = chain.next_send_key();
+
+        assert_eq!(first.as_bytes(), ratchet_message_key(&secret()).as_bytes());
+        assert_eq!(second.as_bytes(), ratchet_message_key(&ratchet_chain_key(&secret())).as_bytes());
+    }
+
+    #[test]
+    fn test_out_of_order_within_window() {
+        let mut sender = RatchetChain::new(&secret());
+        let keys: Vec<_> = (0..4).map(|_| sender.next_send_key().1).collect();
+        let mut receiver = RatchetChain::with_window(&secret(), 4);
+
+        // 3 arrives first; 0..=2 are cached and each opens exactly once
+        assert_eq!(receiver.key_for(3).unwrap().as_bytes(), keys[3].as_bytes());
+        assert_eq!(receiver.key_for(1).unwrap().as_bytes(), keys[1].as_bytes());
+        assert_eq!(receiver.key_for(0).unwrap().as_bytes(), keys[0].as_bytes());
+        assert!(matches!(receiver.key_for(1), Err(RatchetError::KeyUsed(1))));
+        assert!(matches!(receiver.key_for(3), Err(RatchetError::KeyUsed(3))));
+        assert_eq!(receiver.key_for(2).unwrap().as_bytes(), keys[2].as_bytes());
+        assert_eq!(receiver.next_index(), 4);
+    }
+
+    #[test]
+    fn test_window_bounds_skips_and_cache() {
+        let mut receiver = RatchetChain::with_window(&secret(), 4);
+
+        assert!(matches!(receiver.key_for(5), Err(RatchetError::TooFarAhead { index: 5, next: 0, .. })));
+        assert_eq!(receiver.next_index(), 0);
+
+        // Jumping twice leaves 8 skipped keys; only the newest 4 are kept
+        receiver.key_for(4).unwrap();
+        receiver.key_for(9).unwrap();
+        assert!(matches!(receiver.key_for(0), Err(RatchetError::KeyUsed(0))));
+        assert!(matches!(receiver.key_for(3), Err(RatchetError::KeyUsed(3))));
+        assert!(receiver.key_for(5).is_ok());
+        assert!(receiver.key_for(8).is_ok());
+    }
+
+    #[test]
+    fn test_store_only_advances_on_successful_decrypt() {
+        let store = RatchetStore::default();
+        assert!(store.start("client-1", "ratchet-1", &secret()));
+        let mut client = RatchetChain::new(&secret());
+        let (index, key) = client.next_send_key();
+        let message = EncryptedMessage::encrypt(b"hello", &key).unwrap();
+
+        // A forged message doesn't burn the index
+        let forged = EncryptedMessage::encrypt(b"forged", &SharedSecret::from([1u8; 32])).unwrap();
+        assert!(matches!(
+            store.open("client-1", "ratchet-1", index, |k| forged.decrypt(k)),
+            Err(RatchetError::Crypto(_))
+        ));
+        assert_eq!(store.next_index("ratchet-1"), Some(0));
+
+        let (plaintext, reply_key) = store.open("client-1", "ratchet-1", index, |k| message.decrypt(k)).unwrap();
+        assert_eq!(plaintext, b"hello");
+        assert_eq!(reply_key.as_bytes(), key.as_bytes());
+        assert_eq!(store.next_index("ratchet-1"), Some(1));
+        assert!(matches!(
+            store.open("client-1", "ratchet-1", index, |k| message.decrypt(k)),
+            Err(RatchetError::KeyUsed(0))
+        ));
+    }
+
+    #[test]
+    fn test_store_chains_belong_to_their_client() {
+        let store = RatchetStore::default();
+        assert!(store.start("client-1", "ratchet-1", &secret()));
+
+        assert!(matches!(store.open("client-2", "ratchet-1", 0, |_| Ok(())), Err(RatchetError::UnknownChain)));
+        assert!(matches!(store.open("client-1", "ratchet-2", 0, |_| Ok(())), Err(RatchetError::UnknownChain)));
+        assert_eq!(store.next_index("ratchet-1"), Some(0));
+    }
+
+    #[test]
+    fn test_store_eviction_does_not_restart_chain() {
+        let store = RatchetStore::new(2, DEFAULT_RATCHET_WINDOW);
+        let open = |ratchet: &str| store.open("client-1", ratchet, 0, |_| Ok(())).map(|_| ());
+
+        assert!(store.start("client-1", "a", &secret()));
+        assert!(store.start("client-1", "b", &secret()));
+        open("a").unwrap();
+        assert!(store.start("client-1", "c", &secret()));
+
+        // "b" was least recently used; its index 0 can't be opened again
+        assert_eq!(store.next_index("b"), None);
+        assert!(matches!(open("b"), Err(RatchetError::UnknownChain)));
+        assert!(matches!(open("a"), Err(RatchetError::KeyUsed(0))));
+        open("c").unwrap();
+
+        // Nor can a root be reused for a chain that is still kept
+        assert!(!store.start("client-1", "a", &secret()));
+        assert_eq!(store.next_index("a"), Some(1));
+    }
+
+    #[test]
+    fn test_peek_key_matches_key_for_without_moving() {
+        let mut chain = RatchetChain::new(&secret());
+        let peeked = chain.peek_key(3).unwrap();
+        assert_eq!(chain.next_index(), 0);
+        assert_eq!(chain.key_for(3).unwrap().as_bytes(), peeked.as_bytes());
+
+        let skipped = chain.peek_key(1).unwrap();
+        assert_eq!(chain.key_for(1).unwrap().as_bytes(), skipped.as_bytes());
+        assert!(matches!(chain.peek_key(1), Err(RatchetError::KeyUsed(1))));
+    }
+
+    #[test]
+    fn test_store_decrypts_outside_the_lock() {
+        let store = RatchetStore::default();
+        assert!(store.start("client-1", "ratchet-1", &secret()));
+        assert!(store.start("client-2", "ratchet-2", &secret()));
+
+        // Another chain, and this one, stay usable while decrypt runs
+        let result = store.open("client-1", "ratchet-1", 0, |_| {
+            store.open("client-2", "ratchet-2", 0, |_| Ok(())).unwrap();
+            assert_eq!(store.next_index("ratchet-1"), Some(0));
+            // A racing message for the same index finishes first
+            store.open("client-1", "ratchet-1", 0, |_| Ok(())).unwrap();
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(RatchetError::KeyUsed(0))));
+        assert_eq!(store.next_index("ratchet-1"), Some(1));
+        assert_eq!(store.next_index("ratchet-2"), Some(1));
+    }
+}
@@ -0,0 +1,69 @@
+//! Token-bucket rate limiting keyed by client IP
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of tracked IPs above which refilled buckets are swept
+const SWEEP_THRESHOLD: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Allows `per_minute` requests per IP, refilled continuously, with bursts
+/// of up to `per_minute` requests.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        let capacity = per_minute.max(1) as f64;
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            capacity,
+            refill_per_sec: capacity / 60.0,
+        }
+    }
+
+    /// Take a token for `ip`, or return how long to wait before retrying
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.check_at(ip, Instant::now())
+    }
+
+    pub fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let capacity = self.capacity;
+        let refill = self.refill_per_sec;
+
+        // Full buckets carry no state, so drop them to keep the map bounded
+        if buckets.len() >= SWEEP_THRESHOLD {
+            buckets.retain(|_, b| {
+                b.tokens + now.saturating_duration_since(b.updated).as_secs_f64() * refill < capacity
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / refill))
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Tests for rate_limit module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::rate_limit::*;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    const IP_A: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const IP_B: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn test_allows_up_to_limit() {
+        let limiter = RateLimiter::per_minute(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(IP_A, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_blocks_over_limit_with_retry_after() {
+        let limiter = RateLimiter::per_minute(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at(IP_A, now).unwrap();
+        }
+        let retry_after = limiter.check_at(IP_A, now).unwrap_err();
+
+        // One token refills every 20 seconds at 3 per minute
+        assert_eq!(retry_after.as_secs(), 20);
+    }
+
+    #[test]
+    fn test_tokens_refill_over_time() {
+        let limiter = RateLimiter::per_minute(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at(IP_A, now).unwrap();
+        }
+        assert!(limiter.check_at(IP_A, now + Duration::from_secs(10)).is_err());
+        assert!(limiter.check_at(IP_A, now + Duration::from_secs(21)).is_ok());
+    }
+
+    #[test]
+    fn test_ips_have_separate_buckets() {
+        let limiter = RateLimiter::per_minute(1);
+        let now = Instant::now();
+
+        assert!(limiter.check_at(IP_A, now).is_ok());
+        assert!(limiter.check_at(IP_A, now).is_err());
+        assert!(limiter.check_at(IP_B, now).is_ok());
+    }
+}
@@ -0,0 +1,138 @@
+//! Replay protection for encrypted messages
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Default number of clients whose last sequence number is remembered
+pub const DEFAULT_REPLAY_CAPACITY: usize = 10_000;
+
+struct ReplayState {
+    /// Registered client key -> last accepted sequence; not evicted while registered
+    registered: HashMap<String, u64>,
+    /// Any other key -> (last accepted sequence, last use tick)
+    last_seen: HashMap<String, (u64, u64)>,
+    /// Last use tick -> key in `last_seen`, oldest first
+    by_tick: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl ReplayState {
+    /// Put `key` in the bounded map as just used, evicting the least
+    /// recently used key if it is full
+    fn mark_seen(&mut self, key: &str, sequence: u64, capacity: usize) {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.last_seen.insert(key.to_string(), (sequence, tick)) {
+            Some((_, used)) => {
+                self.by_tick.remove(&used);
+            }
+            None if self.last_seen.len() > capacity => {
+                if let Some((_, oldest)) = self.by_tick.pop_first() {
+                    self.last_seen.remove(&oldest);
+                }
+            }
+            None => {}
+        }
+        self.by_tick.insert(tick, key.to_string());
+    }
+
+    /// Take `key` out of the bounded map, returning its last sequence
+    fn take_seen(&mut self, key: &str) -> Option<u64> {
+        let (last, used) = self.last_seen.remove(key)?;
+        self.by_tick.remove(&used);
+        Some(last)
+    }
+}
+
+/// Tracks the last accepted sequence number per client.
+///
+/// A message is only accepted if its sequence is strictly greater than the
+/// last one seen for that client. Registered clients are kept while they
+/// stay registered, so nobody can evict their marks by sending from fresh
+/// keys. Other keys share a bounded map: once `capacity` of them are
+/// tracked, the least recently used one is forgotten.
+#[derive(Clone)]
+pub struct ReplayGuard {
+    state: Arc<Mutex<ReplayState>>,
+    capacity: usize,
+}
+
+impl ReplayGuard {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                registered: HashMap::new(),
+                last_seen: HashMap::new(),
+                by_tick: BTreeMap::new(),
+                tick: 0,
+            })),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Record `sequence` for `client_id`, returning false if it is a replay
+    pub fn check(&self, client_id: &str, sequence: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if let Some(last) = state.registered.get_mut(client_id) {
+            return advance(last, sequence);
+        }
+        if state.last_seen.get(client_id).is_some_and(|(last, _)| sequence <= *last) {
+            return false;
+        }
+        state.mark_seen(client_id, sequence, self.capacity);
+        true
+    }
+
+    /// Like [`Self::check`] for a registered client, whose mark is kept
+    /// outside the bounded map from then on
+    pub fn check_registered(&self, client_id: &str, sequence: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let seen = state.take_seen(client_id);
+        match state.registered.get_mut(client_id) {
+            Some(last) => advance(last, sequence),
+            None => {
+                state.registered.insert(client_id.to_string(), seen.map_or(sequence, |last| last.max(sequence)));
+                seen.is_none_or(|last| sequence > last)
+            }
+        }
+    }
+
+    /// Move the marks of keys that are no longer registered into the bounded
+    /// map, where they age out like any other key's.
+    ///
+    /// Call after clients are removed. The mark is kept rather than dropped
+    /// so that captured messages can't be replayed right after removal.
+    pub fn retain_registered(&self, is_registered: impl Fn(&str) -> bool) {
+        let mut state = self.state.lock().unwrap();
+        let removed: Vec<(String, u64)> = state.registered.iter()
+            .filter(|(key, _)| !is_registered(key))
+            .map(|(key, last)| (key.clone(), *last))
+            .collect();
+        for (key, last) in removed {
+            state.registered.remove(&key);
+            state.mark_seen(&key, last, self.capacity);
+        }
+    }
+
+    /// Last accepted sequence for a client, if tracked
+    pub fn last_sequence(&self, client_id: &str) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        state.registered.get(client_id).copied()
+            .or_else(|| state.last_seen.get(client_id).map(|(seq, _)| *seq))
+    }
+}
+
+/// Move `last` up to `sequence` if it is newer
+fn advance(last: &mut u64, sequence: u64) -> bool {
+    if sequence <= *last {
+        return false;
+    }
+    *last = sequence;
+    true
+}
+
+impl Default for ReplayGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_CAPACITY)
+    }
+}
@@ -0,0 +1,85 @@
+//! Tests for replay module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::replay::*;
+
+    #[test]
+    fn test_accepts_increasing_sequences() {
+        let guard = ReplayGuard::default();
+
+        assert!(guard.check("client-1", 1));
+        assert!(guard.check("client-1", 2));
+        assert!(guard.check("client-1", 10));
+        assert_eq!(guard.last_sequence("client-1"), Some(10));
+    }
+
+    #[test]
+    fn test_rejects_replayed_sequence() {
+        let guard = ReplayGuard::default();
+
+        assert!(guard.check("client-1", 5));
+        assert!(!guard.check("client-1", 5));
+        assert!(!guard.check("client-1", 4));
+        assert_eq!(guard.last_sequence("client-1"), Some(5));
+    }
+
+    #[test]
+    fn test_clients_are_tracked_independently() {
+        let guard = ReplayGuard::default();
+
+        assert!(guard.check("client-1", 5));
+        assert!(guard.check("client-2", 1));
+    }
+
+    #[test]
+    fn test_capacity_evicts_least_recently_used() {
+        let guard = ReplayGuard::new(2);
+
+        assert!(guard.check("client-1", 1));
+        assert!(guard.check("client-2", 1));
+        assert!(guard.check("client-1", 2));
+        // client-2 is now the least recently used
+        assert!(guard.check("client-3", 1));
+
+        assert_eq!(guard.last_sequence("client-1"), Some(2));
+        assert_eq!(guard.last_sequence("client-2"), None);
+        assert_eq!(guard.last_sequence("client-3"), Some(1));
+    }
+
+    #[test]
+    fn test_flooding_cannot_evict_registered_client() {
+        let guard = ReplayGuard::new(2);
+
+        // Seen before registering; the earlier mark carries over
+        assert!(guard.check("victim", 5));
+        assert!(!guard.check_registered("victim", 5));
+        assert!(guard.check_registered("victim", 6));
+
+        for i in 0..10 {
+            assert!(guard.check(&format!("flood-{}", i), 1));
+        }
+
+        assert_eq!(guard.last_sequence("victim"), Some(6));
+        assert!(!guard.check_registered("victim", 6));
+        assert!(!guard.check("victim", 6));
+        assert_eq!(guard.last_sequence("flood-0"), None);
+    }
+
+    #[test]
+    fn test_removed_client_mark_ages_out() {
+        let guard = ReplayGuard::new(2);
+        assert!(guard.check_registered("kept", 1));
+        assert!(guard.check_registered("removed", 7));
+
+        guard.retain_registered(|key| key == "kept");
+
+        // Still no replays right after removal
+        assert!(!guard.check("removed", 7));
+        for i in 0..2 {
+            assert!(guard.check(&format!("flood-{}", i), 1));
+        }
+        assert_eq!(guard.last_sequence("removed"), None);
+        assert_eq!(guard.last_sequence("kept"), Some(1));
+    }
+}
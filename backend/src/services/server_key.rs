@@ -0,0 +1,132 @@
+//! The default server keypair used by `/keys/*`, rotatable at runtime
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use super::crypto::{Encrypted, SecretKeyBytes, ServerKeyPair};
+use super::keystore::{sealed_secret_error, KeyStoreError};
+use super::storage::atomic_write;
+
+/// Name used for the default key in key store errors
+const KEY_NAME: &str = "default";
+
+/// On-disk form of the default keypair (server_key.yaml)
+#[derive(Serialize, Deserialize)]
+struct StoredServerKey {
+    /// Hex X25519 secret, sealed when a master key is set
+    secret_key: Encrypted<String>,
+    created_at: String,
+}
+
+struct KeyRingState {
+    current: Arc<ServerKeyPair>,
+    /// Key replaced by the last rotation and when it was replaced
+    previous: Option<(Arc<ServerKeyPair>, Instant)>,
+}
+
+/// The current default keypair plus, for a grace period after rotation, the
+/// one it replaced
+#[derive(Clone)]
+pub struct ServerKeyRing {
+    state: Arc<RwLock<KeyRingState>>,
+    grace: Duration,
+    /// Backing file; `None` keeps the key in memory only
+    path: Option<String>,
+    master_key: Option<Arc<[u8; 32]>>,
+}
+
+impl ServerKeyRing {
+    /// Key ring over a fixed keypair that is never written to disk
+    pub fn in_memory(keypair: ServerKeyPair, grace: Duration) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(KeyRingState {
+                current: Arc::new(keypair),
+                previous: None,
+            })),
+            grace,
+            path: None,
+            master_key: None,
+        }
+    }
+
+    /// Load the keypair from `path`, generating and saving one if it is missing.
+    /// The secret is sealed under `master_key` when one is given.
+    pub fn load_or_generate(path: &str, master_key: Option<[u8; 32]>, grace: Duration) -> Result<Self, KeyStoreError> {
+        let mut ring = Self::in_memory(ServerKeyPair::generate(), grace);
+        ring.path = Some(path.to_string());
+        ring.master_key = master_key.map(Arc::new);
+
+        if Path::new(path).exists() {
+            let value: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+            let was_sealed = value.get("secret_key").and_then(serde_yaml::Value::as_str).is_some_and(Encrypted::<String>::is_sealed);
+            let stored: StoredServerKey = Encrypted::<String>::with_master_key(master_key, || {
+                serde_yaml::from_value(value.clone())
+                    .map_err(|e| sealed_secret_error(KEY_NAME, value.get("secret_key"), master_key.is_some()).unwrap_or(e.into()))
+            })?;
+            let keypair = Self::open(&stored.secret_key.0)?;
+            ring.state.write().unwrap().current = Arc::new(keypair);
+            // A key saved before the master key was configured gets sealed now
+            if ring.master_key.is_some() && !was_sealed {
+                ring.save(&ring.current())?;
+            }
+        } else {
+            ring.save(&ring.current())?;
+        }
+        Ok(ring)
+    }
+
+    /// Keypair handed out for new exchanges
+    pub fn current(&self) -> Arc<ServerKeyPair> {
+        self.state.read().unwrap().current.clone()
+    }
+
+    /// Keys to try for incoming traffic: the current one first, then the
+    /// previous one while it is inside the grace window
+    pub fn accepted(&self) -> Vec<Arc<ServerKeyPair>> {
+        let state = self.state.read().unwrap();
+        let mut keys = vec![state.current.clone()];
+        if let Some((previous, rotated_at)) = &state.previous {
+            if rotated_at.elapsed() < self.grace {
+                keys.push(previous.clone());
+            }
+        }
+        keys
+    }
+
+    /// Replace the current keypair with a fresh one and persist it.
+    ///
+    /// If saving fails the old key stays current. On success it remains
+    /// accepted for the grace period, then only the new key works.
+    pub fn rotate(&self) -> Result<Arc<ServerKeyPair>, KeyStoreError> {
+        let mut state = self.state.write().unwrap();
+        let next = Arc::new(ServerKeyPair::generate());
+        self.save(&next)?;
+
+        let replaced = std::mem::replace(&mut state.current, next.clone());
+        state.previous = Some((replaced, Instant::now()));
+        Ok(next)
+    }
+
+    fn save(&self, keypair: &ServerKeyPair) -> Result<(), KeyStoreError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let stored = StoredServerKey {
+            secret_key: Encrypted(hex::encode(keypair.secret_bytes())),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let yaml = Encrypted::<String>::with_master_key(self.master_key.as_deref().copied(), || serde_yaml::to_string(&stored))?;
+        atomic_write(path, yaml)?;
+        Ok(())
+    }
+
+    fn open(secret_hex: &str) -> Result<ServerKeyPair, KeyStoreError> {
+        let secret = hex::decode(secret_hex.trim()).ok()
+            .and_then(|bytes| SecretKeyBytes::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| KeyStoreError::InvalidSecretKey(KEY_NAME.to_string()))?;
+        Ok(ServerKeyPair::from_secret(&secret))
+    }
+}
@@ -0,0 +1,80 @@
+//! Tests for server_key module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::keystore::KeyStoreError;
+    use crate::services::server_key::*;
+    use crate::services::ServerKeyPair;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    const GRACE: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn test_key_persists_across_loads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("server_key.yaml");
+        let path = path.to_str().unwrap();
+
+        let first = ServerKeyRing::load_or_generate(path, None, GRACE).unwrap();
+        let second = ServerKeyRing::load_or_generate(path, None, GRACE).unwrap();
+        assert_eq!(first.current().public_key_hex(), second.current().public_key_hex());
+
+        let rotated = first.rotate().unwrap();
+        let third = ServerKeyRing::load_or_generate(path, None, GRACE).unwrap();
+        assert_eq!(third.current().public_key_hex(), rotated.public_key_hex());
+    }
+
+    #[test]
+    fn test_sealed_key_needs_master_key() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("server_key.yaml");
+        let path = path.to_str().unwrap();
+        let master_key = [3u8; 32];
+
+        let ring = ServerKeyRing::load_or_generate(path, Some(master_key), GRACE).unwrap();
+        let secret_hex = hex::encode(ring.current().secret_bytes());
+        assert!(!std::fs::read_to_string(path).unwrap().contains(&secret_hex));
+
+        let reloaded = ServerKeyRing::load_or_generate(path, Some(master_key), GRACE).unwrap();
+        assert_eq!(reloaded.current().public_key_hex(), ring.current().public_key_hex());
+        assert!(matches!(
+            ServerKeyRing::load_or_generate(path, None, GRACE),
+            Err(KeyStoreError::MasterKeyRequired(_))
+        ));
+        assert!(matches!(
+            ServerKeyRing::load_or_generate(path, Some([4u8; 32]), GRACE),
+            Err(KeyStoreError::InvalidMasterKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_previous_key_accepted_only_within_grace() {
+        let ring = ServerKeyRing::in_memory(ServerKeyPair::generate(), Duration::from_millis(50));
+        let old = ring.current().public_key_hex();
+        let new = ring.rotate().unwrap().public_key_hex();
+
+        let accepted: Vec<String> = ring.accepted().iter().map(|k| k.public_key_hex()).collect();
+        assert_eq!(accepted, [new.clone(), old]);
+
+        std::thread::sleep(Duration::from_millis(60));
+        let accepted: Vec<String> = ring.accepted().iter().map(|k| k.public_key_hex()).collect();
+        assert_eq!(accepted, [new]);
+    }
+
+    #[test]
+    fn test_failed_rotation_keeps_current_key() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keys").join("server_key.yaml");
+        let ring = ServerKeyRing::load_or_generate(path.to_str().unwrap(), None, GRACE).unwrap();
+        let before = ring.current().public_key_hex();
+
+        // Replace the directory with a file so the next save fails
+        std::fs::remove_dir_all(dir.path().join("keys")).unwrap();
+        std::fs::write(dir.path().join("keys"), b"").unwrap();
+
+        assert!(matches!(ring.rotate(), Err(KeyStoreError::Io(_))));
+        assert_eq!(ring.current().public_key_hex(), before);
+        assert_eq!(ring.accepted().len(), 1);
+    }
+}
@@ -1,11 +1,41 @@
 //! In-memory session store
 
+use base64::Engine;
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+use super::clock::{Clock, SystemClock};
+use super::crypto::SharedSecret;
+use super::session_events::{NoopEventSink, SessionEvent, SessionEventKind, SessionEventSink};
+
+/// Fixed JOSE header for HS256 tokens
+const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Claims carried by a session JWT
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    pub session_id: Uuid,
+    pub client_id: Option<String>,
+    /// Expiry as seconds since the Unix epoch
+    pub exp: i64,
+    pub is_admin: bool,
+}
+
+/// Counts of sessions held in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SessionStats {
+    pub active: usize,
+    /// Expired but not yet removed by `cleanup_expired`
+    pub expired: usize,
+    pub total: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: Uuid,
@@ -13,11 +43,30 @@ pub struct Session {
     pub created_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
     pub last_seen: DateTime<Utc>,
+    /// Created through admin login
+    #[serde(default)]
+    pub is_admin: bool,
+    /// Registered client this session belongs to, if any
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Only accepted from this address, when set
+    #[serde(default)]
+    pub bound_ip: Option<IpAddr>,
+    /// Lifetime granted at creation, reapplied on each sliding renewal
+    #[serde(default)]
+    pub ttl_secs: u64,
+    /// Key for encrypted admin responses, agreed at admin login
+    #[serde(skip)]
+    pub payload_key: Option<SharedSecret>,
 }
 
 impl Session {
     pub fn new(ttl_secs: u64) -> Self {
-        let now = Utc::now();
+        Self::new_at(ttl_secs, Utc::now())
+    }
+
+    /// A session created at `now` rather than the current system time
+    pub fn new_at(ttl_secs: u64, now: DateTime<Utc>) -> Self {
         let api_key = generate_api_key();
         Self {
             id: Uuid::new_v4(),
@@ -25,20 +74,51 @@ impl Session {
             created_at: now,
             expires_at: now + chrono::Duration::seconds(ttl_secs as i64),
             last_seen: now,
+            is_admin: false,
+            client_id: None,
+            bound_ip: None,
+            ttl_secs,
+            payload_key: None,
+        }
+    }
+
+    /// A session for `client_id` created at `now`
+    pub fn new_for_client_at(client_id: &str, ttl_secs: u64, now: DateTime<Utc>) -> Self {
+        Self {
+            client_id: Some(client_id.to_string()),
+            ..Self::new_at(ttl_secs, now)
         }
     }
 
-    pub fn is_expired(&self) -> bool {
-        Utc::now() > self.expires_at
+    /// An admin session created at `now`
+    pub fn new_admin_at(ttl_secs: u64, now: DateTime<Utc>) -> Self {
+        Self {
+            is_admin: true,
+            ..Self::new_at(ttl_secs, now)
+        }
+    }
+
+    /// Whether the session has expired as of `now`, which callers take from
+    /// the store's clock
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now > self.expires_at
+    }
+
+    /// Record use of the session at `now`
+    pub fn touch(&mut self, now: DateTime<Utc>) {
+        self.last_seen = now;
     }
 
-    pub fn touch(&mut self) {
-        self.last_seen = Utc::now();
+    /// Push expiry out to `now` plus another TTL, never past
+    /// `created_at + max_lifetime_secs`
+    pub fn renew(&mut self, max_lifetime_secs: u64, now: DateTime<Utc>) {
+        let cap = self.created_at + chrono::Duration::seconds(max_lifetime_secs as i64);
+        let renewed = now + chrono::Duration::seconds(self.ttl_secs as i64);
+        self.expires_at = self.expires_at.max(renewed.min(cap));
     }
 }
 
 fn generate_api_key() -> String {
-    use base64::Engine;
     use rand::RngCore;
 
     let mut bytes = [0u8; 32];
@@ -46,50 +126,295 @@ fn generate_api_key() -> String {
     format!("omni_{}", base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes))
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct SessionStore {
     sessions: Arc<RwLock<HashMap<String, Session>>>,
+    /// HMAC key for session JWTs
+    jwt_key: Arc<[u8; 32]>,
+    /// Absolute cap for sliding renewal; renewal is off when unset
+    max_lifetime_secs: Option<u64>,
+    /// Source of creation and expiry times
+    clock: Arc<dyn Clock>,
+    /// Receives create/revoke/expire/failed-validation events
+    events: Arc<dyn SessionEventSink>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        use rand::RngCore;
+
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self {
+            sessions: Arc::default(),
+            jwt_key: Arc::new(key),
+            max_lifetime_secs: None,
+            clock: Arc::new(SystemClock),
+            events: Arc::new(NoopEventSink),
+        }
+    }
 }
 
 impl SessionStore {
+    /// Store whose JWTs are only valid for the life of this process
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Store whose JWTs are keyed from `secret`, so they survive restarts.
+    ///
+    /// The shipped default secret is public, so a key derived from it would
+    /// let anyone mint tokens; it gets a per-process key instead.
+    pub fn with_secret(secret: &str) -> Self {
+        if secret == crate::config::DEFAULT_SECRET_KEY {
+            return Self::default();
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(b"omni-core/jwt/v1");
+        hasher.update(secret.as_bytes());
+        Self {
+            jwt_key: Arc::new(hasher.finalize().into()),
+            ..Self::default()
+        }
+    }
+
+    /// Extend sessions on each successful validation, up to `max_lifetime_secs`
+    /// after creation
+    pub fn with_sliding_renewal(mut self, max_lifetime_secs: u64) -> Self {
+        self.max_lifetime_secs = Some(max_lifetime_secs);
+        self
+    }
+
+    /// Take timestamps from `clock` instead of the system time
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Report session lifecycle events to `sink`
+    pub fn with_event_sink(mut self, sink: impl SessionEventSink + 'static) -> Self {
+        self.events = Arc::new(sink);
+        self
+    }
+
+    /// Sign a session as an HS256 JWT
+    pub fn issue_jwt(&self, session: &Session) -> String {
+        let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let claims = Claims {
+            session_id: session.id,
+            client_id: session.client_id.clone(),
+            exp: session.expires_at.timestamp(),
+            is_admin: session.is_admin,
+        };
+        let claims = serde_json::to_vec(&claims).expect("claims serialize to JSON");
+        let signing_input = format!("{}.{}", b64.encode(JWT_HEADER), b64.encode(claims));
+
+        let signature = self.jwt_mac(&signing_input).finalize().into_bytes();
+        format!("{}.{}", signing_input, b64.encode(signature))
+    }
+
+    /// Check a JWT's signature and expiry, and that its session is still held.
+    ///
+    /// Revoking or expiring a session stops its tokens verifying as well.
+    pub fn verify_jwt(&self, token: &str) -> Option<Claims> {
+        let claims = self.decode_jwt(token)?;
+        self.live_api_key(claims.session_id)?;
+        Some(claims)
+    }
+
+    /// Like [`SessionStore::validate_bound`], for the session a JWT was issued for
+    pub fn validate_jwt_bound(&self, token: &str, ip: IpAddr) -> Option<Session> {
+        let api_key = self.decode_jwt(token).and_then(|claims| self.live_api_key(claims.session_id));
+        let Some(api_key) = api_key else {
+            self.events.record(&SessionEvent::new(SessionEventKind::ValidateFailed, token, self.clock.now()));
+            return None;
+        };
+        self.validate_bound(&api_key, ip)
+    }
+
+    /// API key of the unexpired session with this id
+    fn live_api_key(&self, session_id: Uuid) -> Option<String> {
+        let now = self.clock.now();
+        let sessions = self.sessions.read().unwrap();
+        sessions.values()
+            .find(|s| s.id == session_id && !s.is_expired(now))
+            .map(|s| s.api_key.clone())
+    }
+
+    /// Check a JWT's signature and expiry without consulting the store
+    fn decode_jwt(&self, token: &str) -> Option<Claims> {
+        let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signing_input.split_once('.')?;
+
+        self.jwt_mac(signing_input)
+            .verify_slice(&b64.decode(signature).ok()?)
+            .ok()?;
+        if b64.decode(header).ok()? != JWT_HEADER.as_bytes() {
+            return None;
+        }
+
+        let claims: Claims = serde_json::from_slice(&b64.decode(claims).ok()?).ok()?;
+        (claims.exp > self.clock.now().timestamp()).then_some(claims)
+    }
+
+    fn jwt_mac(&self, signing_input: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.jwt_key.as_slice())
+            .expect("HMAC accepts any key length");
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+
     pub fn create(&self, ttl_secs: u64) -> Session {
-        let session = Session::new(ttl_secs);
-        let mut sessions = self.sessions.write().unwrap();
-        sessions.insert(session.api_key.clone(), session.clone());
+        self.insert(Session::new_at(ttl_secs, self.clock.now()))
+    }
+
+    pub fn create_admin(&self, ttl_secs: u64) -> Session {
+        self.insert(Session::new_admin_at(ttl_secs, self.clock.now()))
+    }
+
+    pub fn create_for_client(&self, client_id: &str, ttl_secs: u64) -> Session {
+        self.insert(Session::new_for_client_at(client_id, ttl_secs, self.clock.now()))
+    }
+
+    fn insert(&self, session: Session) -> Session {
+        self.sessions.write().unwrap().insert(session.api_key.clone(), session.clone());
+        self.events.record(&SessionEvent::for_session(SessionEventKind::Created, &session, session.created_at));
         session
     }
 
+    /// Record `kind` for each session; called after the store lock is released
+    fn record_all(&self, kind: SessionEventKind, sessions: &[Session], now: DateTime<Utc>) {
+        for session in sessions {
+            self.events.record(&SessionEvent::for_session(kind, session, now));
+        }
+    }
+
     pub fn get(&self, api_key: &str) -> Option<Session> {
         let sessions = self.sessions.read().unwrap();
         sessions.get(api_key).cloned()
     }
 
     pub fn validate(&self, api_key: &str) -> Option<Session> {
+        let now = self.clock.now();
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(session) = sessions.get_mut(api_key) else {
+            drop(sessions);
+            self.events.record(&SessionEvent::new(SessionEventKind::ValidateFailed, api_key, now));
+            return None;
+        };
+        if session.is_expired(now) {
+            let expired = sessions.remove(api_key);
+            drop(sessions);
+            self.record_all(SessionEventKind::Expired, expired.as_slice(), now);
+            return None;
+        }
+        session.touch(now);
+        if let Some(max_lifetime) = self.max_lifetime_secs {
+            session.renew(max_lifetime, now);
+        }
+        Some(session.clone())
+    }
+
+    /// Like [`SessionStore::validate`], but refuses sessions bound to a different IP
+    pub fn validate_bound(&self, api_key: &str, ip: IpAddr) -> Option<Session> {
+        let mismatched = self.sessions.read().unwrap()
+            .get(api_key)
+            .filter(|session| session.bound_ip.is_some_and(|bound| bound != ip))
+            .cloned();
+        if let Some(session) = mismatched {
+            self.record_all(SessionEventKind::ValidateFailed, &[session], self.clock.now());
+            return None;
+        }
+        self.validate(api_key)
+    }
+
+    /// Restrict a session to requests from `ip`
+    pub fn bind_to_ip(&self, api_key: &str, ip: IpAddr) -> bool {
         let mut sessions = self.sessions.write().unwrap();
-        if let Some(session) = sessions.get_mut(api_key) {
-            if session.is_expired() {
-                sessions.remove(api_key);
-                return None;
+        match sessions.get_mut(api_key) {
+            Some(session) => {
+                session.bound_ip = Some(ip);
+                true
             }
-            session.touch();
-            return Some(session.clone());
+            None => false,
+        }
+    }
+
+    /// Attach the key admin responses are encrypted under
+    pub fn set_payload_key(&self, api_key: &str, key: SharedSecret) -> bool {
+        let mut sessions = self.sessions.write().unwrap();
+        match sessions.get_mut(api_key) {
+            Some(session) => {
+                session.payload_key = Some(key);
+                true
+            }
+            None => false,
         }
-        None
     }
 
     pub fn revoke(&self, api_key: &str) -> bool {
+        let revoked = self.sessions.write().unwrap().remove(api_key);
+        self.record_all(SessionEventKind::Revoked, revoked.as_slice(), self.clock.now());
+        revoked.is_some()
+    }
+
+    /// Revoke every session belonging to a client, returning how many were removed
+    pub fn revoke_all_for_client(&self, client_id: &str) -> usize {
+        let revoked = self.remove_where(|s| s.client_id.as_deref() == Some(client_id));
+        self.record_all(SessionEventKind::Revoked, &revoked, self.clock.now());
+        revoked.len()
+    }
+
+    /// Remove and return every session matching `pred`
+    fn remove_where(&self, pred: impl Fn(&Session) -> bool) -> Vec<Session> {
         let mut sessions = self.sessions.write().unwrap();
-        sessions.remove(api_key).is_some()
+        let keys: Vec<String> = sessions.iter()
+            .filter(|(_, s)| pred(s))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter().filter_map(|key| sessions.remove(key)).collect()
+    }
+
+    /// Number of sessions that have not expired yet
+    pub fn active_count(&self) -> usize {
+        let now = self.clock.now();
+        let sessions = self.sessions.read().unwrap();
+        sessions.values().filter(|s| !s.is_expired(now)).count()
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        let now = self.clock.now();
+        let sessions = self.sessions.read().unwrap();
+        let expired = sessions.values().filter(|s| s.is_expired(now)).count();
+        SessionStats {
+            active: sessions.len() - expired,
+            expired,
+            total: sessions.len(),
+        }
+    }
+
+    /// Unexpired sessions belonging to a client
+    pub fn list_for_client(&self, client_id: &str) -> Vec<Session> {
+        let now = self.clock.now();
+        let sessions = self.sessions.read().unwrap();
+        sessions.values()
+            .filter(|s| !s.is_expired(now) && s.client_id.as_deref() == Some(client_id))
+            .cloned()
+            .collect()
+    }
+
+    /// All unexpired sessions
+    pub fn list_all_active(&self) -> Vec<Session> {
+        let now = self.clock.now();
+        let sessions = self.sessions.read().unwrap();
+        sessions.values().filter(|s| !s.is_expired(now)).cloned().collect()
     }
 
     pub fn cleanup_expired(&self) -> usize {
-        let mut sessions = self.sessions.write().unwrap();
-        let before = sessions.len();
-        sessions.retain(|_, s| !s.is_expired());
-        before - sessions.len()
+        let now = self.clock.now();
+        let expired = self.remove_where(|s| s.is_expired(now));
+        self.record_all(SessionEventKind::Expired, &expired, now);
+        expired.len()
     }
 }
@@ -0,0 +1,149 @@
+//! Record of session lifecycle events, for security review after the fact
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Default size at which the session event log is rotated (10 MiB)
+pub const DEFAULT_EVENT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Hex digits of SHA-256(api key) kept in an event
+const KEY_PREFIX_LEN: usize = 12;
+
+/// What happened to a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEventKind {
+    Created,
+    /// An API key was presented and refused
+    ValidateFailed,
+    Revoked,
+    Expired,
+}
+
+/// One entry in the session event log
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionEvent {
+    pub kind: SessionEventKind,
+    pub at: DateTime<Utc>,
+    /// Start of SHA-256 over the API key, so entries can be correlated
+    /// without the log holding usable keys
+    pub key_hash: String,
+    /// Unset when the key did not match any session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+impl SessionEvent {
+    pub fn new(kind: SessionEventKind, api_key: &str, at: DateTime<Utc>) -> Self {
+        Self {
+            kind,
+            at,
+            key_hash: hash_key_prefix(api_key),
+            session_id: None,
+            client_id: None,
+        }
+    }
+
+    /// Event about a known session
+    pub fn for_session(kind: SessionEventKind, session: &super::Session, at: DateTime<Utc>) -> Self {
+        Self {
+            session_id: Some(session.id),
+            client_id: session.client_id.clone(),
+            ..Self::new(kind, &session.api_key, at)
+        }
+    }
+}
+
+/// Short hex digest identifying an API key in logs
+pub fn hash_key_prefix(api_key: &str) -> String {
+    let mut digest = hex::encode(Sha256::digest(api_key.as_bytes()));
+    digest.truncate(KEY_PREFIX_LEN);
+    digest
+}
+
+/// Where session events go
+pub trait SessionEventSink: Send + Sync {
+    fn record(&self, event: &SessionEvent);
+}
+
+/// Drops every event; the default when the log is off
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopEventSink;
+
+impl SessionEventSink for NoopEventSink {
+    fn record(&self, _event: &SessionEvent) {}
+}
+
+/// Keeps events in memory; clones share the same list
+#[derive(Debug, Clone, Default)]
+pub struct MemoryEventSink {
+    events: Arc<Mutex<Vec<SessionEvent>>>,
+}
+
+impl MemoryEventSink {
+    pub fn events(&self) -> Vec<SessionEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl SessionEventSink for MemoryEventSink {
+    fn record(&self, event: &SessionEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+/// Appends events as JSON lines to a file, moving it to `<path>.1` once it
+/// reaches `max_bytes` so at most two files' worth is kept
+#[derive(Debug)]
+pub struct FileEventSink {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Serializes appends and rotation
+    lock: Mutex<()>,
+}
+
+impl FileEventSink {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Path the log is rotated to
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        rotated.into()
+    }
+
+    fn append(&self, event: &SessionEvent) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().unwrap();
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            fs::rename(&self.path, self.rotated_path())?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&line)
+    }
+}
+
+impl SessionEventSink for FileEventSink {
+    fn record(&self, event: &SessionEvent) {
+        if let Err(e) = self.append(event) {
+            tracing::warn!("Failed to write session event to {}: {}", self.path.display(), e);
+        }
+    }
+}
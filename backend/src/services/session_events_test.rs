@@ -0,0 +1,111 @@
+//! Tests for session_events module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::clock::{Clock, MockClock};
+    use crate::services::session::SessionStore;
+    use crate::services::session_events::*;
+    use std::net::{IpAddr, Ipv4Addr};
+    use tempfile::tempdir;
+
+    fn store() -> (SessionStore, MemoryEventSink, MockClock) {
+        let sink = MemoryEventSink::default();
+        let clock = MockClock::default();
+        let store = SessionStore::new().with_clock(clock.clone()).with_event_sink(sink.clone());
+        (store, sink, clock)
+    }
+
+    fn kinds(sink: &MemoryEventSink) -> Vec<SessionEventKind> {
+        sink.events().iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_revoke_records_created_and_revoked() {
+        let (store, sink, _) = store();
+        let session = store.create_for_client("device-1", 3600);
+
+        assert!(store.revoke(&session.api_key));
+        // Revoking again removes nothing and records nothing
+        assert!(!store.revoke(&session.api_key));
+
+        let events = sink.events();
+        assert_eq!(kinds(&sink), vec![SessionEventKind::Created, SessionEventKind::Revoked]);
+        assert_eq!(events[1].session_id, Some(session.id));
+        assert_eq!(events[1].client_id.as_deref(), Some("device-1"));
+        assert_eq!(events[1].key_hash, hash_key_prefix(&session.api_key));
+        assert!(!events[1].key_hash.contains(&session.api_key));
+    }
+
+    #[test]
+    fn test_revoke_all_for_client_records_each_session() {
+        let (store, sink, _) = store();
+        store.create_for_client("device-1", 3600);
+        store.create_for_client("device-1", 3600);
+        store.create_for_client("device-2", 3600);
+
+        assert_eq!(store.revoke_all_for_client("device-1"), 2);
+
+        let revoked: Vec<_> = sink.events().into_iter().filter(|e| e.kind == SessionEventKind::Revoked).collect();
+        assert_eq!(revoked.len(), 2);
+        assert!(revoked.iter().all(|e| e.client_id.as_deref() == Some("device-1")));
+    }
+
+    #[test]
+    fn test_expiry_records_expired() {
+        let (store, sink, clock) = store();
+        let on_validate = store.create(60);
+        let on_cleanup = store.create(60);
+        clock.advance(chrono::Duration::seconds(61));
+
+        assert!(store.validate(&on_validate.api_key).is_none());
+        assert_eq!(store.cleanup_expired(), 1);
+
+        let expired: Vec<_> = sink.events().into_iter().filter(|e| e.kind == SessionEventKind::Expired).collect();
+        assert_eq!(expired.len(), 2);
+        assert_eq!(expired[0].session_id, Some(on_validate.id));
+        assert_eq!(expired[1].session_id, Some(on_cleanup.id));
+        assert_eq!(expired[1].at, clock.now());
+    }
+
+    #[test]
+    fn test_failed_validation_is_recorded() {
+        let (store, sink, _) = store();
+        let session = store.create(3600);
+        store.bind_to_ip(&session.api_key, IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        assert!(store.validate("omni_unknown").is_none());
+        assert!(store.validate_bound(&session.api_key, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))).is_none());
+
+        let events = sink.events();
+        assert_eq!(
+            kinds(&sink),
+            vec![SessionEventKind::Created, SessionEventKind::ValidateFailed, SessionEventKind::ValidateFailed]
+        );
+        assert_eq!(events[1].session_id, None);
+        assert_eq!(events[1].key_hash, hash_key_prefix("omni_unknown"));
+        assert_eq!(events[2].session_id, Some(session.id));
+    }
+
+    #[test]
+    fn test_file_sink_rotates_by_size() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("session_events.log");
+        let sink = FileEventSink::new(&path, 300);
+        let store = SessionStore::new().with_event_sink(sink);
+
+        for _ in 0..5 {
+            store.create(3600);
+        }
+
+        let rotated = dir.path().join("session_events.log.1");
+        assert!(rotated.exists());
+        for file in [&path, &rotated] {
+            let content = std::fs::read_to_string(file).unwrap();
+            assert!(content.len() <= 300);
+            for line in content.lines() {
+                let event: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(event["kind"], "created");
+            }
+        }
+    }
+}
@@ -2,7 +2,9 @@
 
 #[cfg(test)]
 mod tests {
+    use crate::services::clock::{Clock, MockClock};
     use crate::services::session::*;
+    use chrono::Duration;
 
     #[test]
     fn test_session_creation() {
@@ -10,7 +12,7 @@ mod tests {
         
         assert!(!session.api_key.is_empty());
         assert!(session.api_key.starts_with("omni_"));
-        assert!(!session.is_expired());
+        assert!(!session.is_expired(session.created_at));
     }
 
     #[test]
@@ -63,21 +65,20 @@ mod tests {
     #[test]
     fn test_session_expiry() {
         let session = Session::new(0); // 0 second TTL
-        
-        // Should be expired immediately or very soon
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        assert!(session.is_expired());
+
+        // Expired the moment any time passes
+        assert!(!session.is_expired(session.created_at));
+        assert!(session.is_expired(session.created_at + Duration::milliseconds(1)));
     }
 
     #[test]
     fn test_session_touch_updates_last_seen() {
         let mut session = Session::new(3600);
-        let original_last_seen = session.last_seen;
-        
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        session.touch();
-        
-        assert!(session.last_seen > original_last_seen);
+        let later = session.last_seen + Duration::seconds(10);
+
+        session.touch(later);
+
+        assert_eq!(session.last_seen, later);
     }
 
     #[test]
@@ -91,10 +92,217 @@ mod tests {
         // Create valid session
         let valid = store.create(3600);
         
+        assert_eq!(store.stats(), SessionStats { active: 1, expired: 1, total: 2 });
+
         let cleaned = store.cleanup_expired();
         assert_eq!(cleaned, 1);
+        assert_eq!(store.stats(), SessionStats { active: 1, expired: 0, total: 1 });
         
         // Valid session should still exist
         assert!(store.get(&valid.api_key).is_some());
     }
+
+    #[test]
+    fn test_admin_and_regular_sessions_are_tagged() {
+        let store = SessionStore::new();
+        let regular = store.create(3600);
+        let admin = store.create_admin(3600);
+
+        assert!(!regular.is_admin);
+        assert!(admin.is_admin);
+
+        // validate must preserve the flag
+        assert!(!store.validate(&regular.api_key).unwrap().is_admin);
+        assert!(store.validate(&admin.api_key).unwrap().is_admin);
+    }
+
+    #[test]
+    fn test_list_for_client_filters_and_skips_expired() {
+        let store = SessionStore::new();
+        let laptop = store.create_for_client("device-1", 3600);
+        let phone = store.create_for_client("device-1", 3600);
+        let _expired = store.create_for_client("device-1", 0);
+        let _other = store.create_for_client("device-2", 3600);
+        let _anonymous = store.create(3600);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let mut keys: Vec<String> = store.list_for_client("device-1")
+            .into_iter()
+            .map(|s| s.api_key)
+            .collect();
+        keys.sort();
+        let mut expected = vec![laptop.api_key, phone.api_key];
+        expected.sort();
+
+        assert_eq!(keys, expected);
+        assert_eq!(store.list_all_active().len(), 4);
+        assert!(store.list_for_client("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_revoke_all_for_client() {
+        let store = SessionStore::new();
+        let first = store.create_for_client("device-1", 3600);
+        let second = store.create_for_client("device-1", 3600);
+        let other = store.create_for_client("device-2", 3600);
+
+        assert_eq!(store.revoke_all_for_client("device-1"), 2);
+
+        assert!(store.get(&first.api_key).is_none());
+        assert!(store.get(&second.api_key).is_none());
+        assert!(store.get(&other.api_key).is_some());
+        assert_eq!(store.revoke_all_for_client("device-1"), 0);
+    }
+
+    #[test]
+    fn test_jwt_roundtrip() {
+        let store = SessionStore::with_secret("test-secret");
+        let session = store.create_for_client("device-1", 3600);
+        let token = store.issue_jwt(&session);
+
+        let claims = store.verify_jwt(&token).unwrap();
+        assert_eq!(claims.session_id, session.id);
+        assert_eq!(claims.client_id.as_deref(), Some("device-1"));
+        assert_eq!(claims.exp, session.expires_at.timestamp());
+        assert!(!claims.is_admin);
+
+        assert!(SessionStore::with_secret("other-secret").verify_jwt(&token).is_none());
+    }
+
+    #[test]
+    fn test_jwt_revoked_with_its_session() {
+        let store = SessionStore::new();
+        let session = store.create_for_client("device-1", 3600);
+        let token = store.issue_jwt(&session);
+        let ip = "10.0.0.1".parse().unwrap();
+        assert_eq!(store.validate_jwt_bound(&token, ip).unwrap().id, session.id);
+
+        store.revoke_all_for_client("device-1");
+        assert!(store.verify_jwt(&token).is_none());
+        assert!(store.validate_jwt_bound(&token, ip).is_none());
+    }
+
+    #[test]
+    fn test_default_secret_does_not_key_jwts() {
+        let store = SessionStore::with_secret(crate::config::DEFAULT_SECRET_KEY);
+        let session = store.create_admin(3600);
+        let token = store.issue_jwt(&session);
+        assert!(store.verify_jwt(&token).is_some());
+
+        // Neither a token minted from the public default nor one from
+        // another process is accepted
+        let forged = SessionStore::with_secret(crate::config::DEFAULT_SECRET_KEY).issue_jwt(&session);
+        assert!(store.verify_jwt(&forged).is_none());
+        assert!(SessionStore::with_secret(crate::config::DEFAULT_SECRET_KEY).verify_jwt(&token).is_none());
+    }
+
+    #[test]
+    fn test_jwt_expired_rejected() {
+        let store = SessionStore::new();
+        let mut session = Session::new_admin_at(3600, chrono::Utc::now());
+        session.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+
+        assert!(store.verify_jwt(&store.issue_jwt(&session)).is_none());
+    }
+
+    #[test]
+    fn test_jwt_tampered_rejected() {
+        use base64::Engine;
+
+        let store = SessionStore::new();
+        let token = store.issue_jwt(&Session::new(3600));
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3);
+
+        // Promote the session to admin without re-signing
+        let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let claims = String::from_utf8(b64.decode(parts[1]).unwrap()).unwrap()
+            .replace(r#""is_admin":false"#, r#""is_admin":true"#);
+        let forged = format!("{}.{}.{}", parts[0], b64.encode(claims), parts[2]);
+
+        assert!(store.verify_jwt(&forged).is_none());
+        assert!(store.verify_jwt("not.a.jwt").is_none());
+        assert!(store.verify_jwt("").is_none());
+    }
+
+    #[test]
+    fn test_validate_bound_checks_ip() {
+        let store = SessionStore::new();
+        let home: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+        let away: std::net::IpAddr = "10.0.0.2".parse().unwrap();
+
+        // Unbound sessions are accepted from anywhere
+        let session = store.create(3600);
+        assert!(store.validate_bound(&session.api_key, away).is_some());
+
+        assert!(store.bind_to_ip(&session.api_key, home));
+        assert!(store.validate_bound(&session.api_key, home).is_some());
+        assert!(store.validate_bound(&session.api_key, away).is_none());
+        // The mismatch does not revoke the session
+        assert!(store.validate_bound(&session.api_key, home).is_some());
+
+        assert!(!store.bind_to_ip("omni_unknown", home));
+    }
+
+    #[test]
+    fn test_sliding_renewal_extends_up_to_cap() {
+        let clock = MockClock::default();
+        let store = SessionStore::new().with_sliding_renewal(3).with_clock(clock.clone());
+        let session = store.create(1);
+        let cap = session.created_at + Duration::seconds(3);
+
+        clock.advance(Duration::milliseconds(600));
+        let renewed = store.validate(&session.api_key).unwrap();
+        assert_eq!(renewed.expires_at, clock.now() + Duration::seconds(1));
+
+        // Renewing close to the cap clamps to it, and the session then lapses
+        for _ in 0..3 {
+            clock.advance(Duration::milliseconds(600));
+            store.validate(&session.api_key).unwrap();
+        }
+        assert_eq!(store.get(&session.api_key).unwrap().expires_at, cap);
+
+        clock.advance(Duration::milliseconds(800));
+        assert!(store.validate(&session.api_key).is_none());
+    }
+
+    #[test]
+    fn test_mock_clock_expires_sessions_past_ttl() {
+        let clock = MockClock::default();
+        let store = SessionStore::new().with_clock(clock.clone());
+        let session = store.create(60);
+        assert_eq!(session.created_at, clock.now());
+        assert_eq!(session.expires_at, clock.now() + Duration::seconds(60));
+
+        clock.advance(Duration::seconds(60));
+        assert!(store.validate(&session.api_key).is_some());
+
+        clock.advance(Duration::seconds(1));
+        assert_eq!(store.stats(), SessionStats { active: 0, expired: 1, total: 1 });
+        assert!(store.validate(&session.api_key).is_none());
+    }
+
+    #[test]
+    fn test_mock_clock_drives_cleanup_and_jwt_expiry() {
+        let clock = MockClock::default();
+        let store = SessionStore::new().with_clock(clock.clone());
+        let short = store.create_for_client("device-1", 10);
+        let long = store.create_admin(100);
+        let token = store.issue_jwt(&short);
+
+        clock.advance(Duration::seconds(11));
+        assert!(store.verify_jwt(&token).is_none());
+        assert_eq!(store.cleanup_expired(), 1);
+        assert!(store.get(&short.api_key).is_none());
+        assert!(store.validate(&long.api_key).unwrap().is_admin);
+    }
+
+    #[test]
+    fn test_validate_without_renewal_keeps_expiry() {
+        let store = SessionStore::new();
+        let session = store.create(3600);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(store.validate(&session.api_key).unwrap().expires_at, session.expires_at);
+    }
 }
@@ -0,0 +1,36 @@
+//! Filesystem helpers shared by the YAML-backed stores
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Path of the temporary file used while replacing `path`
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Write `contents` to `path` without ever exposing a partially written file.
+///
+/// The data is written and synced to a sibling `.tmp` file which is then
+/// renamed over the target, so readers see either the old or the new file.
+pub fn atomic_write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = tmp_path(path);
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
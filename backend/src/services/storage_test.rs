@@ -0,0 +1,39 @@
+//! Tests for storage module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::keystore::*;
+    use crate::services::storage::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_atomic_write_replaces_contents() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("nested").join("file.yaml");
+
+        atomic_write(&path, "first").unwrap();
+        atomic_write(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(!tmp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_load_ignores_leftover_tmp_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("server_keys.yaml");
+        let path_str = path.to_str().unwrap();
+
+        let mut store = ServerKeysStore::default();
+        store.add_key(ServerKeyEntry::generate("client-1"));
+        store.save_to(path_str).unwrap();
+
+        // Simulate a crash in the middle of the next save
+        fs::write(tmp_path(&path), "keys:\n  client-2:\n    client_id: cli").unwrap();
+
+        let loaded = ServerKeysStore::load_from(path_str).unwrap();
+        assert!(loaded.get_key("client-1").is_some());
+        assert!(loaded.get_key("client-2").is_none());
+    }
+}
@@ -0,0 +1,58 @@
+//! Graceful shutdown handling
+
+use std::future::Future;
+use crate::services::AppState;
+
+/// Resolve on Ctrl+C, or SIGTERM on unix
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Wait for `signal` and log that the server is draining.
+///
+/// Servers stop accepting connections once this resolves, but requests
+/// already in flight still run; call [`flush_state`] after they finish.
+pub async fn drain_on<F>(signal: F)
+where
+    F: Future<Output = ()>,
+{
+    signal.await;
+    tracing::info!("Shutdown requested, draining in-flight requests");
+}
+
+/// Tidy up state once every server has stopped, so writes made by the last
+/// requests (such as coalesced `last_seen` updates) reach disk
+pub fn flush_state(state: &AppState) {
+    let expired = state.sessions.cleanup_expired();
+    tracing::info!("Removed {} expired sessions", expired);
+
+    if let Err(e) = state.keystore.flush() {
+        tracing::error!("Failed to flush key store: {}", e);
+    }
+}
@@ -0,0 +1,39 @@
+//! Tests for shutdown module
+
+#[cfg(test)]
+mod tests {
+    use crate::services::AppState;
+    use crate::shutdown::{drain_on, flush_state};
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_signal() {
+        let (tx, rx) = oneshot::channel::<()>();
+
+        let drain = tokio::spawn(drain_on(async { let _ = rx.await; }));
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!drain.is_finished());
+
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), drain).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_state_cleans_up_expired_sessions() {
+        let dir = tempdir().unwrap();
+        let state = AppState::for_tests(dir.path());
+        let expired = state.sessions.create(0);
+        let active = state.sessions.create(3600);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        flush_state(&state);
+
+        assert!(state.sessions.get(&expired.api_key).is_none());
+        assert!(state.sessions.get(&active.api_key).is_some());
+        // Flushing writes the key files
+        assert!(dir.path().join("server_keys.yaml").exists());
+    }
+}
@@ -0,0 +1,36 @@
+//! Optional TLS for the HTTP listener
+
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use serde::Deserialize;
+
+/// Certificate and private key locations (PEM)
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsPaths {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsPaths {
+    /// Build from the `TLS_CERT_PATH` / `TLS_KEY_PATH` values.
+    ///
+    /// Both unset means plain HTTP; setting only one is an error.
+    pub fn from_vars(cert_path: Option<String>, key_path: Option<String>) -> anyhow::Result<Option<Self>> {
+        match (cert_path, key_path) {
+            (Some(cert_path), Some(key_path)) => Ok(Some(Self { cert_path, key_path })),
+            (None, None) => Ok(None),
+            (Some(_), None) => anyhow::bail!("TLS_CERT_PATH is set but TLS_KEY_PATH is missing"),
+            (None, Some(_)) => anyhow::bail!("TLS_KEY_PATH is set but TLS_CERT_PATH is missing"),
+        }
+    }
+
+    /// Load and parse the certificate chain and key
+    pub async fn load(&self) -> anyhow::Result<RustlsConfig> {
+        RustlsConfig::from_pem_file(&self.cert_path, &self.key_path)
+            .await
+            .with_context(|| format!(
+                "Failed to load TLS certificate '{}' and key '{}'",
+                self.cert_path, self.key_path
+            ))
+    }
+}
@@ -0,0 +1,42 @@
+//! Tests for tls module
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::TlsPaths;
+
+    const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls_cert.pem");
+    const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls_key.pem");
+
+    #[test]
+    fn test_from_vars_both_unset_is_plain_http() {
+        assert!(TlsPaths::from_vars(None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_from_vars_requires_both_paths() {
+        assert!(TlsPaths::from_vars(Some(CERT.to_string()), None).is_err());
+        assert!(TlsPaths::from_vars(None, Some(KEY.to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_self_signed_fixture() {
+        let paths = TlsPaths::from_vars(Some(CERT.to_string()), Some(KEY.to_string()))
+            .unwrap()
+            .unwrap();
+
+        assert!(paths.load().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_unparseable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let bogus = dir.path().join("bogus.pem");
+        std::fs::write(&bogus, "not a certificate").unwrap();
+
+        let paths = TlsPaths {
+            cert_path: bogus.to_str().unwrap().to_string(),
+            key_path: KEY.to_string(),
+        };
+        assert!(paths.load().await.is_err());
+    }
+}
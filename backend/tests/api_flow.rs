@@ -0,0 +1,551 @@
+//! End-to-end tests driving the full /api/v1 router in-process
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use omni_backend::api::routes;
+use omni_backend::config::{Config, Paths};
+use omni_backend::services::{
+    key_confirmation_tag, open_raw, parse_public_key, ratchet_shared_secret, seal_raw, AppState, EncryptedMessage, PublicKeyBytes, RatchetChain,
+    ServerKeyPair, SharedSecret,
+};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::Path;
+use tempfile::tempdir;
+use tower::ServiceExt;
+
+fn test_state(dir: &Path) -> AppState {
+    state_with_limit(dir, 1024 * 1024)
+}
+
+fn state_with_limit(dir: &Path, max_ciphertext_len: usize) -> AppState {
+    AppState::new(Config {
+        port: 0,
+        secret_key: "test-secret".to_string(),
+        session_ttl_secs: 3600,
+        admin_session_ttl_secs: 24 * 3600,
+        session_max_lifetime_secs: None,
+        register_rate_per_min: 60,
+        tls: None,
+        paths: Paths::new(dir),
+        audit_log: None,
+        max_ciphertext_len,
+        max_backup_len: 64 * 1024 * 1024,
+        master_key: None,
+        cors_allowed_origins: Vec::new(),
+        bind_sessions_to_ip: false,
+        server_key_grace_secs: 300,
+        compress_responses: true,
+        compression_min_size: 1024,
+        bind_addrs: Vec::new(),
+        session_event_log: false,
+        session_event_log_max_bytes: omni_backend::services::DEFAULT_EVENT_LOG_MAX_BYTES,
+    })
+    .unwrap()
+}
+
+/// Same nesting as the binary, without the CORS and trace layers
+fn app(state: &AppState) -> Router {
+    Router::new()
+        .nest("/api/v1", routes(state.clone()))
+        .with_state(state.clone())
+}
+
+async fn call(app: &Router, method: &str, uri: &str, body: Option<Value>, bearer: Option<&str>) -> (StatusCode, Value) {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(token) = bearer {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let req = match body {
+        Some(body) => req
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => req.body(Body::empty()),
+    }
+    .unwrap();
+
+    let res = app.clone().oneshot(req).await.unwrap();
+    let status = res.status();
+    let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let value = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    (status, value)
+}
+
+/// Encrypt `client_public` for `/register/complete` under a fresh ephemeral key
+fn register_complete_body(client_id: &str, init: &Value, client_public: &PublicKeyBytes) -> Value {
+    let ephemeral = ServerKeyPair::generate();
+    let server_public = parse_public_key(init["server_public_key"].as_str().unwrap()).unwrap();
+    let shared_secret = ephemeral.derive_shared_secret(&server_public).unwrap();
+    let challenge = hex::decode(init["challenge"].as_str().unwrap()).unwrap();
+    let encrypted = EncryptedMessage::encrypt_with_aad(client_public.as_bytes(), &shared_secret, &challenge).unwrap();
+    json!({
+        "client_id": client_id,
+        "ephemeral_public_key": ephemeral.public_key_hex(),
+        "encrypted_client_public_key": encrypted,
+    })
+}
+
+#[tokio::test]
+async fn join_verify_logout() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+
+    let (status, joined) = call(&app, "POST", "/api/v1/auth/join", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let api_key = joined["api_key"].as_str().unwrap().to_string();
+    assert!(api_key.starts_with("omni_"));
+
+    let (status, verified) = call(&app, "POST", "/api/v1/auth/verify", Some(json!({ "api_key": api_key })), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(verified["valid"], true);
+    assert_eq!(verified["session_id"], joined["session_id"]);
+
+    let (status, logged_out) = call(&app, "POST", "/api/v1/auth/logout", Some(json!({ "api_key": api_key })), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(logged_out["success"], true);
+
+    let (_, verified) = call(&app, "POST", "/api/v1/auth/verify", Some(json!({ "api_key": api_key })), None).await;
+    assert_eq!(verified["valid"], false);
+}
+
+#[tokio::test]
+async fn key_exchange_then_encrypted_message() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+
+    let (status, exchanged) = call(
+        &app,
+        "POST",
+        "/api/v1/keys/exchange",
+        Some(json!({ "client_public_key": client.public_key_hex() })),
+        None,
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(exchanged["api_key"].as_str().unwrap().starts_with("omni_"));
+
+    let server_public = parse_public_key(exchanged["server_public_key"].as_str().unwrap()).unwrap();
+    let shared_secret = client.derive_shared_secret(&server_public).unwrap();
+
+    let send = |sequence: u64| {
+        let payload = EncryptedMessage::encrypt_with_aad(b"ping", &shared_secret, &sequence.to_be_bytes()).unwrap();
+        json!({
+            "client_public_key": client.public_key_hex(),
+            "sequence": sequence,
+            "payload": payload,
+        })
+    };
+
+    let (status, reply) = call(&app, "POST", "/api/v1/keys/send", Some(send(1)), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let reply: EncryptedMessage = serde_json::from_value(reply["payload"].clone()).unwrap();
+    assert_eq!(reply.decrypt(&shared_secret).unwrap(), b"Received: ping");
+
+    // Replaying the same sequence is refused
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(1)), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn ratcheted_messages_use_fresh_keys() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let server_public = state.server_key.current().public_key_bytes();
+    let shared_secret = client.derive_shared_secret(&server_public).unwrap();
+
+    let start = json!({ "client_public_key": client.public_key_hex() });
+    let (status, started) = call(&app, "POST", "/api/v1/keys/ratchet", Some(start), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let ratchet_key = started["ratchet_key"].as_str().unwrap().to_string();
+    let ephemeral_secret = client.derive_shared_secret(&parse_public_key(&ratchet_key).unwrap()).unwrap();
+
+    let mut chain = RatchetChain::new(&ratchet_shared_secret(&shared_secret, &ephemeral_secret));
+    let keys: Vec<_> = (0..3).map(|_| chain.next_send_key()).collect();
+    let send_on = |ratchet_key: &str, (index, key): &(u64, SharedSecret)| {
+        let payload = EncryptedMessage::encrypt_with_aad(b"ping", key, &index.to_be_bytes()).unwrap();
+        json!({
+            "client_public_key": client.public_key_hex(),
+            "sequence": index,
+            "payload": payload,
+            "ratchet_key": ratchet_key,
+        })
+    };
+    let send = |message: &(u64, SharedSecret)| send_on(&ratchet_key, message);
+
+    // Index 1 arrives before 0; both open, and replies use each message key
+    for (index, key) in [keys[1], keys[0], keys[2]] {
+        let (status, reply) = call(&app, "POST", "/api/v1/keys/send", Some(send(&(index, key))), None).await;
+        assert_eq!(status, StatusCode::OK, "index {}", index);
+        let reply: EncryptedMessage = serde_json::from_value(reply["payload"].clone()).unwrap();
+        assert_eq!(reply.decrypt(&key).unwrap(), b"Received: ping");
+        assert!(reply.decrypt(&shared_secret).is_err());
+    }
+
+    // Each index opens once
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&keys[0])), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A chain the server doesn't hold (evicted, or from before a restart) is
+    // refused rather than restarted from index 0
+    let unknown = SharedSecret::from([9u8; 32]);
+    let body = send_on(&state.server_key.current().public_key_hex(), &(0, unknown));
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(body), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn binary_send_matches_json_send() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let shared_secret = client.derive_shared_secret(&state.server_key.current().public_key_bytes()).unwrap();
+
+    let payload = EncryptedMessage::encrypt_with_aad(b"ping", &shared_secret, &1u64.to_be_bytes()).unwrap();
+    let body = json!({ "client_public_key": client.public_key_hex(), "sequence": 1, "payload": payload });
+    let (status, reply) = call(&app, "POST", "/api/v1/keys/send", Some(body), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let reply: EncryptedMessage = serde_json::from_value(reply["payload"].clone()).unwrap();
+    let json_plaintext = reply.decrypt(&shared_secret).unwrap();
+
+    let send_binary = |sequence: u64| {
+        let sealed = seal_raw(b"ping", &shared_secret, &sequence.to_be_bytes()).unwrap();
+        Request::builder()
+            .method("POST")
+            .uri("/api/v1/keys/send-binary")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header("x-client-public-key", client.public_key_hex())
+            .header("x-sequence", sequence.to_string())
+            .body(Body::from(sealed))
+            .unwrap()
+    };
+
+    let res = app.clone().oneshot(send_binary(2)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/octet-stream");
+    let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let binary_plaintext = open_raw(&bytes, &shared_secret, &[], usize::MAX).unwrap();
+    assert_eq!(binary_plaintext, json_plaintext);
+
+    // Both endpoints share one replay window per key
+    let res = app.clone().oneshot(send_binary(1)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn key_confirmation_matches_derived_secret() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let shared_secret = client.derive_shared_secret(&state.server_key.current().public_key_bytes()).unwrap();
+
+    let proof = hex::encode(key_confirmation_tag(&shared_secret, &client.public_key_bytes()));
+    let body = json!({ "client_public_key": client.public_key_hex(), "proof": proof });
+    let (status, confirmed) = call(&app, "POST", "/api/v1/keys/confirm", Some(body), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(confirmed["confirmed"], true);
+
+    // A tag under some other secret is refused
+    let wrong = hex::encode(key_confirmation_tag(&SharedSecret::from([9u8; 32]), &client.public_key_bytes()));
+    let body = json!({ "client_public_key": client.public_key_hex(), "proof": wrong });
+    let (status, _) = call(&app, "POST", "/api/v1/keys/confirm", Some(body), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let body = json!({ "client_public_key": client.public_key_hex(), "proof": "abcd" });
+    let (status, _) = call(&app, "POST", "/api/v1/keys/confirm", Some(body), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn server_key_rotation_keeps_old_key_during_grace() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let admin = state.sessions.create_admin(3600);
+    let client = ServerKeyPair::generate();
+
+    let (_, before) = call(&app, "GET", "/api/v1/keys/public", None, None).await;
+    let old_public = parse_public_key(before["public_key"].as_str().unwrap()).unwrap();
+    let old_secret = client.derive_shared_secret(&old_public).unwrap();
+
+    let (status, rotated) = call(&app, "POST", "/api/v1/admin/server-key/rotate", None, Some(&admin.api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(rotated["server_public_key"], before["public_key"]);
+
+    let (_, after) = call(&app, "GET", "/api/v1/keys/public", None, None).await;
+    assert_eq!(after["public_key"], rotated["server_public_key"]);
+    let (_, info) = call(&app, "GET", "/api/v1/server/info", None, None).await;
+    assert_eq!(info["server_public_key"], rotated["server_public_key"]);
+
+    // A client still holding the old key gets a reply under the old secret
+    let payload = EncryptedMessage::encrypt_with_aad(b"ping", &old_secret, &1u64.to_be_bytes()).unwrap();
+    let body = json!({ "client_public_key": client.public_key_hex(), "sequence": 1, "payload": payload });
+    let (status, reply) = call(&app, "POST", "/api/v1/keys/send", Some(body), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let reply: EncryptedMessage = serde_json::from_value(reply["payload"].clone()).unwrap();
+    assert_eq!(reply.decrypt(&old_secret).unwrap(), b"Received: ping");
+
+    // The new key is persisted for the next start
+    let restarted = test_state(dir.path());
+    assert_eq!(restarted.server_key.current().public_key_hex(), rotated["server_public_key"].as_str().unwrap());
+}
+
+#[tokio::test]
+async fn register_then_list_clients() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+
+    let (status, init) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(init["server_public_key"].as_str().unwrap().len(), 64);
+
+    let (status, complete) = call(
+        &app,
+        "POST",
+        "/api/v1/register/complete",
+        Some(register_complete_body("device-1", &init, &client.public_key_bytes())),
+        None,
+    ).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(complete["registered"], true);
+    let api_key = complete["api_key"].as_str().unwrap().to_string();
+
+    // Registering the same id again conflicts
+    let (status, _) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Listing needs an admin session; a client's own session is not enough
+    let (status, _) = call(&app, "GET", "/api/v1/register/clients", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&app, "GET", "/api/v1/register/clients", None, Some(&api_key)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let admin = state.sessions.create_admin(3600);
+    let (status, page) = call(&app, "GET", "/api/v1/register/clients", None, Some(&admin.api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["client_id"], "device-1");
+
+    // The client can read back its own registration
+    let (status, _) = call(&app, "GET", "/api/v1/register/me", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, me) = call(&app, "GET", "/api/v1/register/me", None, Some(&api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["client_id"], "device-1");
+    assert_eq!(me["client_public_key"], client.public_key_hex());
+    assert_eq!(me["server_public_key"], init["server_public_key"]);
+    assert!(me.get("secret_key").is_none());
+
+    // Metadata updates merge into what is stored
+    let (status, _) = call(&app, "PUT", "/api/v1/register/metadata", Some(json!({ "name": "Phone", "tags": ["work"] })), Some(&api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, me) = call(&app, "PUT", "/api/v1/register/metadata", Some(json!({ "device_type": "android" })), Some(&api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["metadata"], json!({ "name": "Phone", "device_type": "android", "tags": ["work"] }));
+
+    // Sessions not tied to a client have nothing to show
+    let (_, joined) = call(&app, "POST", "/api/v1/auth/join", None, None).await;
+    let (status, _) = call(&app, "GET", "/api/v1/register/me", None, joined["api_key"].as_str()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&app, "PUT", "/api/v1/register/metadata", Some(json!({ "name": "x" })), joined["api_key"].as_str()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The registration reached the temp data dir, not ./data
+    assert!(dir.path().join("client_config.yaml").exists());
+}
+
+#[tokio::test]
+async fn register_complete_requires_matching_challenge() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+
+    let (_, init) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+
+    // Encrypted against a different challenge: refused, and the real one is spent
+    let mut stale = init.clone();
+    stale["challenge"] = json!("00".repeat(32));
+    let body = register_complete_body("device-1", &stale, &client.public_key_bytes());
+    let (status, _) = call(&app, "POST", "/api/v1/register/complete", Some(body), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let body = register_complete_body("device-1", &init, &client.public_key_bytes());
+    let (status, _) = call(&app, "POST", "/api/v1/register/complete", Some(body), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(state.keystore.get_client("device-1").is_none());
+
+    // A fresh init issues a new challenge that works
+    let (_, init) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+    let body = register_complete_body("device-1", &init, &client.public_key_bytes());
+    let (status, _) = call(&app, "POST", "/api/v1/register/complete", Some(body), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.keystore.get_client("device-1").unwrap().client_public_key, client.public_key_hex());
+}
+
+#[tokio::test]
+async fn register_complete_retry_is_idempotent() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+
+    let (_, init) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+    let body = register_complete_body("device-1", &init, &client.public_key_bytes());
+    let (status, first) = call(&app, "POST", "/api/v1/register/complete", Some(body.clone()), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The response was lost; resending the same request succeeds with the same session
+    let (status, retried) = call(&app, "POST", "/api/v1/register/complete", Some(body.clone()), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(retried["registered"], true);
+    assert_eq!(retried["api_key"], first["api_key"]);
+    assert_eq!(state.sessions.list_for_client("device-1").len(), 1);
+
+    // The same bytes replayed from elsewhere don't get the session
+    let mut replay = Request::builder()
+        .method("POST")
+        .uri("/api/v1/register/complete")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    replay.extensions_mut().insert(ConnectInfo("10.0.0.2:5000".parse::<SocketAddr>().unwrap()));
+    let res = app.clone().oneshot(replay).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    // Once the original session is gone a retry gets a fresh one instead
+    assert!(state.sessions.revoke(first["api_key"].as_str().unwrap()));
+    let (status, renewed) = call(&app, "POST", "/api/v1/register/complete", Some(body.clone()), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(renewed["api_key"], first["api_key"]);
+    assert_eq!(state.sessions.list_for_client("device-1").len(), 1);
+
+    // A different key for the same id is refused and the stored key is kept
+    let other = ServerKeyPair::generate();
+    let body = register_complete_body("device-1", &init, &other.public_key_bytes());
+    let (status, _) = call(&app, "POST", "/api/v1/register/complete", Some(body), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(state.keystore.get_client("device-1").unwrap().client_public_key, client.public_key_hex());
+}
+
+#[tokio::test]
+async fn bootstrap_registers_and_opens_session() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let body = json!({ "client_id": "device-1", "client_public_key": client.public_key_hex() });
+
+    let (status, bundle) = call(&app, "POST", "/api/v1/register/bootstrap", Some(body.clone()), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bundle["server"]["server_fingerprint"], state.server_key.current().fingerprint());
+    let stored = state.keystore.get_client("device-1").unwrap();
+    assert_eq!(stored.client_public_key, client.public_key_hex());
+
+    // The session works straight away and the per-client key agrees on a secret
+    let api_key = bundle["api_key"].as_str().unwrap();
+    let (status, me) = call(&app, "GET", "/api/v1/register/me", None, Some(api_key)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["server_public_key"], bundle["server_public_key"]);
+    let server_public = parse_public_key(bundle["server_public_key"].as_str().unwrap()).unwrap();
+    let client_secret = client.derive_shared_secret(&server_public).unwrap();
+    let server_secret = state.keystore.derive_shared_secret("device-1").unwrap();
+    assert_eq!(client_secret.as_bytes(), server_secret.as_bytes());
+
+    // A second bootstrap for the same id is refused
+    let (status, _) = call(&app, "POST", "/api/v1/register/bootstrap", Some(body), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn bootstrap_cannot_replace_pending_registration() {
+    let dir = tempdir().unwrap();
+    let state = test_state(dir.path());
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+
+    let (_, init) = call(&app, "POST", "/api/v1/register/init", Some(json!({ "client_id": "device-1" })), None).await;
+    let attacker = ServerKeyPair::generate();
+    let body = json!({ "client_id": "device-1", "client_public_key": attacker.public_key_hex() });
+    let (status, _) = call(&app, "POST", "/api/v1/register/bootstrap", Some(body), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The original challenge still completes
+    let body = register_complete_body("device-1", &init, &client.public_key_bytes());
+    let (status, _) = call(&app, "POST", "/api/v1/register/complete", Some(body), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.keystore.get_client("device-1").unwrap().client_public_key, client.public_key_hex());
+}
+
+#[tokio::test]
+async fn oversized_encrypted_payloads_rejected() {
+    let dir = tempdir().unwrap();
+    let state = state_with_limit(dir.path(), 1024);
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let shared_secret = client.derive_shared_secret(&state.server_key.current().public_key_bytes()).unwrap();
+
+    let send = |plaintext: &[u8], sequence: u64| {
+        let payload = EncryptedMessage::encrypt_with_aad(plaintext, &shared_secret, &sequence.to_be_bytes()).unwrap();
+        json!({
+            "client_public_key": client.public_key_hex(),
+            "sequence": sequence,
+            "payload": payload,
+        })
+    };
+
+    // Exactly at the limit (16-byte tag included)
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&[7u8; 1008], 1)), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Over the ciphertext limit but inside the body limit
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&[7u8; 2048], 2)), None).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Far past the body limit: refused before the handler runs
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&[7u8; 64 * 1024], 3)), None).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn compressed_payloads_inflate_within_ciphertext_limit() {
+    let dir = tempdir().unwrap();
+    let state = state_with_limit(dir.path(), 1024);
+    let app = app(&state);
+    let client = ServerKeyPair::generate();
+    let shared_secret = client.derive_shared_secret(&state.server_key.current().public_key_bytes()).unwrap();
+
+    let send = |plaintext: &[u8], sequence: u64| {
+        let payload = EncryptedMessage::builder()
+            .compressed(true)
+            .aad(&sequence.to_be_bytes())
+            .encrypt(plaintext, &shared_secret)
+            .unwrap();
+        assert!(base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &payload.ciphertext).unwrap().len() < 1024);
+        json!({
+            "client_public_key": client.public_key_hex(),
+            "sequence": sequence,
+            "payload": payload,
+        })
+    };
+
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&[7u8; 1024], 1)), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // A small envelope that would inflate past the limit
+    let (status, _) = call(&app, "POST", "/api/v1/keys/send", Some(send(&[7u8; 64 * 1024], 2)), None).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
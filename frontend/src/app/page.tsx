@@ -24,6 +24,7 @@ interface ServerKey {
 
 interface ServerInfo {
   server_public_key: string;
+  server_fingerprint: string;
   server_name: string;
   version: string;
 }
@@ -73,7 +74,10 @@ export default function Home() {
 
   const fetchServerKeys = async () => {
     try {
-      const res = await fetch('/api/v1/register/keys');
+      const apiKey = localStorage.getItem('omni_api_key');
+      const res = await fetch('/api/v1/register/keys', {
+        headers: apiKey ? { Authorization: `Bearer ${apiKey}` } : {},
+      });
       if (res.ok) {
         const data = await res.json();
         setServerKeys(data.keys.map((k: { client_id: string; public_key: string }) => ({
@@ -274,6 +278,9 @@ export default function Home() {
                     >
                       {serverInfo.server_public_key}
                     </div>
+                    <p className="font-mono text-sm text-slate-300" title="Key fingerprint">
+                      {serverInfo.server_fingerprint}
+                    </p>
                     <p className="text-xs text-slate-500">
                       {serverInfo.server_name} v{serverInfo.version}
                     </p>